@@ -49,21 +49,164 @@ use syn::{self};
 ///     test_foo("/path/to/crate/testdata/foo/baz.md")
 /// }
 /// ```
+///
+/// # Arguments
+///
+/// The glob pattern can be passed either as the first argument or as a named
+/// `pattern = "testdata/**/*.md"` argument, and can be combined with the
+/// following optional arguments:
+///
+/// - `base = "../shared"` or `base = env!("SPECS_DIR")`: resolve the glob
+///   patterns relative to the given directory instead of `CARGO_MANIFEST_DIR`.
+///   Relative `base` paths are resolved relative to `CARGO_MANIFEST_DIR`, and
+///   `env!(...)` values are read at compile time.
+/// - `group_by = "directory"`: emit a module named after the annotated function
+///   with a nested `mod` for each matched subdirectory. With this option, the
+///   tests for the above example will be `test_foo::bar` and `test_foo::baz`.
+/// - `should_panic = true`: annotate every generated test with
+///   `#[should_panic]`.
+/// - `runtime = "tokio"`: annotate every generated test with `#[tokio::test]`
+///   instead of `#[test]`. This argument is required if the annotated function
+///   is an `async fn`.
+/// - `test_attr = test_log::test`: annotate every generated test with the given
+///   attribute instead of `#[test]`. This is useful for attributes such as
+///   `#[test_log::test]` or `#[tracing_test::traced_test]` that wrap the test
+///   body. The attribute must be able to drive the annotated function, so
+///   `async fn` tests need an attribute such as `tokio::test`. Can't be
+///   combined with `runtime`.
+/// - `shared_handler = make_handler`: share a single handler between all
+///   generated tests. The annotated function must then have a second `&mut H`
+///   parameter. The handler is created by calling the `make_handler` function
+///   on first use and is stored in a `static` [`Mutex`](std::sync::Mutex)
+///   that each generated test locks for its duration.
+/// - `reset = reset_handler`: call the `reset_handler` function with the shared
+///   handler at the start of each generated test. Requires `shared_handler`.
+/// - `ignore_pattern = "testdata/slow/**"`: annotate the generated tests for
+///   all matched paths that also match this glob pattern with `#[ignore]`. Run
+///   these tests with `cargo test -- --ignored`.
+/// - `extensions = [".md", ".markdown"]`: only generate tests for matched
+///   files whose names end with one of the given extensions. This is useful
+///   for broad patterns such as `"testdata/**/*"` that would otherwise also
+///   match directories and non-Markdown files.
+/// - `doc = true`: document each generated test with the text of the first
+///   `# Heading` line in its spec file (if any), so that the spec titles show
+///   up in the `rustdoc` output of the test binary.
+/// - `sort = false`: generate the tests in the order in which the matched
+///   paths are returned by the file system. By default, the matched paths are
+///   sorted lexicographically so that the generated tests don't depend on the
+///   directory traversal order.
+///
+/// For example:
+///
+/// ```ignore
+/// #[glob_test("testdata/**/*.md", shared_handler = DbHandler::connect, reset = DbHandler::reset)]
+/// fn test_db(path: &str, handler: &mut DbHandler) {
+///     spectest::run(path, handler);
+/// }
+/// ```
+///
+/// Patterns can also match TOML spec files (for example,
+/// `extensions = [".md", ".toml"]`). Call `spectest::run_toml` in the annotated
+/// function in order to parse these with the TOML parser (requires the `toml`
+/// feature of `spectest`).
+///
+/// Other attributes of the annotated function, such as `#[allow(...)]` or doc
+/// comments, are copied to each generated test. Note that `#[cfg(...)]` and
+/// `#[cfg_attr(...)]` attributes are evaluated before the macro is expanded
+/// and therefore apply to the whole family of tests.
+///
+/// Independent of the `should_panic` argument, tests generated for spec files
+/// whose names end with `.failing.md` are always annotated with
+/// `#[should_panic]`.
+///
+/// # Environment
+///
+/// Matched paths are resolved relative to `CARGO_MANIFEST_DIR` (or the `base`
+/// directory) at compile time.
+/// Set the `SPECTEST_BASE_DIR` environment variable at runtime in order to run
+/// the generated tests against the same relative paths under a different base
+/// directory without recompiling the tests.
+///
+/// The matched spec files are tracked with `include_bytes!`, so the tests are
+/// expanded again when one of them changes. Spec files added later are only
+/// picked up once the crate is recompiled, for example after `touch`ing the
+/// file with the annotated function.
 #[proc_macro_attribute]
 pub fn glob_test(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let Ok(syn::Lit::Str(glob_pattern)) = syn::parse(attr) else {
-        let msg = "glob_test: needs a glob pattern literal string parameter";
+    let args = match syn::parse::<Args>(attr) {
+        Ok(args) => args,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    expand(args, item, false)
+}
+
+/// A version of [`glob_test`] for a single spec file.
+///
+/// The path of the spec file is passed instead of a glob pattern, and exactly
+/// one test is generated for it:
+///
+/// ```ignore
+/// #[spectest::spec_file("tests/calculator.md")]
+/// fn test(path: &str) {
+///     spectest::run(path, &mut CalculatorHandler::default());
+/// }
+/// ```
+///
+/// expands to a `test_calculator` test that calls `test` with the resolved
+/// path. Compilation fails if the spec file doesn't exist.
+///
+/// The `base`, `should_panic`, `runtime`, `test_attr`, `shared_handler`,
+/// `reset`, and `doc` arguments of [`glob_test`] are supported as well.
+#[proc_macro_attribute]
+pub fn spec_file(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = match syn::parse::<Args>(attr) {
+        Ok(args) => args,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    if args.group_by.is_some() || args.ignore_pattern.is_some() || args.extensions.is_some() {
+        let msg = "spec_file: the `group_by`, `ignore_pattern`, and `extensions` arguments are \
+                   only supported by `glob_test`";
         let err = syn::Error::new(Span::call_site(), msg);
         return err.to_compile_error().into();
+    }
+    if args.pattern.value().contains(['*', '?', '[']) {
+        let msg = "spec_file: expected a path instead of a glob pattern (use `glob_test` instead)";
+        let err = syn::Error::new(args.pattern.span(), msg);
+        return err.to_compile_error().into();
+    }
+    expand(args, item, true)
+}
+
+/// Expand the test function `item` annotated with [`glob_test`] (or with
+/// [`spec_file`] if `single` is set) into a family of tests.
+fn expand(args: Args, item: TokenStream, single: bool) -> TokenStream {
+    let glob_pattern = &args.pattern;
+    let base_dir = match &args.base {
+        Some(base) => match base.resolve() {
+            Ok(base_dir) => Some(base_dir),
+            Err(err) => return err.to_compile_error().into(),
+        },
+        None => None,
     };
+    let glob_resolved = resolve_pattern(glob_pattern, base_dir.as_deref());
+    if single && !std::path::Path::new(&glob_resolved).is_file() {
+        let msg = format!("spec_file: spec file not found: `{glob_resolved}`");
+        let err = syn::Error::new(glob_pattern.span(), msg);
+        return err.to_compile_error().into();
+    }
 
-    // This seems to be
-    let glob_resolved = match std::env::var("CARGO_MANIFEST_DIR") {
-        Ok(path) => {
-            let glob_pattern = glob_pattern.value().to_string();
-            format!("{path}/{glob_pattern}") // TODO: find a safer way to do this
+    let ignore_pattern = match &args.ignore_pattern {
+        Some(ignore_pattern) => {
+            match glob::Pattern::new(&resolve_pattern(ignore_pattern, base_dir.as_deref())) {
+                Ok(pattern) => Some(pattern),
+                Err(_) => {
+                    let msg = "glob_test: `ignore_pattern` is not a valid glob pattern";
+                    let err = syn::Error::new(ignore_pattern.span(), msg);
+                    return err.to_compile_error().into();
+                }
+            }
         }
-        Err(_) => glob_pattern.value().to_string(),
+        None => None,
     };
 
     let Ok(syn::ItemFn {
@@ -78,14 +221,24 @@ pub fn glob_test(attr: TokenStream, item: TokenStream) -> TokenStream {
         return err.to_compile_error().into();
     };
 
-    if let Err(err) = check_signature(&sig) {
+    if let Err(err) = check_signature(&sig, &args) {
         return err;
     };
 
-    let test_attr: syn::Attribute = if sig.asyncness.is_some() {
-        syn::parse_quote!(#[tokio::test])
-    } else {
-        syn::parse_quote!(#[test])
+    // The name and the definition of a function that locks the shared handler.
+    let shared_handler = args.shared_handler.as_ref().map(|make_handler| {
+        let ident = format!("{}_shared_handler", sig.ident);
+        let ident = syn::Ident::new(&ident, sig.ident.span());
+        let item = make_shared_handler(&sig, &ident, make_handler, args.reset.as_ref());
+        (ident, item)
+    });
+    let shared_handler_item = shared_handler.as_ref().map(|(_, item)| item);
+    let shared_handler_ident = shared_handler.as_ref().map(|(ident, _)| ident);
+
+    let test_attr: syn::Attribute = match (&args.test_attr, &args.runtime) {
+        (Some(test_attr), _) => syn::parse_quote!(#[#test_attr]),
+        (None, Some(Runtime::Tokio)) => syn::parse_quote!(#[tokio::test]),
+        (None, None) => syn::parse_quote!(#[test]),
     };
 
     let Ok(paths) = glob::glob(&glob_resolved) else {
@@ -94,11 +247,14 @@ pub fn glob_test(attr: TokenStream, item: TokenStream) -> TokenStream {
         return err.to_compile_error().into();
     };
 
-    let const_prefix_len = glob_resolved.find('*').unwrap_or(0);
-    let test_attrs = std::iter::repeat(attrs.clone());
-    let fn_name = &sig.ident;
-    let mut test_sig = Vec::new();
-    let mut test_block = Vec::new();
+    // Name the generated tests after the matched paths without this prefix.
+    let const_prefix_len = match single {
+        true => glob_resolved
+            .rfind(std::path::is_separator)
+            .map_or(0, |i| i + 1),
+        false => glob_resolved.find('*').unwrap_or(0),
+    };
+    let mut matched_paths = Vec::new();
     for entry in paths {
         match entry {
             Ok(path) => {
@@ -107,43 +263,9 @@ pub fn glob_test(attr: TokenStream, item: TokenStream) -> TokenStream {
                     let err = syn::Error::new(glob_pattern.span(), msg);
                     return err.to_compile_error().into();
                 }
-
-                test_sig.push({
-                    let test_signature = syn::Signature {
-                        ident: {
-                            let prefix = sig.ident.to_string();
-                            let suffix = path
-                                .with_extension("")
-                                .to_string_lossy() // lossless conversion asserted above
-                                .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
-                                .split_off(const_prefix_len);
-                            let test_fn_name = format!("{}_{}", &prefix, &suffix);
-                            syn::Ident::new(&test_fn_name, sig.ident.span())
-                        },
-                        inputs: syn::punctuated::Punctuated::new(),
-                        ..sig.clone()
-                    };
-                    Box::new(test_signature)
-                });
-
-                let path = path.to_str();
-
-                test_block.push({
-                    let value = syn::parse2::<syn::Block>(if sig.asyncness.is_some() {
-                        quote::quote! {
-                            {
-                                #fn_name(#path).await
-                            }
-                        }
-                    } else {
-                        quote::quote! {
-                            {
-                                #fn_name(#path)
-                            }
-                        }
-                    });
-                    Box::new(value.expect("test body"))
-                });
+                if args.allows(&path) {
+                    matched_paths.push(path);
+                }
             }
             Err(err) => {
                 let err = syn::Error::new(glob_pattern.span(), err);
@@ -152,12 +274,117 @@ pub fn glob_test(attr: TokenStream, item: TokenStream) -> TokenStream {
         };
     }
 
-    if test_sig.is_empty() {
-        let msg = format!("glob_test: resolved pattern `{glob_resolved}` didn't match any paths");
+    if args.sort {
+        matched_paths.sort_by(|a, b| a.as_os_str().cmp(b.as_os_str()));
+    }
+
+    if matched_paths.is_empty() {
+        let mut msg =
+            format!("glob_test: resolved pattern `{glob_resolved}` didn't match any paths");
+        if let Some(extensions) = &args.extensions {
+            let extensions = extensions.iter().map(|ext| format!("`.{ext}`"));
+            let extensions = extensions.collect::<Vec<_>>().join(", ");
+            msg.push_str(&format!(" with extensions {extensions}"));
+        }
+        let base_dir = pattern_base_dir(&glob_resolved);
+        if !base_dir.exists() {
+            let base_dir = base_dir.display();
+            msg.push_str(&format!("; base directory `{base_dir}` does not exist"));
+        }
         let err = syn::Error::new(glob_pattern.span(), msg);
         return err.to_compile_error().into();
     }
 
+    let tests = match args.group_by {
+        None => {
+            // All attributes of the annotated function except `glob_test`
+            // itself, which is consumed by this expansion.
+            let test_attrs = std::iter::repeat(attrs.clone());
+            let fn_name = &sig.ident;
+            let mut test_sig = Vec::new();
+            let mut test_block = Vec::new();
+            let mut should_panic = Vec::new();
+            let mut ignore = Vec::new();
+            let mut doc = Vec::new();
+            for path in matched_paths.iter() {
+                test_sig.push({
+                    let prefix = sig.ident.to_string();
+                    let suffix = path
+                        .with_extension("")
+                        .to_string_lossy() // lossless conversion asserted above
+                        .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+                        .split_off(const_prefix_len);
+                    let test_fn_name = format!("{}_{}", &prefix, &suffix);
+                    make_test_signature(&sig, syn::Ident::new(&test_fn_name, sig.ident.span()))
+                });
+                let handler_fn = shared_handler_ident.map(|ident| quote!(#ident));
+                test_block.push(make_test_block(&sig, quote!(#fn_name), handler_fn, path));
+                should_panic.push(make_should_panic(args.should_panic, path));
+                ignore.push(make_ignore(ignore_pattern.as_ref(), path));
+                doc.push(make_doc(args.doc, path));
+            }
+
+            quote! {
+                #( #doc #(#test_attrs)* #test_attr #should_panic #ignore #vis #test_sig #test_block )*
+            }
+        }
+        Some(GroupBy::Directory) => {
+            // Group the matched paths by their parent directory relative to
+            // the directory that contains the constant glob pattern prefix.
+            let base_dir = {
+                let prefix = &glob_resolved[..const_prefix_len];
+                if prefix.ends_with(std::path::is_separator) {
+                    std::path::PathBuf::from(prefix)
+                } else {
+                    let prefix = std::path::Path::new(prefix);
+                    prefix.parent().unwrap_or(prefix).to_path_buf()
+                }
+            };
+
+            let mut root = TestModule::default();
+            for path in matched_paths.iter() {
+                let relative = path.strip_prefix(&base_dir).unwrap_or(path);
+                let mut module = &mut root;
+                if let Some(parent) = relative.parent() {
+                    for dir in parent.iter() {
+                        let dir = to_ident(&dir.to_string_lossy());
+                        module = module.modules.entry(dir).or_default();
+                    }
+                }
+                module.tests.push(path.clone());
+            }
+
+            let fn_name = &sig.ident;
+            let test_module = root.expand(&TestModuleCtx {
+                attrs: &attrs,
+                test_attr: &test_attr,
+                sig: &sig,
+                should_panic: args.should_panic,
+                ignore_pattern: ignore_pattern.as_ref(),
+                doc: args.doc,
+                shared_handler: shared_handler_ident,
+                depth: 1,
+            });
+
+            quote! {
+                mod #fn_name {
+                    #test_module
+                }
+            }
+        }
+    };
+
+    // Include the matched spec files in the compilation, so that the tests are
+    // re-expanded when one of them changes. Spec files that are added later
+    // are only picked up when the crate is recompiled for another reason.
+    let tracked = matched_paths
+        .iter()
+        .filter(|path| path.is_absolute())
+        .map(|path| path.to_string_lossy()); // lossless conversion asserted above
+    let tracked = quote! {
+        #( const _: &[u8] = include_bytes!(#tracked); )*
+    };
+
     // let mut key = vec![];
     // let mut val = vec![];
     // for (k, v) in std::env::vars() {
@@ -170,6 +397,10 @@ pub fn glob_test(attr: TokenStream, item: TokenStream) -> TokenStream {
     let expanded = quote! {
         #(#attrs)* #vis #sig #block
 
+        #shared_handler_item
+
+        #tracked
+
         // #[test] fn test_current_env() {
         //     #(
         //         print!("{}", #key);
@@ -179,26 +410,600 @@ pub fn glob_test(attr: TokenStream, item: TokenStream) -> TokenStream {
         //     )*
         // }
 
-        #( #(#test_attrs)* #test_attr #vis #test_sig #test_block )*
+        #tests
     };
 
     // Convert into a token stream and return it
     expanded.into()
 }
 
-fn check_signature(sig: &syn::Signature) -> Result<&Ident, TokenStream> {
-    if sig.inputs.len() != 1 {
-        let span = if sig.inputs.is_empty() {
-            sig.ident.span()
-        } else {
-            sig.inputs.iter().nth(1).unwrap().span()
+// Macro arguments
+// ===============
+
+/// Arguments accepted by the [`glob_test`] macro.
+struct Args {
+    /// The glob pattern used to find spec files.
+    pattern: syn::LitStr,
+    /// An optional strategy for grouping the generated tests into modules.
+    group_by: Option<GroupBy>,
+    /// Whether to annotate all generated tests with `#[should_panic]`.
+    should_panic: bool,
+    /// An optional async runtime used to drive `async fn` tests.
+    runtime: Option<Runtime>,
+    /// An optional function that creates a handler shared by all tests.
+    shared_handler: Option<syn::ExprPath>,
+    /// An optional function that resets the shared handler before each test.
+    reset: Option<syn::ExprPath>,
+    /// An optional glob pattern for paths whose tests are marked `#[ignore]`.
+    ignore_pattern: Option<syn::LitStr>,
+    /// An optional base directory for resolving the glob patterns.
+    base: Option<Base>,
+    /// Whether to document the generated tests with their spec file titles.
+    doc: bool,
+    /// An optional list of file extensions (without a leading `.`) that the
+    /// matched paths must have.
+    extensions: Option<Vec<String>>,
+    /// Whether to sort the matched paths lexicographically.
+    sort: bool,
+    /// An optional attribute used instead of `#[test]`.
+    test_attr: Option<syn::Path>,
+}
+
+impl Args {
+    /// Check whether the given `path` has one of the allowed `extensions`.
+    fn allows(&self, path: &std::path::Path) -> bool {
+        let Some(extensions) = &self.extensions else {
+            return true;
+        };
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            return false;
+        };
+        path.is_file()
+            && extensions.iter().any(|extension| {
+                let stem = name.strip_suffix(extension.as_str());
+                stem.is_some_and(|stem| stem.len() > 1 && stem.ends_with('.'))
+            })
+    }
+}
+
+/// Supported values for the `base` argument.
+enum Base {
+    /// A string literal path.
+    Path(syn::LitStr),
+    /// An `env!("NAME")` macro call that is evaluated at compile time.
+    Env(syn::LitStr),
+}
+
+impl Base {
+    fn parse(expr: &syn::Expr) -> syn::Result<Self> {
+        if let syn::Expr::Macro(syn::ExprMacro { mac, .. }) = expr {
+            if mac.path.is_ident("env") {
+                return Ok(Base::Env(mac.parse_body()?));
+            }
+        }
+        match lit_str(expr) {
+            Ok(path) => Ok(Base::Path(path.clone())),
+            Err(_) => {
+                let msg = "glob_test: expected a string literal or an `env!(\"NAME\")` call";
+                Err(syn::Error::new(expr.span(), msg))
+            }
+        }
+    }
+
+    /// Resolve the base directory at compile time.
+    fn resolve(&self) -> syn::Result<String> {
+        match self {
+            Base::Path(path) => Ok(path.value()),
+            Base::Env(name) => std::env::var(name.value()).map_err(|_| {
+                let msg = format!(
+                    "glob_test: environment variable `{}` is not set",
+                    name.value()
+                );
+                syn::Error::new(name.span(), msg)
+            }),
+        }
+    }
+}
+
+/// Supported values for the `group_by` argument.
+enum GroupBy {
+    /// Emit a `mod` for each directory.
+    Directory,
+}
+
+/// Supported values for the `runtime` argument.
+enum Runtime {
+    /// Annotate the generated tests with `#[tokio::test]`.
+    Tokio,
+}
+
+impl syn::parse::Parse for Args {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        // The glob pattern can be passed as a leading positional argument.
+        let mut pattern = match input.peek(syn::LitStr) {
+            true => Some(input.parse::<syn::LitStr>()?),
+            false => None,
+        };
+        let mut group_by = None;
+        let mut should_panic = false;
+        let mut runtime = None;
+        let mut shared_handler = None;
+        let mut reset = None;
+        let mut ignore_pattern = None;
+        let mut base = None;
+        let mut doc = false;
+        let mut extensions = None;
+        let mut sort = true;
+        let mut test_attr = None;
+
+        let mut first = pattern.is_none();
+        while !input.is_empty() {
+            if !std::mem::take(&mut first) {
+                input.parse::<syn::Token![,]>()?;
+                if input.is_empty() {
+                    break; // Allow trailing commas.
+                }
+            }
+
+            let syn::MetaNameValue { path, value, .. } = input.parse()?;
+            if path.is_ident("group_by") {
+                match lit_str(&value)?.value().as_str() {
+                    "directory" => group_by = Some(GroupBy::Directory),
+                    _ => {
+                        let msg = "glob_test: supported `group_by` values are: \"directory\"";
+                        return Err(syn::Error::new(value.span(), msg));
+                    }
+                }
+            } else if path.is_ident("should_panic") {
+                should_panic = lit_bool(&value)?.value();
+            } else if path.is_ident("runtime") {
+                match lit_str(&value)?.value().as_str() {
+                    "tokio" => runtime = Some(Runtime::Tokio),
+                    _ => {
+                        let msg = "glob_test: supported `runtime` values are: \"tokio\"";
+                        return Err(syn::Error::new(value.span(), msg));
+                    }
+                }
+            } else if path.is_ident("shared_handler") {
+                shared_handler = Some(expr_path(&value)?.clone());
+            } else if path.is_ident("reset") {
+                reset = Some(expr_path(&value)?.clone());
+            } else if path.is_ident("ignore_pattern") {
+                ignore_pattern = Some(lit_str(&value)?.clone());
+            } else if path.is_ident("pattern") && pattern.is_none() {
+                pattern = Some(lit_str(&value)?.clone());
+            } else if path.is_ident("base") {
+                base = Some(Base::parse(&value)?);
+            } else if path.is_ident("doc") {
+                doc = lit_bool(&value)?.value();
+            } else if path.is_ident("extensions") {
+                extensions = Some(lit_extensions(&value)?);
+            } else if path.is_ident("sort") {
+                sort = lit_bool(&value)?.value();
+            } else if path.is_ident("test_attr") {
+                test_attr = Some(expr_path(&value)?.path.clone());
+            } else {
+                let msg = "glob_test: unsupported argument";
+                return Err(syn::Error::new(path.span(), msg));
+            }
+        }
+
+        let Some(pattern) = pattern else {
+            let msg = "glob_test: needs a glob pattern literal string parameter";
+            return Err(syn::Error::new(Span::call_site(), msg));
+        };
+
+        if let (Some(reset), None) = (&reset, &shared_handler) {
+            let msg = "glob_test: the `reset` argument requires a `shared_handler` argument";
+            return Err(syn::Error::new(reset.span(), msg));
+        }
+
+        if let (Some(test_attr), Some(_)) = (&test_attr, &runtime) {
+            let msg = "glob_test: the `test_attr` and `runtime` arguments can't be combined";
+            return Err(syn::Error::new(test_attr.span(), msg));
+        }
+
+        Ok(Self {
+            pattern,
+            group_by,
+            should_panic,
+            runtime,
+            shared_handler,
+            reset,
+            ignore_pattern,
+            base,
+            doc,
+            extensions,
+            sort,
+            test_attr,
+        })
+    }
+}
+
+fn lit_str(expr: &syn::Expr) -> syn::Result<&syn::LitStr> {
+    match expr {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(lit),
+            ..
+        }) => Ok(lit),
+        _ => {
+            let msg = "glob_test: expected a string literal";
+            Err(syn::Error::new(expr.span(), msg))
+        }
+    }
+}
+
+fn lit_extensions(expr: &syn::Expr) -> syn::Result<Vec<String>> {
+    let msg = "glob_test: expected a non-empty array of extensions such as `[\".md\"]`";
+    let syn::Expr::Array(array) = expr else {
+        return Err(syn::Error::new(expr.span(), msg));
+    };
+    if array.elems.is_empty() {
+        return Err(syn::Error::new(expr.span(), msg));
+    }
+    array
+        .elems
+        .iter()
+        .map(|elem| {
+            let extension = lit_str(elem)?.value();
+            match extension.strip_prefix('.').unwrap_or(&extension) {
+                "" => Err(syn::Error::new(elem.span(), msg)),
+                extension => Ok(extension.to_string()),
+            }
+        })
+        .collect()
+}
+
+fn expr_path(expr: &syn::Expr) -> syn::Result<&syn::ExprPath> {
+    match expr {
+        syn::Expr::Path(path) => Ok(path),
+        _ => {
+            let msg = "glob_test: expected a function path";
+            Err(syn::Error::new(expr.span(), msg))
+        }
+    }
+}
+
+fn lit_bool(expr: &syn::Expr) -> syn::Result<&syn::LitBool> {
+    match expr {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Bool(lit),
+            ..
+        }) => Ok(lit),
+        _ => {
+            let msg = "glob_test: expected a boolean literal";
+            Err(syn::Error::new(expr.span(), msg))
+        }
+    }
+}
+
+// Code generation helpers
+// =======================
+
+/// A tree of generated test modules used for `group_by = "directory"`.
+#[derive(Default)]
+struct TestModule {
+    modules: std::collections::BTreeMap<String, TestModule>,
+    tests: Vec<std::path::PathBuf>,
+}
+
+struct TestModuleCtx<'a> {
+    attrs: &'a [syn::Attribute],
+    test_attr: &'a syn::Attribute,
+    sig: &'a syn::Signature,
+    should_panic: bool,
+    ignore_pattern: Option<&'a glob::Pattern>,
+    doc: bool,
+    shared_handler: Option<&'a Ident>,
+    depth: usize,
+}
+
+impl TestModule {
+    fn expand(&self, ctx: &TestModuleCtx) -> proc_macro2::TokenStream {
+        let TestModuleCtx {
+            attrs,
+            test_attr,
+            sig,
+            should_panic,
+            ignore_pattern,
+            doc,
+            shared_handler,
+            depth,
+        } = ctx;
+
+        // Reference the annotated function from the current module depth.
+        let fn_name = &sig.ident;
+        let supers = std::iter::repeat_n(quote!(super::), *depth).collect::<Vec<_>>();
+        let fn_path = quote!(#(#supers)* #fn_name);
+        let handler_fn = shared_handler.map(|ident| quote!(#(#supers)* #ident));
+
+        // Files in the same directory might map to the same identifier, so
+        // disambiguate these with a numeric suffix.
+        let mut used = std::collections::BTreeSet::new();
+        let mut tests = Vec::new();
+        for path in self.tests.iter() {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let mut name = to_ident(&stem);
+            let mut i = 1;
+            while self.modules.contains_key(&name) || !used.insert(name.clone()) {
+                i += 1;
+                name = format!("{}_{i}", to_ident(&stem));
+            }
+            let test_sig = make_test_signature(sig, syn::Ident::new(&name, sig.ident.span()));
+            let test_block = make_test_block(sig, fn_path.clone(), handler_fn.clone(), path);
+            let should_panic = make_should_panic(*should_panic, path);
+            let ignore = make_ignore(*ignore_pattern, path);
+            let doc = make_doc(*doc, path);
+            tests.push(quote! {
+                #doc #(#attrs)* #test_attr #should_panic #ignore #test_sig #test_block
+            });
+        }
+
+        let mut modules = Vec::new();
+        for (name, module) in self.modules.iter() {
+            let name = syn::Ident::new(name, sig.ident.span());
+            let module = module.expand(&TestModuleCtx {
+                depth: depth + 1,
+                ..*ctx
+            });
+            modules.push(quote! {
+                mod #name {
+                    #module
+                }
+            });
+        }
+
+        quote! {
+            #(#tests)*
+            #(#modules)*
+        }
+    }
+}
+
+/// Resolve a glob `pattern` argument relative to the optional `base_dir`,
+/// which is in turn resolved relative to `CARGO_MANIFEST_DIR`.
+fn resolve_pattern(pattern: &syn::LitStr, base_dir: Option<&str>) -> String {
+    let mut path = std::path::PathBuf::new();
+    if let Ok(manifest_dir) = std::env::var("CARGO_MANIFEST_DIR") {
+        path.push(manifest_dir);
+    }
+    if let Some(base_dir) = base_dir {
+        path.push(base_dir); // Replaces the `path` if `base_dir` is absolute.
+    }
+    path.push(pattern.value());
+    path.to_string_lossy().into_owned()
+}
+
+/// The directory that contains the constant prefix of a `glob_resolved`
+/// pattern (that is, the part before the first glob metacharacter).
+fn pattern_base_dir(glob_resolved: &str) -> std::path::PathBuf {
+    let prefix_len = glob_resolved
+        .find(['*', '?', '['])
+        .unwrap_or(glob_resolved.len());
+    let prefix = &glob_resolved[..prefix_len];
+    match prefix.rfind(std::path::is_separator) {
+        Some(end) => std::path::PathBuf::from(&prefix[..=end]),
+        None => std::path::PathBuf::from("."),
+    }
+}
+
+/// Convert a path segment into a valid Rust identifier.
+fn to_ident(segment: &str) -> String {
+    let ident = segment.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+    if syn::parse_str::<Ident>(&ident).is_ok() {
+        ident
+    } else {
+        format!("_{ident}") // Handle leading digits and keywords.
+    }
+}
+
+/// Derive the `#[should_panic]` attribute of a generated test for `path`.
+///
+/// Tests for `*.failing.md` spec files are always expected to panic.
+fn make_should_panic(should_panic: bool, path: &std::path::Path) -> Option<syn::Attribute> {
+    let failing = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(".failing.md"));
+    (should_panic || failing).then(|| syn::parse_quote!(#[should_panic]))
+}
+
+/// Derive the `#[ignore]` attribute of a generated test for `path`.
+fn make_ignore(
+    ignore_pattern: Option<&glob::Pattern>,
+    path: &std::path::Path,
+) -> Option<syn::Attribute> {
+    let ignored = ignore_pattern.is_some_and(|pattern| pattern.matches_path(path));
+    ignored.then(|| syn::parse_quote!(#[ignore]))
+}
+
+/// Derive the `#[doc]` attribute of a generated test for `path`.
+fn make_doc(doc: bool, path: &std::path::Path) -> Option<syn::Attribute> {
+    let title = spec_title(path).filter(|_| doc)?;
+    Some(syn::parse_quote!(#[doc = #title]))
+}
+
+/// The text of the first level 1 ATX heading (`# Title`) in the spec file at
+/// `path`, skipping headings in fenced code blocks.
+fn spec_title(path: &std::path::Path) -> Option<String> {
+    let source = std::fs::read_to_string(path).ok()?;
+    let mut fence = None;
+    for line in source.lines() {
+        let line = line.trim_start();
+        let marker = ["```", "~~~"].into_iter().find(|m| line.starts_with(m));
+        match (fence, marker) {
+            (None, Some(marker)) => fence = Some(marker),
+            (Some(open), Some(marker)) if open == marker => fence = None,
+            (Some(_), _) => (),
+            (None, None) => {
+                let Some(title) = line.strip_prefix("# ") else {
+                    continue;
+                };
+                let title = title.trim().trim_end_matches('#').trim_end();
+                return (!title.is_empty()).then(|| title.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Derive the signature of a generated test from the annotated function.
+fn make_test_signature(sig: &syn::Signature, ident: Ident) -> Box<syn::Signature> {
+    let test_signature = syn::Signature {
+        ident,
+        inputs: syn::punctuated::Punctuated::new(),
+        ..sig.clone()
+    };
+    Box::new(test_signature)
+}
+
+/// Derive the body of a generated test that calls `fn_path` with `path`.
+///
+/// If `path` is located under `CARGO_MANIFEST_DIR`, the generated test will
+/// replace that prefix with the value of the `SPECTEST_BASE_DIR` environment
+/// variable at runtime (if set).
+///
+/// If `handler_fn` is set, the generated test also passes the shared handler
+/// returned by that function to `fn_path`.
+fn make_test_block(
+    sig: &syn::Signature,
+    fn_path: proc_macro2::TokenStream,
+    handler_fn: Option<proc_macro2::TokenStream>,
+    path: &std::path::Path,
+) -> Box<syn::Block> {
+    let suffix = std::env::var("CARGO_MANIFEST_DIR")
+        .ok()
+        .and_then(|base_dir| path.strip_prefix(base_dir).ok())
+        .and_then(|suffix| suffix.to_str());
+    let path = path.to_str();
+
+    let path = match suffix {
+        Some(suffix) => quote::quote! {
+            &match ::std::env::var("SPECTEST_BASE_DIR") {
+                Ok(base_dir) => ::std::path::Path::new(&base_dir)
+                    .join(#suffix)
+                    .to_string_lossy()
+                    .into_owned(),
+                Err(_) => ::std::string::String::from(#path),
+            }
+        },
+        None => quote::quote!(#path),
+    };
+
+    let (handler, handler_arg) = match handler_fn {
+        Some(handler_fn) => (
+            Some(quote::quote!(let mut handler = #handler_fn();)),
+            Some(quote::quote!(, &mut handler)),
+        ),
+        None => (None, None),
+    };
+
+    let value = syn::parse2::<syn::Block>(if sig.asyncness.is_some() {
+        quote::quote! {
+            {
+                #handler
+                #fn_path(#path #handler_arg).await
+            }
+        }
+    } else {
+        quote::quote! {
+            {
+                #handler
+                #fn_path(#path #handler_arg)
+            }
+        }
+    });
+    Box::new(value.expect("test body"))
+}
+
+/// Derive a function named `ident` that locks the handler shared by all tests
+/// and resets it with the optional `reset` function.
+///
+/// The handler is created with `make_handler` on first use. A poisoned lock
+/// (caused by a failed test) is recovered, so tests that use a `reset`
+/// function don't observe the state left behind by a prior failure.
+fn make_shared_handler(
+    sig: &syn::Signature,
+    ident: &Ident,
+    make_handler: &syn::ExprPath,
+    reset: Option<&syn::ExprPath>,
+) -> proc_macro2::TokenStream {
+    let ty = shared_handler_type(sig).expect("checked signature");
+    let reset = reset.map(|reset| quote!(#reset(&mut *handler);));
+    quote! {
+        fn #ident() -> ::std::sync::MutexGuard<'static, #ty> {
+            static HANDLER: ::std::sync::OnceLock<::std::sync::Mutex<#ty>> =
+                ::std::sync::OnceLock::new();
+            let handler = HANDLER.get_or_init(|| ::std::sync::Mutex::new(#make_handler()));
+            let mut handler = handler
+                .lock()
+                .unwrap_or_else(::std::sync::PoisonError::into_inner);
+            #reset
+            handler
+        }
+    }
+}
+
+/// The `H` type of the second `&mut H` parameter of the annotated function.
+fn shared_handler_type(sig: &syn::Signature) -> Option<&syn::Type> {
+    match sig.inputs.iter().nth(1)? {
+        syn::FnArg::Typed(syn::PatType { ty, .. }) => match ty.as_ref() {
+            syn::Type::Reference(syn::TypeReference {
+                mutability: Some(_),
+                elem,
+                ..
+            }) => Some(elem),
+            _ => None,
+        },
+        syn::FnArg::Receiver(_) => None,
+    }
+}
+
+fn check_signature<'a>(sig: &'a syn::Signature, args: &Args) -> Result<&'a Ident, TokenStream> {
+    match (&sig.asyncness, &args.runtime) {
+        (Some(_), None) if args.test_attr.is_some() => (),
+        (Some(asyncness), None) => {
+            let msg = "glob_test: `#[test]` cannot drive an `async fn`; use \
+                       `runtime = \"tokio\"` in order to generate `#[tokio::test]` tests";
+            let err = syn::Error::new(asyncness.span(), msg);
+            return Err(err.to_compile_error().into());
+        }
+        (None, Some(_)) => {
+            let msg = "glob_test: the `runtime` argument requires an `async fn`";
+            let err = syn::Error::new(sig.fn_token.span(), msg);
+            return Err(err.to_compile_error().into());
+        }
+        _ => (),
+    }
+
+    let (params, msg) = match args.shared_handler {
+        None => (
+            1,
+            "glob_test: annotated function must have exactly one parameter",
+        ),
+        Some(_) => (
+            2,
+            "glob_test: annotated function must have exactly two parameters \
+             when used with `shared_handler`",
+        ),
+    };
+    if sig.inputs.len() != params {
+        let span = match sig.inputs.iter().nth(params) {
+            Some(fn_arg) => fn_arg.span(),
+            None => sig.ident.span(),
         };
-        let msg = "glob_test: annotated function must have exactly one parameter";
         let err = syn::Error::new(span, msg);
         return Err(err.to_compile_error().into());
     }
 
-    let fn_arg = sig.inputs.last().expect("fn arg");
+    if args.shared_handler.is_some() && shared_handler_type(sig).is_none() {
+        let fn_arg = sig.inputs.last().expect("fn arg");
+        let msg = "glob_test: second function parameter type must be `&mut H`";
+        let err = syn::Error::new(fn_arg.span(), msg);
+        return Err(err.to_compile_error().into());
+    }
+
+    let fn_arg = sig.inputs.first().expect("fn arg");
 
     match &fn_arg {
         syn::FnArg::Typed(syn::PatType {
@@ -227,6 +1032,20 @@ fn check_signature(sig: &syn::Signature) -> Result<&Ident, TokenStream> {
                     ident,
                     subpat: None,
                 }) if attrs.is_empty() => Ok(ident),
+                syn::Pat::Ident(syn::PatIdent {
+                    mutability: Some(mutability),
+                    ident,
+                    ..
+                }) => {
+                    let msg = format!(
+                        "glob_test: function parameter must not be `mut`, since the generated \
+                         tests pass a `&str` path that can't be modified in place; remove `mut` \
+                         and use `let mut {ident} = {ident};` in the function body in order to \
+                         rebind it"
+                    );
+                    let err = syn::Error::new(mutability.span(), msg);
+                    Err(err.to_compile_error().into())
+                }
                 _ => {
                     let msg = "glob_test: function parameter must bind a variable";
                     let err = syn::Error::new(fn_arg.span(), msg);
@@ -0,0 +1,138 @@
+//! A command line tool for checking spec files without writing a test.
+//!
+//! Requires the `cli` feature.
+//!
+//! ```bash
+//! # Report malformed spec files.
+//! spectest check 'testdata/**/*.md'
+//! # Print the names of all examples.
+//! spectest list 'testdata/**/*.md'
+//! ```
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use spectest::core::parse_spec_file;
+use spectest::owned::{OwnedExample, OwnedSection};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Check and inspect spectest spec files")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Parse the spec files matched by the given glob patterns and report
+    /// malformed sections.
+    Check {
+        /// Glob patterns for the spec files.
+        #[arg(required = true)]
+        patterns: Vec<String>,
+    },
+    /// Print the names of the examples in the spec files matched by the given
+    /// glob patterns.
+    List {
+        /// Glob patterns for the spec files.
+        #[arg(required = true)]
+        patterns: Vec<String>,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        Command::Check { patterns } => expand(patterns).map(|paths| check(&paths)),
+        Command::List { patterns } => expand(patterns).map(|paths| list(&paths)),
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(msg) => {
+            eprintln!("error: {msg}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Parse each spec file at the given `paths` and print the outcome.
+///
+/// Returns `true` if all spec files are well-formed.
+fn check(paths: &[PathBuf]) -> bool {
+    let mut ok = true;
+    for path in paths {
+        match parse_spec_file(path) {
+            Ok(sections) => {
+                let count = sections.iter().map(|s| examples(s).len()).sum::<usize>();
+                println!("ok `{}` ({count} examples)", path.display());
+            }
+            Err(err) => {
+                println!("error `{}`", path.display());
+                report(err);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+/// Print the name of each example in the spec files at the given `paths`.
+///
+/// Returns `true` if all spec files are well-formed.
+fn list(paths: &[PathBuf]) -> bool {
+    let mut ok = true;
+    for path in paths {
+        match parse_spec_file(path) {
+            Ok(sections) => {
+                for example in sections.iter().flat_map(examples) {
+                    println!("{}: {}", path.display(), example.name);
+                }
+            }
+            Err(err) => {
+                report(err);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+/// Print an error returned by [`parse_spec_file`].
+fn report(err: spectest::Error<()>) {
+    // Parsing doesn't run a handler, so there are no handler errors.
+    let err = err.map_handler::<String, _>(|()| unreachable!("handler error"));
+    eprintln!("{err}");
+}
+
+/// The examples defined in a `section`, with one example per table row for
+/// tabular sections.
+fn examples(section: &OwnedSection) -> &[OwnedExample] {
+    match section {
+        OwnedSection::Example(example) => std::slice::from_ref(example),
+        OwnedSection::Tabular(rows) => rows,
+        OwnedSection::Background(_) | OwnedSection::Raw { .. } => &[],
+    }
+}
+
+/// The sorted paths of all files matched by the given glob `patterns`.
+fn expand(patterns: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        let entries = glob::glob(pattern).map_err(|err| format!("`{pattern}`: {err}"))?;
+        let count = paths.len();
+        for entry in entries {
+            let path = entry.map_err(|err| err.to_string())?;
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        if paths.len() == count {
+            return Err(format!("`{pattern}` didn't match any files"));
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
@@ -4,18 +4,44 @@
 //!
 //! [gherkin]: https://cucumber.io/docs/gherkin/reference/
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use pulldown_cmark::{CowStr, HeadingLevel};
+use pulldown_cmark::CowStr;
 use thiserror::Error;
 
-pub(crate) use crate::core::reader::read_to_string;
-use crate::core::reader::{sections, Pos};
-use crate::md;
-
+use crate::core::exec::{block_on, execute, execute_str, AsyncExecutor, Mode, SyncExecutor};
+use crate::owned::{OwnedBackground, OwnedExample, OwnedSection};
+use crate::{diff, md};
+
+pub use crate::core::chain::{chain, ChainedHandler};
+#[cfg(feature = "regex")]
+pub use crate::core::compare::RegexComparator;
+pub use crate::core::compare::{
+    Comparator, ExactComparator, NormalizedWhitespaceComparator, PerKeyComparator,
+    TrailingNewlineComparator, TrimmedComparator,
+};
+#[cfg(feature = "miette")]
+pub use crate::core::diagnostic::MietteMdSource;
+pub use crate::core::dyn_handler::{process_dyn, rewrite_dyn, DynHandler};
+pub(crate) use crate::core::reader::outline;
+pub use crate::core::reader::{
+    read_to_string, sections, Error as ReaderError, Pos, SectionKind, SectionsIter,
+};
+pub use crate::core::tracked_when::TrackedWhen;
+/// The heading level of a spec section, re-exported from `pulldown_cmark`.
+pub use pulldown_cmark::HeadingLevel;
+
+mod chain;
+mod compare;
+#[cfg(feature = "miette")]
+mod diagnostic;
+mod dyn_handler;
+mod exec;
 mod reader;
+mod tracked_when;
 
 // Data model
 // ==========
@@ -29,6 +55,7 @@ mod reader;
 pub enum Section<'a, 'input> {
     Background(Background<'a>),
     Example(Example<'a, &'a mut CowStr<'input>>),
+    Tabular(Tabular<'a, &'a mut CowStr<'input>>),
     Raw(Raw),
 }
 
@@ -36,6 +63,14 @@ pub enum Section<'a, 'input> {
 ///
 /// Modelled after [Gherkin's `Background` section][gherkin].
 ///
+/// A background stays active until the next heading at the same or a higher
+/// level that is not a `Background` or an `Example`. Backgrounds at level H1
+/// have a file-wide scope and stay active until the end of the file.
+///
+/// A background that follows an active background at the same level replaces
+/// it. The replaced background is left first, and its `given` values are
+/// merged into the `given` values of the new background.
+///
 /// [gherkin]: <https://cucumber.io/docs/gherkin/reference/#background>
 #[derive(Debug)]
 pub struct Background<'a> {
@@ -43,6 +78,25 @@ pub struct Background<'a> {
     pub given: HashMap<&'a str, &'a str>,
 }
 
+impl<'a> Background<'a> {
+    /// The heading level of the section as a number between 1 and 6.
+    pub fn level_u8(&self) -> u8 {
+        self.level as u8
+    }
+
+    /// Copy the background into an [`OwnedBackground`] that doesn't borrow from
+    /// the spec file and can be sent to other threads.
+    pub fn to_owned(&self) -> OwnedBackground {
+        OwnedBackground::from(self)
+    }
+
+    /// Merge the `given` values of a `prior` background at the same level into
+    /// this one. Keys defined by the `prior` background take precedence.
+    fn merge(&mut self, prior: Background<'a>) {
+        self.given.extend(prior.given);
+    }
+}
+
 /// An `Example` spec section.
 ///
 /// Modelled after [Gherkin's `Example` section][gherkin].
@@ -52,8 +106,147 @@ pub struct Background<'a> {
 pub struct Example<'a, T = String> {
     pub level: HeadingLevel,
     pub name: &'a str,
-    pub when: HashMap<&'a str, &'a str>,
+    /// The full text of the section heading. Unlike the [`Example::name`],
+    /// which ends at the first inline element, this includes the content of
+    /// code spans (for example, `Example: pi` for ``## Example: `pi` ``).
+    pub heading: String,
+    /// The path of the spec file that defines the example, or `None` if the
+    /// example was not read by [`process`] or [`rewrite`].
+    pub path: Option<&'a Path>,
+    /// The `when` values of the example, which record the keys that were
+    /// looked up by the handler (see [`TrackedWhen`]).
+    pub when: TrackedWhen<'a>,
+    /// The hints given in parentheses after the `when` keys in the spec file
+    /// (for example, `sql` for ``When `input` is (sql):``).
+    ///
+    /// Handlers can use hints in order to select a parser for a value or to
+    /// highlight it in error messages.
+    pub when_hints: HashMap<&'a str, &'a str>,
     pub then: HashMap<&'a str, T>,
+    /// The hints of the `then` keys. These are either given in parentheses
+    /// (for example, ``Then `output` is (sql):``) or taken from the info string
+    /// of the fenced code block that follows the `Then` paragraph (for
+    /// example, ```` ```sql ````). Parenthesized hints take precedence.
+    ///
+    /// Handlers can use hints in order to select a format-aware comparison or
+    /// formatting for a value.
+    pub then_hints: HashMap<&'a str, &'a str>,
+    /// The `then` keys marked as optional with an `(optional)` suffix in the
+    /// spec file (for example, ``Then `result (optional)` is:``).
+    ///
+    /// Optional values are not pre-filled with the expected value before
+    /// calling [`Handler::example`], and are only checked if they are set by
+    /// the handler.
+    pub optional_then: HashSet<&'a str>,
+    /// The text of the prose paragraphs between the section heading and the
+    /// first `When` paragraph (if any), separated by blank lines.
+    pub notes: Option<String>,
+}
+
+impl<'a, T> Example<'a, T> {
+    /// The heading level of the section as a number between 1 and 6.
+    pub fn level_u8(&self) -> u8 {
+        self.level as u8
+    }
+
+    /// The `[tag]` markers in the example name.
+    ///
+    /// Tags are single words enclosed in square brackets, for example
+    /// `Example: Long queries [slow][db]` has the tags `slow` and `db`.
+    /// Bracketed text that contains whitespace (such as the `[row 1]` suffix
+    /// of [`Tabular`] rows) is not a tag.
+    pub fn tags(&self) -> Vec<&'a str> {
+        find_tags(self.name).map(|(_, tag)| tag).collect()
+    }
+
+    /// The example name without its tags.
+    ///
+    /// Tags are expected at the end of the name, so the name is cut at the
+    /// first tag and trailing whitespace is removed.
+    pub fn bare_name(&self) -> &'a str {
+        match find_tags(self.name).next() {
+            Some((start, _)) => self.name[..start].trim_end(),
+            None => self.name,
+        }
+    }
+
+    /// Copy the example into an [`OwnedExample`] that doesn't borrow from the
+    /// spec file and can be sent to other threads.
+    pub fn to_owned(&self) -> OwnedExample
+    where
+        T: AsRef<str>,
+    {
+        OwnedExample::from(self)
+    }
+}
+
+/// A table-driven [`Example`] spec section.
+///
+/// Tabular examples use Markdown tables instead of code blocks after their
+/// `When` and `Then` paragraphs. Each table column defines the values of the
+/// key in its header cell, and each table row yields a separate [`Example`]
+/// named `"{name} [row {i}]"`. Tables in the same section must have the same
+/// number of rows.
+#[derive(Debug)]
+pub struct Tabular<'a, T = String> {
+    pub level: HeadingLevel,
+    pub name: &'a str,
+    /// The full text of the section heading (see [`Example::heading`]).
+    pub heading: String,
+    pub when: Vec<HashMap<&'a str, &'a str>>,
+    /// The hints of the `when` keys (see [`Example::when_hints`]). The hint
+    /// of a `When` paragraph applies to all columns of its table.
+    pub when_hints: HashMap<&'a str, &'a str>,
+    pub then: Vec<HashMap<&'a str, T>>,
+    /// The hints of the `then` keys (see [`Example::then_hints`]). Tables have
+    /// no info strings, so only parenthesized hints are supported.
+    pub then_hints: HashMap<&'a str, &'a str>,
+    pub optional_then: HashSet<&'a str>,
+}
+
+impl<'a, T> Tabular<'a, T> {
+    /// Split a [`Tabular`] section into one named [`Example`] per row.
+    ///
+    /// The `(ignored)` and `(failing)` suffixes of the section name are
+    /// retained in the name of each row.
+    fn into_examples(self) -> Vec<(String, Example<'a, T>)> {
+        let Self {
+            level,
+            name,
+            heading,
+            when,
+            when_hints,
+            then,
+            then_hints,
+            optional_then,
+        } = self;
+
+        let suffix = [" (ignored)", " (failing)"]
+            .into_iter()
+            .find(|suffix| name.ends_with(suffix))
+            .unwrap_or_default();
+        let base_name = &name[..name.len() - suffix.len()];
+        let base_heading = heading.strip_suffix(suffix).unwrap_or(&heading);
+
+        let rows = when.into_iter().zip(then).enumerate();
+        rows.map(|(i, (when, then))| {
+            let row_name = format!("{base_name} [row {}]{suffix}", i + 1);
+            let example = Example {
+                level,
+                name,
+                heading: format!("{base_heading} [row {}]{suffix}", i + 1),
+                path: None,
+                when: when.into(),
+                when_hints: when_hints.clone(),
+                then,
+                then_hints: then_hints.clone(),
+                optional_then: optional_then.clone(),
+                notes: None,
+            };
+            (row_name, example)
+        })
+        .collect()
+    }
 }
 
 #[derive(Debug)]
@@ -61,12 +254,164 @@ pub struct Raw {
     level: HeadingLevel,
 }
 
+/// Statistics about the [`Example`] sections encountered while processing a
+/// spec file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RunStats {
+    /// The total number of [`Example`] sections in the spec file.
+    pub total: usize,
+    /// The number of examples that were run and passed.
+    pub passed: usize,
+    /// The number of examples that were skipped (e.g., `(ignored)` examples).
+    pub skipped: usize,
+    /// The number of examples that were run and failed.
+    pub failed: usize,
+    /// The number of examples that exceeded the `SPECTEST_SLOW_THRESHOLD`.
+    pub slow: usize,
+    /// The total wall-clock time spent in handler calls for the examples that
+    /// were run.
+    pub elapsed: Duration,
+}
+
+impl Display for RunStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            passed,
+            failed,
+            skipped,
+            ..
+        } = self;
+        write!(f, "{passed} passed, {failed} failed, {skipped} skipped")
+    }
+}
+
+/// Information about an [`Example`] passed to the `on_example` callback of
+/// [`process_with_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExampleInfo<'a> {
+    /// The [`Example::name`].
+    pub name: &'a str,
+    /// The zero-based position of the example in the spec file.
+    pub index: usize,
+    /// The wall-clock duration of the handler call, or `None` if the example
+    /// was skipped.
+    pub duration: Option<Duration>,
+}
+
+/// The outcome of an [`Example`] passed to the `on_example` callback of
+/// [`process_with_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExampleOutcome {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+/// Statistics about the [`Example::then`] values updated by a [`rewrite`]
+/// call.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RewriteStats {
+    /// The number of examples with at least one updated `then` value.
+    pub changed: usize,
+    /// The number of examples whose `then` values were left unchanged.
+    pub unchanged: usize,
+    /// Whether the spec file was written.
+    pub written: bool,
+}
+
+impl Display for RewriteStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            changed, unchanged, ..
+        } = self;
+        write!(f, "{changed} example(s) updated, {unchanged} unchanged")
+    }
+}
+
+/// Options that control how a [`process`] call checks the `then` values and
+/// how a [`rewrite`] call writes the spec file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Copy the original spec file to `<path>.spectest.bak` before it is
+    /// overwritten.
+    pub backup: bool,
+    /// Compare the `then` values exactly instead of ignoring a single trailing
+    /// newline (see [`TrailingNewlineComparator`]). Rewrites keep the expected
+    /// values that match under the same comparator and write all other actual
+    /// values verbatim.
+    pub strict_trailing_newline: bool,
+    /// Replace `${key}` references in the [`Example::when`] values with the
+    /// `given` value of the innermost active [`Background`] that defines
+    /// `key`, without its trailing newline. References to undefined keys are
+    /// left as they are.
+    pub interpolate: bool,
+    /// Continue with the next [`Example`] after an example fails instead of
+    /// stopping at the first failure. The error of the first failing example
+    /// is returned once the whole spec file is processed.
+    pub keep_going: bool,
+    /// Print an `UNUSED` warning for each [`Example::when`] key that was not
+    /// looked up by the handler of an example that passes (see
+    /// [`TrackedWhen`]).
+    pub warn_unused_when: bool,
+}
+
+impl Config {
+    /// Create a [`Config`] from the environment.
+    ///
+    /// The `backup` flag is set by the `SPECTEST_BACKUP` environment variable.
+    pub fn from_env() -> Self {
+        Self {
+            backup: env_flag("SPECTEST_BACKUP"),
+            ..Self::default()
+        }
+    }
+
+    /// Set the [`backup`](Config::backup) flag.
+    pub fn backup(mut self, backup: bool) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    /// Set the [`strict_trailing_newline`](Config::strict_trailing_newline)
+    /// flag.
+    pub fn strict_trailing_newline(mut self, strict: bool) -> Self {
+        self.strict_trailing_newline = strict;
+        self
+    }
+
+    /// Set the [`interpolate`](Config::interpolate) flag.
+    pub fn interpolate(mut self, interpolate: bool) -> Self {
+        self.interpolate = interpolate;
+        self
+    }
+
+    /// Set the [`keep_going`](Config::keep_going) flag.
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Set the [`warn_unused_when`](Config::warn_unused_when) flag.
+    pub fn warn_unused_when(mut self, warn: bool) -> Self {
+        self.warn_unused_when = warn;
+        self
+    }
+
+    /// The [`Comparator`] used by [`process`] calls with this config.
+    fn comparator(&self) -> &'static dyn Comparator {
+        match self.strict_trailing_newline {
+            true => &ExactComparator,
+            false => &TrailingNewlineComparator,
+        }
+    }
+}
+
 // Handler trait
 // =============
 
 /// A trait to be implemented by spec handlers.
 pub trait Handler {
-    type Error: Display;
+    type Error;
 
     #[allow(unused)]
     fn enter(&mut self, background: &Background) -> Result<(), Self::Error> {
@@ -79,24 +424,177 @@ pub trait Handler {
     }
 
     fn example(&mut self, example: &mut Example) -> Result<(), Self::Error>;
+
+    /// Prepare the handler before the sections of the spec file at `path`
+    /// are processed.
+    #[allow(unused)]
+    fn before_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        Ok(()) // Ignore spec file boundaries by default.
+    }
+
+    /// Clean up after the sections of the spec file at `path` were processed.
+    ///
+    /// This is called even if processing the file failed.
+    #[allow(unused)]
+    fn after_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        Ok(()) // Ignore spec file boundaries by default.
+    }
+
+    /// Reset the handler state before the next spec file is processed by
+    /// [`run_many`].
+    fn reset(&mut self) {
+        // Handlers are stateless by default.
+    }
+}
+
+/// Closures that take an [`Example`] are handlers that ignore [`Background`]
+/// sections, which is handy for one-off tests:
+///
+/// ```no_run
+/// use spectest::Example;
+///
+/// spectest::run("testdata/echo.md", &mut |example: &mut Example| {
+///     let input = example.when["input"].to_string();
+///     example.then.insert("output", input);
+///     Ok::<_, String>(())
+/// });
+/// ```
+impl<F, E> Handler for F
+where
+    F: FnMut(&mut Example) -> Result<(), E>,
+{
+    type Error = E;
+
+    fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+        self(example)
+    }
+}
+
+/// A version of [`Handler`] whose `enter` and `leave` methods also receive the
+/// stack of currently active [`Background`] sections, ordered from the
+/// outermost to the innermost one.
+///
+/// Every [`Handler`] is also a [`Handler2`] that ignores the `active` stack.
+pub trait Handler2 {
+    type Error;
+
+    #[allow(unused)]
+    fn enter(
+        &mut self,
+        background: &Background,
+        active: &[&Background],
+    ) -> Result<(), Self::Error> {
+        Ok(()) // Ignore background sections by default.
+    }
+
+    #[allow(unused)]
+    fn leave(
+        &mut self,
+        background: &Background,
+        active: &[&Background],
+    ) -> Result<(), Self::Error> {
+        Ok(()) // Ignore background sections by default.
+    }
+
+    fn example(&mut self, example: &mut Example) -> Result<(), Self::Error>;
+
+    /// Prepare the handler before the sections of the spec file at `path`
+    /// are processed.
+    #[allow(unused)]
+    fn before_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        Ok(()) // Ignore spec file boundaries by default.
+    }
+
+    /// Clean up after the sections of the spec file at `path` were processed.
+    ///
+    /// This is called even if processing the file failed.
+    #[allow(unused)]
+    fn after_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        Ok(()) // Ignore spec file boundaries by default.
+    }
+
+    /// Reset the handler state before the next spec file is processed by
+    /// [`run_many`].
+    fn reset(&mut self) {
+        // Handlers are stateless by default.
+    }
+}
+
+impl<H: Handler> Handler2 for H {
+    type Error = H::Error;
+
+    fn enter(
+        &mut self,
+        background: &Background,
+        _active: &[&Background],
+    ) -> Result<(), Self::Error> {
+        Handler::enter(self, background)
+    }
+
+    fn leave(
+        &mut self,
+        background: &Background,
+        _active: &[&Background],
+    ) -> Result<(), Self::Error> {
+        Handler::leave(self, background)
+    }
+
+    fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+        Handler::example(self, example)
+    }
+
+    fn before_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        Handler::before_file(self, path)
+    }
+
+    fn after_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        Handler::after_file(self, path)
+    }
+
+    fn reset(&mut self) {
+        Handler::reset(self)
+    }
 }
 
 #[allow(async_fn_in_trait)]
 /// An `async` version of [`Handler`].
 pub trait AsyncHandler {
-    type Error: Display;
+    type Error;
 
     #[allow(unused)]
-    async fn enter<'a>(&'a mut self, background: &'a Background<'a>) -> Result<(), Self::Error> {
+    async fn enter<'b>(&mut self, background: &'b Background<'b>) -> Result<(), Self::Error> {
         Ok(()) // Ignore background sections by default.
     }
 
     #[allow(unused)]
-    async fn leave<'a>(&'a mut self, background: &'a Background<'a>) -> Result<(), Self::Error> {
+    async fn leave<'b>(&mut self, background: &'b Background<'b>) -> Result<(), Self::Error> {
         Ok(()) // Ignore background sections by default.
     }
 
     async fn example(&mut self, example: &mut Example) -> Result<(), Self::Error>;
+
+    /// An `async` version of [`Handler::before_file`].
+    #[allow(unused)]
+    async fn before_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        Ok(()) // Ignore spec file boundaries by default.
+    }
+
+    /// An `async` version of [`Handler::after_file`].
+    #[allow(unused)]
+    async fn after_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        Ok(()) // Ignore spec file boundaries by default.
+    }
+
+    /// An optional time limit for each [`AsyncHandler::example`] call.
+    ///
+    /// Examples that exceed the limit fail with an [`Error::Timeout`]. If no
+    /// limit is defined, the `SPECTEST_TIMEOUT_MS` environment variable is used
+    /// (see [`run`]). The limit is enforced with a helper thread, so it works
+    /// with any async runtime, but only if the `example` future yields while
+    /// it waits.
+    fn timeout(&self) -> Option<Duration> {
+        None // No time limit by default.
+    }
 }
 
 /// Either [`process`] or [`rewrite`] the spec-style [`Sections`](Section)
@@ -104,28 +602,107 @@ pub trait AsyncHandler {
 /// [`Handler`] depending on the value of the `REWRITE_SPECS` environment
 /// variable.
 ///
+/// The `SPECTEST_UPDATE_SNAPSHOTS` environment variable is an alias for
+/// `REWRITE_SPECS` for users that are familiar with snapshot testing tools
+/// such as `insta`.
+///
 /// If the `rewrite` flag is `true` the `path` is rewritten in order to reflect
 /// the updated code snippets in the [`Example::then`] values.
+///
+/// Set the `SPECTEST_FILTER` environment variable in order to skip all
+/// examples whose name doesn't contain the given (case-insensitive) substring.
+/// Examples whose name ends with `(ignored)` are always skipped.
+///
+/// Examples whose name ends with `(failing)` document known bugs and are
+/// expected to fail: they pass if the handler returns an error or if any
+/// `then` value doesn't match, and fail with an [`Error::UnexpectedPass`]
+/// otherwise. Their `then` values are never rewritten.
+///
+/// Set the `SPECTEST_TAGS` environment variable to a comma-separated list of
+/// tags (for example, `SPECTEST_TAGS=slow,unit`) in order to skip all examples
+/// that don't have at least one of the given tags (see [`Example::tags`]).
+///
+/// Set the `SPECTEST_SLOW_THRESHOLD` environment variable to a duration in
+/// milliseconds in order to print a `SLOW` warning for each example whose
+/// handler call takes longer than that.
+///
+/// Set the `SPECTEST_TIMEOUT_MS` environment variable to a duration in
+/// milliseconds in order to fail each example whose handler call takes longer
+/// than that with an [`Error::Timeout`]. Synchronous handler calls can't be
+/// interrupted, so a `TIMEOUT` warning is printed as soon as the limit is
+/// exceeded and the error is reported once the call completes. Also set the
+/// `SPECTEST_TIMEOUT_ABORT` environment variable in order to abort the whole
+/// process (including all other tests that run in it) instead, so that hung
+/// handler calls don't stall `cargo test` indefinitely.
+///
+/// A note is printed if the spec file doesn't contain any [`Example`] sections,
+/// which usually indicates a misconfigured glob pattern. Set the
+/// `SPECTEST_WARN_EMPTY` environment variable in order to panic instead.
 pub fn run<P, H>(path: P, handler: &mut H)
 where
     P: AsRef<Path>,
-    H: Handler,
+    H: Handler2,
+    H::Error: Display,
 {
-    let rewrite_specs = std::env::var("REWRITE_SPECS")
-        .map(|var| !["false", "off", "0", ""].contains(&var.to_lowercase().as_ref()))
-        .unwrap_or(false);
+    let path_str = path.as_ref().to_str().unwrap_or("unknown").to_string();
+    let result = run_with_stats(path, handler);
+    check_run(&path_str, result);
+}
+
+/// A version of [`run`] for handlers whose [`Handler::Error`] type only
+/// implements [`Debug`].
+///
+/// Handler errors are formatted with `{:?}` in the panic message.
+pub fn run_debug<P, H>(path: P, handler: &mut H)
+where
+    P: AsRef<Path>,
+    H: Handler2,
+    H::Error: Debug,
+{
+    let path_str = path.as_ref().to_str().unwrap_or("unknown").to_string();
+    let result = run_with_stats(path, handler);
+    let result = result.map_err(|err| err.map_handler(|err| format!("{err:?}")));
+    check_run(&path_str, result);
+}
 
-    let path_str = path.as_ref().to_str().unwrap_or("unknown");
-    let result = if rewrite_specs {
-        println!("rewriting spec at `{path_str}`");
-        rewrite(path, handler)
+/// Either [`process`] or [`rewrite`] the spec file at `path` (see [`run`]).
+fn run_with_stats<P, H>(path: P, handler: &mut H) -> Result<RunStats, StatsError<H::Error>>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+{
+    let path_str = path.as_ref().to_str().unwrap_or("unknown").to_string();
+    if rewrite_specs() {
+        rewrite_with_stats(path, handler).map(|(stats, rewrite_stats)| {
+            println!("rewriting spec at `{path_str}` — {rewrite_stats}");
+            stats
+        })
     } else {
         println!("processing spec at `{path_str}`");
-        process(path, handler)
-    };
+        process_with_stats(path, handler)
+    }
+}
 
-    if let Err(err) = result {
-        panic!("{err}");
+/// Print the `result` of a [`run`] call for the spec at `path_str` if
+/// `SPECTEST_VERBOSE` is set or no examples were found, or panic if the call
+/// failed.
+fn check_run<E: Display>(path_str: &str, result: Result<RunStats, StatsError<E>>) {
+    match result {
+        Ok(stats) if stats.total == 0 && env_flag("SPECTEST_WARN_EMPTY") => {
+            panic!("0 examples found in spec at `{path_str}`")
+        }
+        Ok(stats) if stats.total == 0 => println!("0 examples found in spec at `{path_str}`"),
+        Ok(stats) if env_flag("SPECTEST_VERBOSE") => println!("{stats}"),
+        Ok(_) => (),
+        Err(StatsError { stats, error }) => {
+            if env_flag("SPECTEST_VERBOSE") {
+                println!("{stats}");
+            }
+            match *error {
+                Error::IO(err) => panic!("io error for spec at `{path_str}`: {err}"),
+                err => panic!("{err}"),
+            }
+        }
     }
 }
 
@@ -134,356 +711,755 @@ pub async fn async_run<P, H>(path: P, handler: &mut H)
 where
     P: AsRef<Path>,
     H: AsyncHandler,
+    H::Error: Display,
 {
-    let rewrite_specs = std::env::var("REWRITE_SPECS")
-        .map(|var| !["false", "off", "0", ""].contains(&var.to_lowercase().as_ref()))
-        .unwrap_or(false);
-
-    let path_str = path.as_ref().to_str().unwrap_or("unknown");
-    let result = if rewrite_specs {
-        println!("rewriting spec at `{path_str}`");
-        async_rewrite(path, handler).await
+    let path_str = path.as_ref().to_str().unwrap_or("unknown").to_string();
+    let result = if rewrite_specs() {
+        let result = async_rewrite_with_stats(path, handler).await;
+        result.map(|(stats, rewrite_stats)| {
+            println!("rewriting spec at `{path_str}` — {rewrite_stats}");
+            stats
+        })
     } else {
         println!("processing spec at `{path_str}`");
-        async_process(path, handler).await
+        async_process_with_stats(path, handler).await
     };
-
-    if let Err(err) = result {
-        panic!("{err}");
-    }
+    check_run(&path_str, result);
 }
 
-/// Process spec-style [`Sections`](Section) extracted from a Markdown document
-/// at the given `path` using a user-defined [`Handler`].
+/// Either [`process`] or [`rewrite`] each of the given `paths` depending on the
+/// value of the `REWRITE_SPECS` environment variable (see [`run`]).
 ///
-/// # Errors
+/// The `make_handler` function is used to create a fresh [`Handler`] for each
+/// worker thread. Unlike [`run`], this function doesn't panic on the first
+/// error and instead returns the [`Result`] for each path in input order.
 ///
-/// - When the markdown reader encounters a malformed [`Section`].
-/// - When the `handler` returns an error while processing a [`Section`].
-/// - When the read or write process fails with a [`std::io::Error`].
-pub fn process<P, H>(path: P, handler: &mut H) -> Result<(), Error<H::Error>>
+/// The paths are processed in parallel only if the `parallel` feature is
+/// enabled and sequentially otherwise.
+pub fn run_parallel<P, H, F>(paths: &[P], make_handler: F) -> Vec<Result<(), Error<H::Error>>>
 where
-    P: AsRef<Path>,
-    H: Handler,
+    P: AsRef<Path> + Sync,
+    H: Handler2,
+    H::Error: Send,
+    F: Fn() -> H + Sync,
 {
-    // Read Markdown source into a String buffer.
-    let md_source = read_to_string(&path).expect("file");
-
-    // Parse Markdown source.
-    let mut md_doc = md::MdDocument::from_string(&md_source);
+    let rewrite_specs = rewrite_specs();
 
-    const EMPTY_VEC: Vec<Background<'_>> = Vec::<Background>::new();
-    let mut active = [EMPTY_VEC; HeadingLevel::H6 as usize - 1];
-
-    // Iterate over spec-style sections in the parsed input.
-    for section in sections(&mut md_doc) {
-        let Ok(section) = section else {
-            let err = section.unwrap_err().map_span(&md_source);
-            return Err(err.into());
-        };
-
-        match section {
-            Section::Background(background) => match handler.enter(&background) {
-                Ok(()) => active[background.level as usize - 1].push(background),
-                Err(err) => Err(Error::Handler(err))?,
-            },
-            Section::Example(example) => {
-                let Example {
-                    level,
-                    name,
-                    when,
-                    then,
-                } = example;
+    let run_one = |handler: &mut H, path: &P| {
+        if rewrite_specs {
+            rewrite(path, handler).map(|_| ())
+        } else {
+            process(path, handler)
+        }
+    };
 
-                if name.ends_with("(ignored)") {
-                    continue;
-                }
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
 
-                let mut example = Example {
-                    level,
-                    name,
-                    when,
-                    then: then.iter().map(|(k, v)| (*k, v.to_string())).collect(),
-                };
-
-                let result = handler.example(&mut example);
-                result.map_err(Error::<H::Error>::Handler)?;
-
-                for (key, expect) in then.iter() {
-                    let actual = example.then.get(key).expect("actual");
-                    if expect.as_ref() != actual.as_str() {
-                        return Err(Error::Failure {
-                            key: key.to_string(),
-                            example: name.to_string(),
-                            expected: expect.to_string(),
-                            actual: actual.to_string(),
-                        });
-                    }
-                }
-            }
-            Section::Raw(section) => {
-                for backgrounds in active[section.level as usize - 1..].iter_mut().rev() {
-                    for background in backgrounds.drain(..).rev() {
-                        let result = handler.leave(&background);
-                        result.map_err(Error::Handler)?
-                    }
-                }
-            }
-        }
+        paths.par_iter().map_init(&make_handler, run_one).collect()
     }
 
-    Ok(())
+    #[cfg(not(feature = "parallel"))]
+    {
+        let mut handler = make_handler();
+        paths
+            .iter()
+            .map(|path| run_one(&mut handler, path))
+            .collect()
+    }
 }
 
-/// An `async` version of [`process`].
-pub async fn async_process<P, H>(path: P, handler: &mut H) -> Result<(), Error<H::Error>>
+/// Either [`process`] or [`rewrite`] each of the given `paths` in sequence
+/// using the same `handler` depending on the value of the `REWRITE_SPECS`
+/// environment variable (see [`run`]).
+///
+/// The handler is [`reset`](Handler::reset) between spec files. Unlike [`run`],
+/// this function doesn't panic on the first error and instead returns the
+/// [`RunStats`] or the [`StatsError`] for each path in input order.
+#[allow(clippy::type_complexity)]
+pub fn run_many<P, H>(
+    paths: &[P],
+    handler: &mut H,
+) -> Vec<(PathBuf, Result<RunStats, StatsError<H::Error>>)>
 where
     P: AsRef<Path>,
-    H: AsyncHandler,
+    H: Handler2,
 {
-    // Read Markdown source into a String buffer.
-    let md_source = read_to_string(&path).expect("file");
-
-    // Parse Markdown source.
-    let mut md_doc = md::MdDocument::from_string(&md_source);
-
-    const EMPTY_VEC: Vec<Background<'_>> = Vec::<Background>::new();
-    let mut active = [EMPTY_VEC; HeadingLevel::H6 as usize - 1];
-
-    // Iterate over spec-style sections in the parsed input.
-    for section in sections(&mut md_doc) {
-        let Ok(section) = section else {
-            let err = section.unwrap_err().map_span(&md_source);
-            return Err(err.into());
-        };
-
-        match section {
-            Section::Background(background) => match handler.enter(&background).await {
-                Ok(()) => active[background.level as usize - 1].push(background),
-                Err(err) => Err(Error::Handler(err))?,
-            },
-            Section::Example(example) => {
-                let Example {
-                    level,
-                    name,
-                    when,
-                    then,
-                } = example;
-
-                if name.ends_with("(ignored)") {
-                    continue;
-                }
+    let rewrite_specs = rewrite_specs();
 
-                let mut example = Example {
-                    level,
-                    name,
-                    when,
-                    then: then.iter().map(|(k, v)| (*k, v.to_string())).collect(),
-                };
-
-                let result = handler.example(&mut example).await;
-                result.map_err(Error::<H::Error>::Handler)?;
-
-                for (key, expect) in then.iter() {
-                    let actual = example.then.get(key).expect("actual");
-                    if expect.as_ref() != actual.as_str() {
-                        return Err(Error::Failure {
-                            key: key.to_string(),
-                            example: name.to_string(),
-                            expected: expect.to_string(),
-                            actual: actual.to_string(),
-                        });
-                    }
-                }
-            }
-            Section::Raw(section) => {
-                for backgrounds in active[section.level as usize - 1..].iter_mut().rev() {
-                    for background in backgrounds.drain(..).rev() {
-                        let result = handler.leave(&background).await;
-                        result.map_err(Error::Handler)?
-                    }
-                }
-            }
+    let mut results = Vec::with_capacity(paths.len());
+    for (i, path) in paths.iter().enumerate() {
+        if i > 0 {
+            handler.reset();
         }
+        let result = if rewrite_specs {
+            rewrite_with_stats(path, handler).map(|(stats, _)| stats)
+        } else {
+            process_with_stats(path, handler)
+        };
+        results.push((path.as_ref().to_path_buf(), result));
     }
-
-    Ok(())
+    results
 }
 
-/// Rewrite spec-style [`Sections`](Section) extracted from a Markdown document
+/// Process spec-style [`Sections`](Section) extracted from a Markdown document
 /// at the given `path` using a user-defined [`Handler`].
 ///
+/// The [`Example::then`] values produced by the `handler` are compared with
+/// the [`TrailingNewlineComparator`], so a single trailing newline is ignored.
+/// Use [`process_with_config`] in order to compare the values exactly.
+///
+/// Include directives such as `<!-- include: shared.md -->` are replaced by
+/// the sections of the referenced file (see [`ExpandedSource`](md::ExpandedSource)).
+///
 /// # Errors
 ///
 /// - When the markdown reader encounters a malformed [`Section`].
 /// - When the `handler` returns an error while processing a [`Section`].
-/// - When the read or write process fails with a [`std::io::Error`].
-pub fn rewrite<P, H>(path: P, handler: &mut H) -> Result<(), Error<H::Error>>
+/// - When the read or write process fails with a [`std::io::Error`],
+///   including a missing or cyclic include.
+pub fn process<P, H>(path: P, handler: &mut H) -> Result<(), Error<H::Error>>
 where
     P: AsRef<Path>,
-    H: Handler,
+    H: Handler2,
 {
-    // Read Markdown source into a String buffer.
-    let md_source = read_to_string(&path).expect("file");
-
-    // Parse Markdown source.
-    let mut md_doc = md::MdDocument::from_string(&md_source);
-
-    const EMPTY_VEC: Vec<Background<'_>> = Vec::<Background>::new();
-    let mut active = [EMPTY_VEC; HeadingLevel::H6 as usize - 1];
-
-    // Iterate over spec-style sections in the parsed input.
-    for section in sections(&mut md_doc) {
-        let Ok(section) = section else {
-            let err = section.unwrap_err().map_span(&md_source);
-            return Err(err.into());
-        };
-
-        match section {
-            Section::Background(background) => match handler.enter(&background) {
-                Ok(()) => active[background.level as usize - 1].push(background),
-                Err(err) => Err(Error::Handler(err))?,
-            },
-            Section::Example(example) => {
-                let Example {
-                    level,
-                    name,
-                    when,
-                    mut then,
-                } = example;
-
-                if name.ends_with("(ignored)") {
-                    continue;
-                }
+    process_with_stats(path, handler)
+        .map(|_| ())
+        .map_err(Error::from)
+}
 
-                let mut example = Example {
-                    level,
-                    name,
-                    when,
-                    then: then.iter().map(|(k, v)| (*k, v.to_string())).collect(),
-                };
+/// A version of [`process`] that also returns the [`RunStats`] collected
+/// while processing the spec file.
+pub fn process_with_stats<P, H>(path: P, handler: &mut H) -> Result<RunStats, StatsError<H::Error>>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+{
+    process_with_config(path, handler, &Config::default())
+}
 
-                let result = handler.example(&mut example);
-                result.map_err(Error::<H::Error>::Handler)?;
+/// A version of [`process_with_stats`] that uses the given [`Config`].
+///
+/// Set [`Config::strict_trailing_newline`] in order to compare the
+/// [`Example::then`] values with the [`ExactComparator`].
+pub fn process_with_config<P, H>(
+    path: P,
+    handler: &mut H,
+    config: &Config,
+) -> Result<RunStats, StatsError<H::Error>>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+{
+    process_with_outcomes(path, handler, config, |_, _, _| ())
+}
 
-                for (key, expect) in then.iter_mut() {
-                    let actual = example.then.remove(key).expect("actual");
-                    **expect = CowStr::from(actual);
-                }
-            }
-            Section::Raw(section) => {
-                for backgrounds in active[section.level as usize - 1..].iter_mut().rev() {
-                    for background in backgrounds.drain(..).rev() {
-                        let result = handler.leave(&background);
-                        result.map_err(Error::Handler)?
-                    }
-                }
-            }
-        }
-    }
+/// A version of [`process`] that uses the given [`Comparator`] instead of the
+/// default [`TrailingNewlineComparator`] in order to check the
+/// [`Example::then`] values produced by the `handler`.
+///
+/// Use a [`PerKeyComparator`] in order to compare values with different keys
+/// using different comparators.
+pub fn process_with_comparator<P, H, C>(
+    path: P,
+    handler: &mut H,
+    comparator: C,
+) -> Result<(), Error<H::Error>>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+    C: Comparator,
+{
+    let exec = SyncExecutor(handler);
+    let result = block_on(execute(
+        path,
+        exec,
+        Mode::process(&comparator),
+        &mut |_, _, _| (),
+    ));
+    result.map(|_| ()).map_err(Error::from)
+}
 
-    md_doc.write_to_path(&path)?;
+/// A version of [`process`] that calls `on_example` after each [`Example`]
+/// completes (or is skipped).
+///
+/// This can be used in order to report progress for spec files with many
+/// examples.
+pub fn process_with_progress<P, H, F>(
+    path: P,
+    handler: &mut H,
+    on_example: F,
+) -> Result<(), Error<H::Error>>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+    F: Fn(&ExampleInfo, ExampleOutcome),
+{
+    let exec = SyncExecutor(handler);
+    block_on(execute(
+        path,
+        exec,
+        Mode::process(&TrailingNewlineComparator),
+        &mut |info, outcome, _| on_example(info, outcome),
+    ))
+    .map(|_| ())
+    .map_err(Error::from)
+}
 
-    Ok(())
+/// A version of [`process_with_config`] that calls `on_example` after each
+/// [`Example`] completes (or is skipped), with the error that failed the
+/// example (if any).
+pub(crate) fn process_with_outcomes<P, H, F>(
+    path: P,
+    handler: &mut H,
+    config: &Config,
+    mut on_example: F,
+) -> Result<RunStats, StatsError<H::Error>>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+    F: FnMut(&ExampleInfo, ExampleOutcome, Option<&Error<H::Error>>),
+{
+    let exec = SyncExecutor(handler);
+    let mode = Mode::process_with(config);
+    let result = block_on(execute(path, exec, mode, &mut on_example));
+    result.map(|(stats, _)| stats)
 }
 
-/// An `async` version of [`rewrite`].
-pub async fn async_rewrite<P, H>(path: P, handler: &mut H) -> Result<(), Error<H::Error>>
+/// An `async` version of [`process`].
+pub async fn async_process<P, H>(path: P, handler: &mut H) -> Result<(), Error<H::Error>>
 where
     P: AsRef<Path>,
     H: AsyncHandler,
 {
-    // Read Markdown source into a String buffer.
-    let md_source = read_to_string(&path).expect("file");
-
-    // Parse Markdown source.
-    let mut md_doc = md::MdDocument::from_string(&md_source);
+    let result = async_process_with_stats(path, handler).await;
+    result.map(|_| ()).map_err(Error::from)
+}
 
-    const EMPTY_VEC: Vec<Background<'_>> = Vec::<Background>::new();
-    let mut active = [EMPTY_VEC; HeadingLevel::H6 as usize - 1];
+/// An `async` version of [`process_with_progress`].
+pub async fn async_process_with_progress<P, H, F>(
+    path: P,
+    handler: &mut H,
+    on_example: F,
+) -> Result<(), Error<H::Error>>
+where
+    P: AsRef<Path>,
+    H: AsyncHandler,
+    F: Fn(&ExampleInfo, ExampleOutcome),
+{
+    let exec = AsyncExecutor(handler);
+    let mode = Mode::process(&TrailingNewlineComparator);
+    let on_example = &mut |info: &ExampleInfo, outcome, _: Option<&_>| on_example(info, outcome);
+    let result = execute(path, exec, mode, on_example).await;
+    result.map(|_| ()).map_err(Error::from)
+}
 
-    // Iterate over spec-style sections in the parsed input.
-    for section in sections(&mut md_doc) {
-        let Ok(section) = section else {
-            let err = section.unwrap_err().map_span(&md_source);
-            return Err(err.into());
-        };
+/// An `async` version of [`process_with_stats`].
+pub async fn async_process_with_stats<P, H>(
+    path: P,
+    handler: &mut H,
+) -> Result<RunStats, StatsError<H::Error>>
+where
+    P: AsRef<Path>,
+    H: AsyncHandler,
+{
+    let exec = AsyncExecutor(handler);
+    let mode = Mode::process(&TrailingNewlineComparator);
+    let result = execute(path, exec, mode, &mut |_, _, _| ()).await;
+    result.map(|(stats, _)| stats)
+}
 
-        match section {
-            Section::Background(background) => match handler.enter(&background).await {
-                Ok(()) => active[background.level as usize - 1].push(background),
-                Err(err) => Err(Error::Handler(err))?,
-            },
-            Section::Example(example) => {
-                let Example {
-                    level,
-                    name,
-                    when,
-                    mut then,
-                } = example;
+/// A version of [`process`] for a spec given as a Markdown `source` string
+/// instead of a file.
+///
+/// Errors and the [`Handler::before_file`] and [`Handler::after_file`] calls
+/// refer to the spec by the path `<inline spec>`. Use
+/// [`inline_spec!`](crate::inline_spec) in order to name the spec after its
+/// location in the Rust source instead.
+pub fn process_str<H: Handler2>(source: &str, handler: &mut H) -> Result<(), Error<H::Error>> {
+    InlineSpec::new("<inline spec>", source).process(handler)
+}
 
-                if name.ends_with("(ignored)") {
-                    continue;
-                }
+/// A spec defined inline in Rust code, as returned by the
+/// [`inline_spec!`](crate::inline_spec) macro.
+///
+/// Inline specs can't be rewritten, so the `REWRITE_SPECS` environment
+/// variable is ignored by [`InlineSpec::run`].
+#[derive(Debug, Clone, Copy)]
+pub struct InlineSpec<'a> {
+    name: &'a str,
+    source: &'a str,
+}
 
-                let mut example = Example {
-                    level,
-                    name,
-                    when,
-                    then: then.iter().map(|(k, v)| (*k, v.to_string())).collect(),
-                };
+impl<'a> InlineSpec<'a> {
+    /// Create an inline spec with the given `name` (used in place of a path)
+    /// and Markdown `source`.
+    pub fn new(name: &'a str, source: &'a str) -> Self {
+        Self { name, source }
+    }
 
-                let result = handler.example(&mut example).await;
-                result.map_err(Error::<H::Error>::Handler)?;
+    /// Process the spec with the given `handler` (see [`process`]).
+    pub fn process<H: Handler2>(&self, handler: &mut H) -> Result<(), Error<H::Error>> {
+        self.process_with_stats(handler)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
 
-                for (key, expect) in then.iter_mut() {
-                    let actual = example.then.remove(key).expect("actual");
-                    **expect = CowStr::from(actual);
-                }
-            }
-            Section::Raw(section) => {
-                for backgrounds in active[section.level as usize - 1..].iter_mut().rev() {
-                    for background in backgrounds.drain(..).rev() {
-                        let result = handler.leave(&background).await;
-                        result.map_err(Error::Handler)?
-                    }
-                }
-            }
+    /// Process the spec with the given `handler` and panic on the first error
+    /// (see [`run`]).
+    pub fn run<H>(&self, handler: &mut H)
+    where
+        H: Handler2,
+        H::Error: Display,
+    {
+        check_run(self.name, self.process_with_stats(handler));
+    }
+
+    fn process_with_stats<H: Handler2>(
+        &self,
+        handler: &mut H,
+    ) -> Result<RunStats, StatsError<H::Error>> {
+        let exec = SyncExecutor(handler);
+        let name = Path::new(self.name);
+        block_on(execute_str(
+            name,
+            self.source,
+            exec,
+            &TrailingNewlineComparator,
+        ))
+    }
+}
+
+/// Process a single [`Example`] with the given `when` and `then` values
+/// without a spec file.
+///
+/// The `then` values are compared with the [`ExactComparator`]. This is the
+/// function behind the [`assert_example!`](crate::assert_example) macro.
+///
+/// # Errors
+///
+/// - When the `handler` returns an error.
+/// - When an actual `then` value doesn't match the expected value.
+/// - When the `handler` sets a `then` value that is not in `then`.
+pub fn check_example<H: Handler2>(
+    handler: &mut H,
+    name: &str,
+    when: &[(&str, &str)],
+    then: &[(&str, &str)],
+) -> Result<(), Error<H::Error>> {
+    let mut example = Example {
+        level: HeadingLevel::H2,
+        name,
+        heading: name.to_string(),
+        path: None,
+        when: when.iter().copied().collect(),
+        when_hints: HashMap::new(),
+        then: then.iter().map(|(k, v)| (*k, v.to_string())).collect(),
+        then_hints: HashMap::new(),
+        optional_then: HashSet::new(),
+        notes: None,
+    };
+
+    let result = handler.example(&mut example);
+    result.map_err(|error| Error::example(error, &example))?;
+
+    for (key, expected) in then {
+        let actual = example.then.remove(key).unwrap_or_default();
+        if *expected != actual {
+            return Err(Error::Failure {
+                key: key.to_string(),
+                example: name.to_string(),
+                expected: expected.to_string(),
+                actual,
+            });
         }
     }
 
-    md_doc.write_to_path(&path)?;
+    // Reject keys set by the handler that are not in `then`.
+    if let Some(key) = example.then.keys().min() {
+        return Err(Error::UnexpectedKey {
+            key: key.to_string(),
+            example: name.to_string(),
+        });
+    }
 
     Ok(())
 }
 
+/// Parse the spec file at the given `path` into [`OwnedSection`] values
+/// without running a handler.
+///
+/// [`Tabular`] sections are split into one [`OwnedExample`] per table row.
+///
+/// # Errors
+///
+/// - When the markdown reader encounters a malformed [`Section`].
+/// - When the read process fails with a [`std::io::Error`].
+pub fn parse_spec_file<P: AsRef<Path>>(path: P) -> Result<Vec<OwnedSection>, Error<()>> {
+    let path = path.as_ref();
+    let expanded = md::ExpandedSource::read(path)?;
+    let md_source = expanded.source();
+    let mut md_doc = md::MdDocument::from_expanded(&expanded);
+
+    let mut owned_sections = Vec::new();
+    for section in sections(&mut md_doc) {
+        let section = section.map_err(|error| Error::SpecReader {
+            path: path.to_path_buf(),
+            error: error.map_span(md_source),
+        })?;
+        owned_sections.push(match section {
+            Section::Background(background) => OwnedSection::Background((&background).into()),
+            Section::Example(example) => OwnedSection::Example((&example).into()),
+            Section::Tabular(tabular) => {
+                let rows = tabular.into_examples().into_iter();
+                let rows = rows.map(|(name, example)| OwnedExample {
+                    name,
+                    ..OwnedExample::from(&example)
+                });
+                OwnedSection::Tabular(rows.collect())
+            }
+            Section::Raw(raw) => OwnedSection::Raw {
+                level: raw.level as u8,
+            },
+        });
+    }
+    Ok(owned_sections)
+}
+
+/// Rewrite spec-style [`Sections`](Section) extracted from a Markdown document
+/// at the given `path` using a user-defined [`Handler`].
+///
+/// Returns [`RewriteStats`] that count the updated and unchanged examples. The
+/// file at `path` is written only if at least one example was updated and the
+/// rewritten document differs from the current file contents.
+///
+/// Set the `SPECTEST_BACKUP` environment variable in order to keep a copy of
+/// the original file at `<path>.spectest.bak` (see [`rewrite_with_config`]).
+///
+/// # Errors
+///
+/// - When the markdown reader encounters a malformed [`Section`].
+/// - When the `handler` returns an error while processing a [`Section`].
+/// - When the read or write process fails with a [`std::io::Error`].
+pub fn rewrite<P, H>(path: P, handler: &mut H) -> Result<RewriteStats, Error<H::Error>>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+{
+    let result = rewrite_with_stats(path, handler);
+    result
+        .map(|(_, rewrite_stats)| rewrite_stats)
+        .map_err(Error::from)
+}
+
+/// A version of [`rewrite`] that also returns the [`RunStats`] collected
+/// while processing the spec file.
+pub fn rewrite_with_stats<P, H>(
+    path: P,
+    handler: &mut H,
+) -> Result<(RunStats, RewriteStats), StatsError<H::Error>>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+{
+    rewrite_with_config(path, handler, &Config::from_env())
+}
+
+/// A version of [`rewrite_with_stats`] that uses the given `config` instead of
+/// [`Config::from_env`].
+pub fn rewrite_with_config<P, H>(
+    path: P,
+    handler: &mut H,
+    config: &Config,
+) -> Result<(RunStats, RewriteStats), StatsError<H::Error>>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+{
+    let exec = SyncExecutor(handler);
+    block_on(execute(
+        path,
+        exec,
+        Mode::rewrite(config),
+        &mut |_, _, _| (),
+    ))
+}
+
+/// A version of [`rewrite`] that keeps the expected [`Example::then`] values
+/// that match the actual ones under the given [`Comparator`] instead of the
+/// default [`TrailingNewlineComparator`].
+///
+/// Use this with the comparator passed to [`process_with_comparator`], so
+/// that expected values such as the patterns of a `RegexComparator` are only
+/// replaced if they don't match.
+pub fn rewrite_with_comparator<P, H, C>(
+    path: P,
+    handler: &mut H,
+    comparator: C,
+) -> Result<RewriteStats, Error<H::Error>>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+    C: Comparator,
+{
+    let config = Config::from_env();
+    let mode = Mode::Rewrite {
+        config: &config,
+        comparator: &comparator,
+    };
+    let exec = SyncExecutor(handler);
+    let result = block_on(execute(path, exec, mode, &mut |_, _, _| ()));
+    result
+        .map(|(_, rewrite_stats)| rewrite_stats)
+        .map_err(Error::from)
+}
+
+/// An `async` version of [`rewrite`].
+///
+/// The spec file is left untouched if an example exceeds the
+/// [`AsyncHandler::timeout`] limit.
+pub async fn async_rewrite<P, H>(path: P, handler: &mut H) -> Result<RewriteStats, Error<H::Error>>
+where
+    P: AsRef<Path>,
+    H: AsyncHandler,
+{
+    let result = async_rewrite_with_stats(path, handler).await;
+    result
+        .map(|(_, rewrite_stats)| rewrite_stats)
+        .map_err(Error::from)
+}
+
+/// An `async` version of [`rewrite_with_stats`].
+pub async fn async_rewrite_with_stats<P, H>(
+    path: P,
+    handler: &mut H,
+) -> Result<(RunStats, RewriteStats), StatsError<H::Error>>
+where
+    P: AsRef<Path>,
+    H: AsyncHandler,
+{
+    async_rewrite_with_config(path, handler, &Config::from_env()).await
+}
+
+/// An `async` version of [`rewrite_with_config`].
+pub async fn async_rewrite_with_config<P, H>(
+    path: P,
+    handler: &mut H,
+    config: &Config,
+) -> Result<(RunStats, RewriteStats), StatsError<H::Error>>
+where
+    P: AsRef<Path>,
+    H: AsyncHandler,
+{
+    let exec = AsyncExecutor(handler);
+    execute(path, exec, Mode::rewrite(config), &mut |_, _, _| ()).await
+}
+
+// Helpers
+// =======
+
+/// Format the message of an [`Error::Failure`].
+///
+/// Single-line values are displayed inline with caret markers pointing at the
+/// differing characters. Multi-line values are displayed as a unified diff.
+fn failure_message(key: &str, example: &str, expected: &str, actual: &str) -> String {
+    fn single_line(value: &str) -> Option<&str> {
+        let value = value.strip_suffix('\n').unwrap_or(value);
+        (!value.contains('\n')).then_some(value)
+    }
+
+    let (Some(expected), Some(actual)) = (single_line(expected), single_line(actual)) else {
+        let diff = diff::unified("expected", "actual", expected, actual);
+        return format!("unexpected `{key}` in {example}\n{diff}");
+    };
+
+    let exp = expected.chars().collect::<Vec<_>>();
+    let act = actual.chars().collect::<Vec<_>>();
+    let prefix = exp.iter().zip(&act).take_while(|(e, a)| e == a).count();
+    let suffix = exp[prefix..]
+        .iter()
+        .rev()
+        .zip(act[prefix..].iter().rev())
+        .take_while(|(e, a)| e == a)
+        .count();
+    let width = usize::max(exp.len(), act.len()) - prefix - suffix;
+
+    let padding = " ".repeat("actual: \"".len() + prefix);
+    let carets = "^".repeat(width.max(1));
+    format!("unexpected `{key}` in {example}\nexpect: \"{expected}\"\nactual: \"{actual}\"\n{padding}{carets}")
+}
+
+/// Find the `[tag]` markers in the given example `name`, together with the
+/// byte offset of their opening bracket.
+fn find_tags(name: &str) -> impl Iterator<Item = (usize, &str)> {
+    name.match_indices('[').filter_map(move |(start, _)| {
+        let rest = &name[start + 1..];
+        let tag = &rest[..rest.find(']')?];
+        let is_tag = !tag.is_empty() && !tag.contains(|c: char| c.is_whitespace() || c == '[');
+        is_tag.then_some((start, tag))
+    })
+}
+
+/// Check whether spec files should be rewritten instead of processed.
+fn rewrite_specs() -> bool {
+    env_flag("REWRITE_SPECS") || env_flag("SPECTEST_UPDATE_SNAPSHOTS")
+}
+
+/// Interpret the environment variable with the given `name` as a boolean flag.
+fn env_flag(name: &str) -> bool {
+    std::env::var(name)
+        .map(|var| !["false", "off", "0", ""].contains(&var.to_lowercase().as_ref()))
+        .unwrap_or(false)
+}
+
 // Errors
 // ======
 
 /// Errors that might be returned by a [`process`] call.
 #[derive(Error, Debug)]
 pub enum Error<H> {
-    #[error("reader error: {0}")]
-    SpecReader(#[from] reader::Error<Pos>),
-    #[error("md writer error: {0}")]
-    MdWriter(#[from] md::writer::Error),
-    #[error("handler error: {0}")]
-    Handler(H),
-    #[error("unexpected `{key}` in {example}\n# Expected:\n{expected}\n# Actual:\n{actual}")]
+    #[error("reader error in `{}`: {error}", path.display())]
+    SpecReader {
+        path: PathBuf,
+        #[source]
+        error: reader::Error<Pos>,
+    },
+    #[error("md writer error in `{}`: {error}", path.display())]
+    MdWriter {
+        path: PathBuf,
+        #[source]
+        error: md::writer::Error,
+    },
+    #[error("handler error{}: {error}", in_example(example))]
+    Handler {
+        error: H,
+        /// The name of the [`Example`] that triggered the error, or `None` if
+        /// the error was returned for a [`Background`] section.
+        example: Option<String>,
+    },
+    #[error("{}", failure_message(key, example, expected, actual))]
     Failure {
         key: String,
         example: String,
         expected: String,
         actual: String,
     },
+    #[error("unexpected `{key}` in {example}: the key is not defined in the spec")]
+    UnexpectedKey { key: String, example: String },
+    #[error("example {example} timed out after {duration:?}")]
+    Timeout { example: String, duration: Duration },
+    #[error("example {example} was expected to fail but passed")]
+    UnexpectedPass { example: String },
+    #[error("can't rewrite `{key}` in {example}: table cells can't contain line breaks")]
+    MultilineCell { key: String, example: String },
     #[error("io error")]
     IO(#[from] std::io::Error),
     #[error("unknown error")]
     Unknown(String),
 }
 
+impl<H> Error<H> {
+    /// Convert the handler error (if any) with the given function `f`.
+    pub fn map_handler<E, F: FnOnce(H) -> E>(self, f: F) -> Error<E> {
+        match self {
+            Error::SpecReader { path, error } => Error::SpecReader { path, error },
+            Error::MdWriter { path, error } => Error::MdWriter { path, error },
+            Error::Handler { error, example } => Error::Handler {
+                error: f(error),
+                example,
+            },
+            Error::Failure {
+                key,
+                example,
+                expected,
+                actual,
+            } => Error::Failure {
+                key,
+                example,
+                expected,
+                actual,
+            },
+            Error::UnexpectedKey { key, example } => Error::UnexpectedKey { key, example },
+            Error::MultilineCell { key, example } => Error::MultilineCell { key, example },
+            Error::Timeout { example, duration } => Error::Timeout { example, duration },
+            Error::UnexpectedPass { example } => Error::UnexpectedPass { example },
+            Error::IO(err) => Error::IO(err),
+            Error::Unknown(err) => Error::Unknown(err),
+        }
+    }
+
+    /// Wrap a handler `error` returned for a [`Background`] section.
+    fn background(error: H) -> Self {
+        Error::Handler {
+            error,
+            example: None,
+        }
+    }
+
+    /// Wrap a handler `error` returned for the given `example`.
+    fn example(error: H, example: &Example) -> Self {
+        Error::Handler {
+            error,
+            example: Some(example.name.to_string()),
+        }
+    }
+}
+
+impl<H: std::error::Error + 'static> Error<H> {
+    /// A version of [`std::error::Error::source`] that also returns the
+    /// wrapped error of an [`Error::Handler`].
+    ///
+    /// The derived `source` implementation can't return the handler error
+    /// because handler errors are not required to implement
+    /// [`std::error::Error`].
+    pub fn handler_source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Handler { error, .. } => Some(error),
+            _ => std::error::Error::source(self),
+        }
+    }
+}
+
+/// An [`Error`] returned by the `*_with_stats` functions together with the
+/// [`RunStats`] collected up to the failure, including the failed example.
+#[derive(Error, Debug)]
+#[error("{error}")]
+pub struct StatsError<H> {
+    pub stats: RunStats,
+    /// The error, boxed in order to keep the `Err` variant small.
+    pub error: Box<Error<H>>,
+}
+
+impl<H> StatsError<H> {
+    /// Convert the handler error (if any) with the given function `f` (see
+    /// [`Error::map_handler`]).
+    pub fn map_handler<E, F: FnOnce(H) -> E>(self, f: F) -> StatsError<E> {
+        StatsError {
+            stats: self.stats,
+            error: Box::new(self.error.map_handler(f)),
+        }
+    }
+}
+
+impl<H> From<StatsError<H>> for Error<H> {
+    fn from(err: StatsError<H>) -> Self {
+        *err.error
+    }
+}
+
+/// Format the optional `example` context of an [`Error::Handler`] message.
+fn in_example(example: &Option<String>) -> String {
+    match example {
+        Some(example) => format!(" in {example}"),
+        None => String::new(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::io::ErrorKind;
+
     use super::examples::*;
     use super::*;
 
@@ -512,7 +1488,11 @@ mod tests {
 
         let path = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?;
 
-        process(path, &mut TestHandler).expect("`process` call completes cleanly");
+        process(&path, &mut TestHandler).expect("`process` call completes cleanly");
+
+        let missing = path.with_extension("missing.md");
+        let result = process(&missing, &mut TestHandler);
+        assert!(matches!(result, Err(Error::IO(err)) if err.kind() == ErrorKind::NotFound));
 
         Ok(())
     }
@@ -542,13 +1522,1363 @@ mod tests {
 
         let path = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?;
 
-        rewrite(&path, &mut TestHandler).expect("`rewrite` call completes cleanly");
+        let stats = rewrite(&path, &mut TestHandler).expect("`rewrite` call completes cleanly");
+        assert_eq!(stats.to_string(), "1 example(s) updated, 0 unchanged");
+        assert!(stats.written);
 
         let exp = make_spec(INPUT_SQL, "<redacted>");
         let act = read_to_string(&path)?;
 
         assert_eq!(act, exp);
 
+        let stats = rewrite(&path, &mut TestHandler).expect("`rewrite` call completes cleanly");
+        assert_eq!(
+            stats,
+            RewriteStats {
+                changed: 0,
+                unchanged: 1,
+                written: false,
+            }
+        );
+
+        let spec = make_spec(INPUT_SQL, OUTPUT_SQL);
+        let path = write_spec(&spec)?;
+        let config = Config::default().backup(true);
+        let (_, stats) = rewrite_with_config(&path, &mut TestHandler, &config)
+            .expect("`rewrite_with_config` call completes cleanly");
+        assert!(stats.written);
+
+        let mut backup_path = path.to_path_buf().into_os_string();
+        backup_path.push(".spectest.bak");
+        assert_eq!(read_to_string(&backup_path)?, spec);
+        assert_eq!(read_to_string(&path)?, exp);
+        std::fs::remove_file(backup_path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_async_enter_leave() -> std::io::Result<()> {
+        #[derive(Default)]
+        struct TestHandler {
+            environment: Option<String>,
+        }
+
+        impl AsyncHandler for TestHandler {
+            type Error = String;
+
+            async fn enter<'b>(
+                &mut self,
+                background: &'b Background<'b>,
+            ) -> Result<(), Self::Error> {
+                self.environment = background.given.get("environment").map(|s| s.to_string());
+                Ok(())
+            }
+
+            async fn leave<'b>(
+                &mut self,
+                _background: &'b Background<'b>,
+            ) -> Result<(), Self::Error> {
+                self.environment = None;
+                Ok(())
+            }
+
+            async fn example(&mut self, example: &mut Example<'_>) -> Result<(), Self::Error> {
+                let environment = self.environment.as_deref().ok_or("missing environment")?;
+                assert!(environment.starts_with("CREATE TABLE s"));
+                example.then.insert("output", OUTPUT_SQL.to_string());
+                Ok(())
+            }
+        }
+
+        let path = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?;
+
+        let mut handler = TestHandler::default();
+        let result = async_process(&path, &mut handler).await;
+        result.expect("`async_process` call completes cleanly");
+        assert_eq!(handler.environment, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_async_process_timeout() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl AsyncHandler for TestHandler {
+            type Error = String;
+
+            async fn example(&mut self, _example: &mut Example<'_>) -> Result<(), Self::Error> {
+                std::future::pending().await
+            }
+
+            fn timeout(&self) -> Option<Duration> {
+                Some(Duration::from_millis(10))
+            }
+        }
+
+        /// Wakes a task that runs on the current thread.
+        struct ThreadWaker(std::thread::Thread);
+
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let spec = make_spec(INPUT_SQL, OUTPUT_SQL);
+        let path = write_spec(&spec)?;
+
+        let result = async_rewrite(&path, &mut TestHandler).await;
+        assert!(matches!(result, Err(Error::Timeout { .. })));
+        assert_eq!(read_to_string(&path)?, spec);
+
+        // The limit doesn't depend on the `tokio` runtime.
+        let waker = std::sync::Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut handler = TestHandler;
+        let mut future = std::pin::pin!(async_process(&path, &mut handler));
+        let result = loop {
+            match std::future::Future::poll(future.as_mut(), &mut cx) {
+                std::task::Poll::Ready(result) => break result,
+                std::task::Poll::Pending => std::thread::park(),
+            }
+        };
+        assert!(matches!(result, Err(Error::Timeout { .. })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_parallel() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                match example.then.get_mut("output") {
+                    Some(code) if example.when["input"] == INPUT_SQL => {
+                        *code = String::from(OUTPUT_SQL);
+                        Ok(())
+                    }
+                    _ => Err(String::from("unexpected input")),
+                }
+            }
+        }
+
+        let paths = [
+            write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?,
+            write_spec(&make_spec("SELECT 1;", OUTPUT_SQL))?,
+        ];
+
+        let results = run_parallel(&paths, || TestHandler);
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            &results[1],
+            Err(Error::Handler { example: Some(example), .. })
+                if example == "Example: Simple queries"
+        ));
+        let err = results[1].as_ref().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "handler error in Example: Simple queries: unexpected input"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "handler error in Example: Simple queries: Unsupported")]
+    fn test_run_debug() {
+        #[derive(Debug)]
+        enum TestError {
+            Unsupported,
+        }
+
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = TestError;
+
+            fn example(&mut self, _example: &mut Example) -> Result<(), Self::Error> {
+                Err(TestError::Unsupported)
+            }
+        }
+
+        let path = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL)).expect("spec file");
+        run_debug(path, &mut TestHandler);
+    }
+
+    #[test]
+    fn test_handler_source() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = std::io::Error;
+
+            fn example(&mut self, _example: &mut Example) -> Result<(), Self::Error> {
+                Err(std::io::Error::other("connection refused"))
+            }
+        }
+
+        let path = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?;
+
+        let err = process(&path, &mut TestHandler).expect_err("handler error");
+        let source = err.handler_source().expect("handler error source");
+        assert_eq!(source.to_string(), "connection refused");
+        assert!(source.downcast_ref::<std::io::Error>().is_some());
+
+        let err = Error::<std::io::Error>::IO(std::io::Error::other("disk full"));
+        let source = err.handler_source().expect("io error source");
+        assert_eq!(source.to_string(), "disk full");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assert_example() {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                let input = example.when.get("input").ok_or("missing input")?;
+                let result = input
+                    .split('+')
+                    .map(str::parse::<i32>)
+                    .sum::<Result<i32, _>>();
+                let result = result.map_err(|err| err.to_string())?;
+                example.then.insert("result", result.to_string());
+                Ok(())
+            }
+        }
+
+        crate::assert_example!(
+            when: { "input" => "5+3" },
+            then: { "result" => "8" },
+            handler = TestHandler,
+        );
+
+        let result = check_example(
+            &mut TestHandler,
+            "Sum",
+            &[("input", "1+1")],
+            &[("result", "3")],
+        );
+        let err = result.expect_err("mismatched result");
+        assert!(matches!(err, Error::Failure { ref actual, .. } if actual == "2"));
+
+        let result = check_example(&mut TestHandler, "Sum", &[("input", "1+1")], &[]);
+        let err = result.expect_err("unexpected key");
+        assert!(matches!(err, Error::UnexpectedKey { ref key, .. } if key == "result"));
+
+        let result = check_example(&mut TestHandler, "Sum", &[], &[("result", "0")]);
+        let err = result.expect_err("handler error");
+        assert_eq!(err.to_string(), "handler error in Sum: missing input");
+    }
+
+    #[test]
+    fn test_process_str() {
+        let spec = indoc::indoc! {r"
+            ## Example: Identity
+
+            When `x` is:
+
+            ```
+            1
+            ```
+
+            Then `x` is:
+
+            ```
+            2
+            ```
+        "};
+
+        let result = process_str(spec, &mut crate::fixtures::IdentityHandler);
+        let err = result.expect_err("mismatched value");
+        assert!(matches!(err, Error::Failure { ref actual, .. } if actual == "1\n"));
+
+        let spec = spec.replace("2\n", "1\n");
+        process_str(&spec, &mut crate::fixtures::IdentityHandler).expect("processed spec");
+        crate::inline_spec!(&spec).run(&mut crate::fixtures::IdentityHandler);
+
+        let result = process_str("## Example: x\n", &mut crate::fixtures::IdentityHandler);
+        let Err(Error::SpecReader { path, .. }) = result else {
+            panic!("expected a spec reader error, got {result:?}");
+        };
+        assert_eq!(path, Path::new("<inline spec>"));
+    }
+
+    #[test]
+    fn test_parse_spec_file() -> std::io::Result<()> {
+        let path = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?;
+
+        let owned_sections = parse_spec_file(&path).expect("`parse_spec_file` call completes");
+        let [OwnedSection::Raw { level: 1 }, OwnedSection::Background(background), OwnedSection::Example(example)] =
+            owned_sections.as_slice()
+        else {
+            panic!("unexpected sections: {owned_sections:?}");
+        };
+
+        let mut given = background.given.keys().collect::<Vec<_>>();
+        given.sort();
+        assert_eq!(given, vec!["environment", "pipeline"]);
+        assert_eq!(example.name, "Example: Simple queries");
+        assert_eq!(example.when["input"], INPUT_SQL);
+        assert_eq!(example.then["output"], OUTPUT_SQL);
+
+        let path = write_spec("## Example: Broken\n\nWhen pipeline is:\n")?;
+        let result = parse_spec_file(&path);
+        assert!(matches!(result, Err(Error::SpecReader { .. })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_front_matter() -> std::io::Result<()> {
+        let spec = indoc::indoc! {r"
+            ---
+            title: SQL formatting
+            ---
+
+            ## Example: Identity
+
+            When `x` is:
+
+            ```
+            1
+            ```
+
+            Then `x` is:
+
+            ```
+            2
+            ```
+        "};
+        let path = write_spec(spec)?;
+
+        let stats = rewrite(&path, &mut crate::fixtures::IdentityHandler);
+        assert!(stats.expect("`rewrite` call completes cleanly").written);
+        assert_eq!(read_to_string(&path)?, spec.replace("2\n", "1\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_hooks() -> std::io::Result<()> {
+        #[derive(Default)]
+        struct TestHandler {
+            calls: Vec<&'static str>,
+            fail: bool,
+        }
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                self.calls.push("example");
+                if self.fail {
+                    return Err(String::from("failed"));
+                }
+                example.then.insert("output", OUTPUT_SQL.to_string());
+                Ok(())
+            }
+
+            fn before_file(&mut self, _path: &Path) -> Result<(), Self::Error> {
+                self.calls.push("before_file");
+                Ok(())
+            }
+
+            fn after_file(&mut self, _path: &Path) -> Result<(), Self::Error> {
+                self.calls.push("after_file");
+                Ok(())
+            }
+        }
+
+        let path = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?;
+
+        let mut handler = TestHandler::default();
+        process(&path, &mut handler).expect("`process` call completes cleanly");
+        assert_eq!(handler.calls, vec!["before_file", "example", "after_file"]);
+
+        let mut handler = TestHandler {
+            fail: true,
+            ..TestHandler::default()
+        };
+        process(&path, &mut handler).expect_err("handler error");
+        assert_eq!(handler.calls, vec!["before_file", "example", "after_file"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_leave_on_error() -> std::io::Result<()> {
+        #[derive(Default)]
+        struct TestHandler {
+            calls: Vec<String>,
+        }
+
+        impl Handler2 for TestHandler {
+            type Error = String;
+
+            fn enter(&mut self, bg: &Background, _active: &[&Background]) -> Result<(), String> {
+                let key = bg.given.keys().copied().collect::<String>();
+                self.calls.push(format!("enter {key}"));
+                Ok(())
+            }
+
+            fn leave(&mut self, bg: &Background, _active: &[&Background]) -> Result<(), String> {
+                let key = bg.given.keys().copied().collect::<String>();
+                self.calls.push(format!("leave {key}"));
+                Ok(())
+            }
+
+            fn example(&mut self, example: &mut Example) -> Result<(), String> {
+                self.calls.push(example.name.to_string());
+                Err(String::from("failed"))
+            }
+        }
+
+        let background = indoc::indoc! {r"
+            # Background
+
+            Given `x` as:
+
+            ```
+            1
+            ```
+
+            ## Background
+
+            Given `y` as:
+
+            ```
+            2
+            ```
+        "};
+
+        // A handler error.
+        let spec = format!("{background}\n## Example: A\n\nWhen `a` is:\n\n```\n1\n```\n\nThen `b` is:\n\n```\n2\n```\n");
+        let path = write_spec(&spec)?;
+        let mut handler = TestHandler::default();
+        process(&path, &mut handler).expect_err("handler error");
+        let exp = ["enter x", "enter y", "Example: A", "leave y", "leave x"];
+        assert_eq!(handler.calls, exp);
+
+        // A spec reader error.
+        let spec = format!("{background}\n## Example: B\n\nWhen `b` is:\n");
+        let path = write_spec(&spec)?;
+        let mut handler = TestHandler::default();
+        process(&path, &mut handler).expect_err("spec reader error");
+        assert_eq!(handler.calls, ["enter x", "enter y", "leave y", "leave x"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_empty() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, _example: &mut Example) -> Result<(), Self::Error> {
+                Err(String::from("unreachable"))
+            }
+        }
+
+        let spec = indoc::indoc! {r"
+            # Prose only
+
+            This file doesn't contain any examples.
+        "};
+        let path = write_spec(spec)?;
+
+        let stats = process_with_stats(&path, &mut TestHandler);
+        assert_eq!(stats.expect("`process` call completes cleanly").total, 0);
+        run(&path, &mut TestHandler);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailing_newline() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                example
+                    .then
+                    .insert("output", OUTPUT_SQL.trim_end().to_string());
+                Ok(())
+            }
+        }
+
+        let spec = make_spec(INPUT_SQL, OUTPUT_SQL);
+        let path = write_spec(&spec)?;
+
+        process(&path, &mut TestHandler).expect("`process` call completes cleanly");
+
+        let config = Config::default().strict_trailing_newline(true);
+        let result = process_with_config(&path, &mut TestHandler, &config);
+        assert!(
+            matches!(result, Err(StatsError { error, .. }) if matches!(*error, Error::Failure { .. }))
+        );
+
+        // The code block is still terminated by a newline after a rewrite.
+        rewrite(&path, &mut TestHandler).expect("`rewrite` call completes cleanly");
+        assert_eq!(read_to_string(&path)?, spec);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_closure_handler() -> std::io::Result<()> {
+        let path = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?;
+
+        let mut calls = 0;
+        let result = process(&path, &mut |example: &mut Example| {
+            calls += 1;
+            example.then.insert("output", OUTPUT_SQL.to_string());
+            Ok::<_, String>(())
+        });
+        assert!(result.is_ok());
+        assert_eq!(calls, 1);
+
+        let result = process(&path, &mut |_: &mut Example| Err("unsupported"));
+        assert!(matches!(
+            result,
+            Err(Error::Handler {
+                error: "unsupported",
+                ..
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_include() -> std::io::Result<()> {
+        /// Sets the `output` to the `x` value of the active background.
+        #[derive(Default)]
+        struct XHandler(String);
+
+        impl Handler for XHandler {
+            type Error = String;
+
+            fn enter(&mut self, background: &Background) -> Result<(), Self::Error> {
+                self.0 = background.given["x"].to_string();
+                Ok(())
+            }
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                example.then.insert("output", self.0.clone());
+                Ok(())
+            }
+        }
+
+        let dir = tempfile::tempdir()?;
+        let shared = "# Background\n\nGiven `x` as:\n\n```\n1\n```\n";
+        std::fs::write(dir.path().join("shared.md"), shared)?;
+        let spec = |output: &str| {
+            indoc::formatdoc! {r"
+                <!-- include: shared.md -->
+
+                ## Example: Included
+
+                When `input` is:
+
+                ```
+                x
+                ```
+
+                Then `output` is:
+
+                ```
+                {output}
+                ```
+            "}
+        };
+        let path = dir.path().join("spec.md");
+        std::fs::write(&path, spec("1"))?;
+        process(&path, &mut XHandler::default()).expect("included background");
+
+        // Rewriting preserves the directive and leaves the included file as is.
+        std::fs::write(&path, spec("2"))?;
+        let stats = rewrite(&path, &mut XHandler::default()).expect("rewrite");
+        assert!(stats.written);
+        assert_eq!(read_to_string(&path)?, spec("1"));
+        assert_eq!(read_to_string(dir.path().join("shared.md"))?, shared);
+
+        std::fs::write(&path, "<!-- include: spec.md -->\n")?;
+        let result = process(&path, &mut XHandler::default());
+        assert!(matches!(result, Err(Error::IO(err)) if err.kind() == ErrorKind::InvalidData));
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolate() -> std::io::Result<()> {
+        let spec = indoc::indoc! {r"
+            # Background
+
+            Given `table` as:
+
+            ```
+            t
+            ```
+
+            ## Background
+
+            Given `table` as:
+
+            ```
+            s
+            ```
+
+            ### Example: Interpolated
+
+            When `input` is:
+
+            ```
+            SELECT * FROM ${table} JOIN ${other}
+            ```
+
+            Then `output` is:
+
+            ```
+            SELECT * FROM s JOIN ${other}
+            ```
+        "};
+        let path = write_spec(spec)?;
+        let mut handler = |example: &mut Example| {
+            let input = example.when["input"].to_string();
+            example.then.insert("output", input);
+            Ok::<_, String>(())
+        };
+
+        let config = Config::default().interpolate(true);
+        process_with_config(&path, &mut handler, &config).expect("interpolated input");
+
+        // Interpolation is disabled by default.
+        let result = process(&path, &mut handler);
+        assert!(
+            matches!(result, Err(Error::Failure { actual, .. }) if actual.contains("${table}"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_failing() -> std::io::Result<()> {
+        struct TestHandler(Result<&'static str, &'static str>);
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                let output = self.0?;
+                example.then.insert("output", output.to_string());
+                Ok(())
+            }
+        }
+
+        let spec = make_spec(INPUT_SQL, OUTPUT_SQL).replace("queries", "queries (failing)");
+        let path = write_spec(&spec)?;
+
+        // Mismatching values and handler errors are expected.
+        let stats = process_with_stats(&path, &mut TestHandler(Ok("SELECT 1;\n")));
+        assert_eq!(stats.expect("expected failure").passed, 1);
+        let stats = process_with_stats(&path, &mut TestHandler(Err("unsupported")));
+        assert_eq!(stats.expect("expected failure").passed, 1);
+
+        // Matching values are not.
+        let err = process(&path, &mut TestHandler(Ok(OUTPUT_SQL))).expect_err("passed");
+        assert!(matches!(
+            err,
+            Error::UnexpectedPass { ref example } if example == "Example: Simple queries (failing)"
+        ));
+
+        // The `then` values of failing examples are never rewritten.
+        let (_, rewrite_stats) = rewrite_with_stats(&path, &mut TestHandler(Ok("SELECT 1;\n")))
+            .expect("`rewrite` call completes cleanly");
+        assert!(!rewrite_stats.written);
+        assert_eq!(read_to_string(&path)?, spec);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unexpected_key() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                example.then.insert("ouptut", String::from(OUTPUT_SQL));
+                Ok(())
+            }
+        }
+
+        let path = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?;
+
+        let err = process(&path, &mut TestHandler).expect_err("unexpected key");
+        assert!(matches!(
+            err,
+            Error::UnexpectedKey { ref key, .. } if key == "ouptut"
+        ));
+        assert_eq!(
+            err.to_string(),
+            "unexpected `ouptut` in Example: Simple queries: the key is not defined in the spec"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_with_comparator() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                if let Some(code) = example.then.get_mut("output") {
+                    *code = OUTPUT_SQL.replace('\n', " ");
+                }
+                Ok(())
+            }
+        }
+
+        let path = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?;
+
+        let result = process(&path, &mut TestHandler);
+        assert!(matches!(result, Err(Error::Failure { .. })));
+
+        let comparator = PerKeyComparator::default().with("output", NormalizedWhitespaceComparator);
+        process_with_comparator(&path, &mut TestHandler, comparator)
+            .expect("`process_with_comparator` call completes cleanly");
+
+        // Matching values are kept by a rewrite with the same comparator.
+        let spec = read_to_string(&path)?;
+        let comparator = PerKeyComparator::default().with("output", NormalizedWhitespaceComparator);
+        let stats = rewrite_with_comparator(&path, &mut TestHandler, comparator)
+            .expect("`rewrite_with_comparator` call completes cleanly");
+        assert_eq!((stats.changed, stats.written), (0, false));
+        assert_eq!(read_to_string(&path)?, spec);
+
+        let stats = rewrite(&path, &mut TestHandler).expect("`rewrite` call completes cleanly");
+        assert_eq!((stats.changed, stats.written), (1, true));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failure_display() {
+        let failure = |expected: &str, actual: &str| Error::<String>::Failure {
+            key: String::from("output"),
+            example: String::from("Example: test"),
+            expected: String::from(expected),
+            actual: String::from(actual),
+        };
+
+        assert_eq!(
+            failure("foo bar\n", "foo baz\n").to_string(),
+            indoc::indoc! {r#"
+                unexpected `output` in Example: test
+                expect: "foo bar"
+                actual: "foo baz"
+                               ^"#}
+        );
+        assert_eq!(
+            failure("foo\n", "foo bar\n").to_string(),
+            indoc::indoc! {r#"
+                unexpected `output` in Example: test
+                expect: "foo"
+                actual: "foo bar"
+                            ^^^^"#}
+        );
+        assert_eq!(
+            failure("a\nb\n", "a\nc\n").to_string(),
+            indoc::indoc! {"
+                unexpected `output` in Example: test
+                --- expected
+                +++ actual
+                @@ -1,2 +1,2 @@
+                 a
+                -b
+                +c
+            "}
+        );
+    }
+
+    #[test]
+    fn test_process_with_progress() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                if let Some(code) = example.then.get_mut("output") {
+                    *code = String::from(OUTPUT_SQL);
+                }
+                Ok(())
+            }
+        }
+
+        let spec = make_spec(INPUT_SQL, OUTPUT_SQL);
+        let spec = spec.replace("queries", "queries (ignored)") + "\n" + &spec;
+        let path = write_spec(&spec)?;
+
+        let progress = std::cell::RefCell::new(Vec::new());
+        let on_example = |info: &ExampleInfo, outcome| {
+            let ran = info.duration.is_some();
+            progress
+                .borrow_mut()
+                .push((info.index, info.name.to_string(), ran, outcome));
+        };
+        process_with_progress(path, &mut TestHandler, on_example).expect("progress");
+
+        assert_eq!(
+            progress.into_inner(),
+            vec![
+                (
+                    0,
+                    "Example: Simple queries (ignored)".into(),
+                    false,
+                    ExampleOutcome::Skipped
+                ),
+                (
+                    1,
+                    "Example: Simple queries".into(),
+                    true,
+                    ExampleOutcome::Passed
+                ),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_warn_unused_when() -> std::io::Result<()> {
+        #[derive(Default)]
+        struct TestHandler(Vec<String>);
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                // Look up all `then` values, but none of the `when` values.
+                if let Some(code) = example.then.get_mut("output") {
+                    *code = String::from(OUTPUT_SQL);
+                }
+                let unused = example.when.unused_keys().into_iter();
+                self.0.extend(unused.map(String::from));
+                Ok(())
+            }
+        }
+
+        let path = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?;
+        let mut handler = TestHandler::default();
+        let config = Config::default().warn_unused_when(true);
+        let stats = process_with_config(&path, &mut handler, &config).expect("passing spec");
+
+        assert_eq!(stats.passed, 1);
+        assert_eq!(handler.0, vec!["input"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tabular() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                let x = example.when["x"]
+                    .parse::<i32>()
+                    .map_err(|e| e.to_string())?;
+                let y = example.when["y"]
+                    .parse::<i32>()
+                    .map_err(|e| e.to_string())?;
+                example.then.insert("sum", (x + y).to_string());
+                Ok(())
+            }
+        }
+
+        let spec = |sum: &str| {
+            indoc::formatdoc! {"
+                ## Example: Addition
+
+                When `input` is:
+
+                | x | y |
+                | --- | --- |
+                | 1 | 2 |
+                | 3 | 4 |
+
+                Then `result` is:
+
+                | sum |
+                | --- |
+                | 3 |
+                | {sum} |
+            "}
+        };
+        let path = write_spec(&spec("0"))?;
+
+        let result = process(&path, &mut TestHandler);
+        let Err(Error::Failure { example, .. }) = result else {
+            panic!("expected a failure, got {result:?}");
+        };
+        assert_eq!(example, "Example: Addition [row 2]");
+
+        let stats = rewrite(&path, &mut TestHandler).expect("`rewrite` call completes cleanly");
+        assert_eq!(
+            stats,
+            RewriteStats {
+                changed: 1,
+                unchanged: 1,
+                written: true,
+            }
+        );
+        assert_eq!(read_to_string(&path)?, spec("7"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tabular_rewrite_cells() -> std::io::Result<()> {
+        struct TestHandler(&'static str);
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                example.then.insert("output", self.0.to_string());
+                Ok(())
+            }
+        }
+
+        let spec = |output: &str| {
+            indoc::formatdoc! {"
+                ## Example: Cells
+
+                When `input` is:
+
+                | x |
+                | --- |
+                | 1 |
+
+                Then `result` is:
+
+                | output |
+                | --- |
+                | {output} |
+            "}
+        };
+        let path = write_spec(&spec("0"))?;
+
+        // The trailing newline of a value is not written into the table cell.
+        let config = Config::default().strict_trailing_newline(true);
+        rewrite_with_config(&path, &mut TestHandler("7\n"), &config).expect("rewrite");
+        assert_eq!(read_to_string(&path)?, spec("7"));
+        process(&path, &mut TestHandler("7\n")).expect("`process` call completes cleanly");
+
+        // A `|` is escaped, so it doesn't split the table cell.
+        rewrite(&path, &mut TestHandler("a|b")).expect("rewrite");
+        assert_eq!(read_to_string(&path)?, spec("a\\|b"));
+        process(&path, &mut TestHandler("a|b")).expect("`process` call completes cleanly");
+
+        // Values with line breaks can't be written into a table cell.
+        let result = rewrite(&path, &mut TestHandler("a\nb\n"));
+        assert!(
+            matches!(result, Err(Error::MultilineCell { .. })),
+            "{result:?}"
+        );
+        assert_eq!(read_to_string(&path)?, spec("a\\|b"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_scoped_background() -> std::io::Result<()> {
+        #[derive(Default)]
+        struct TestHandler {
+            calls: Vec<String>,
+        }
+
+        impl Handler2 for TestHandler {
+            type Error = String;
+
+            fn enter(&mut self, bg: &Background, _active: &[&Background]) -> Result<(), String> {
+                let key = bg.given.keys().copied().collect::<String>();
+                self.calls.push(format!("enter {key}"));
+                Ok(())
+            }
+
+            fn leave(&mut self, bg: &Background, _active: &[&Background]) -> Result<(), String> {
+                let key = bg.given.keys().copied().collect::<String>();
+                self.calls.push(format!("leave {key}"));
+                Ok(())
+            }
+
+            fn example(&mut self, example: &mut Example) -> Result<(), String> {
+                self.calls.push(example.name.to_string());
+                Ok(())
+            }
+        }
+
+        let path = write_spec(indoc::indoc! {r"
+            # Background
+
+            Given `x` as:
+
+            ```
+            1
+            ```
+
+            ## Background
+
+            Given `y` as:
+
+            ```
+            2
+            ```
+
+            # Feature
+
+            ###### Background
+
+            Given `z` as:
+
+            ```
+            3
+            ```
+
+            ## Example: test
+
+            When `input` is:
+
+            ```
+            x
+            ```
+
+            Then `output` is:
+
+            ```
+            1
+            ```
+        "})?;
+
+        let mut handler = TestHandler::default();
+        process(path, &mut handler).expect("`process` call completes cleanly");
+
+        assert_eq!(
+            handler.calls,
+            vec![
+                "enter x",
+                "enter y",
+                "leave y",
+                "enter z",
+                "Example: test",
+                "leave z",
+                "leave x",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merged_backgrounds() -> std::io::Result<()> {
+        #[derive(Default)]
+        struct TestHandler {
+            calls: Vec<String>,
+        }
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn enter(&mut self, bg: &Background) -> Result<(), String> {
+                let given = bg.given.iter().map(|(k, v)| format!("{k}={}", v.trim()));
+                let mut given = given.collect::<Vec<_>>();
+                given.sort();
+                self.calls.push(format!("enter {}", given.join(",")));
+                Ok(())
+            }
+
+            fn leave(&mut self, bg: &Background) -> Result<(), String> {
+                self.calls.push(format!("leave {}", bg.given.len()));
+                Ok(())
+            }
+
+            fn example(&mut self, _example: &mut Example) -> Result<(), String> {
+                Ok(())
+            }
+        }
+
+        let path = write_spec(indoc::indoc! {r"
+            ## Background
+
+            Given `x` as:
+
+            ```
+            1
+            ```
+
+            ## Background
+
+            Given `x` as:
+
+            ```
+            2
+            ```
+
+            And `y` as:
+
+            ```
+            3
+            ```
+        "})?;
+
+        let mut handler = TestHandler::default();
+        process(path, &mut handler).expect("`process` call completes cleanly");
+
+        assert_eq!(
+            handler.calls,
+            vec!["enter x=1", "leave 1", "enter x=1,y=3", "leave 2"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_optional_then() -> std::io::Result<()> {
+        struct TestHandler {
+            log: Option<&'static str>,
+        }
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                assert!(example.optional_then.contains("log"));
+                assert!(!example.then.contains_key("log"));
+                if let Some(log) = self.log {
+                    example.then.insert("log", log.to_string());
+                }
+                Ok(())
+            }
+        }
+
+        let spec = make_spec(INPUT_SQL, OUTPUT_SQL)
+            + indoc::indoc! {"
+
+            And `log (optional)` is:
+
+            ```
+            ok
+            ```
+        "};
+        let path = write_spec(&spec)?;
+
+        process(&path, &mut TestHandler { log: None }).expect("missing `log` is skipped");
+        process(&path, &mut TestHandler { log: Some("ok\n") }).expect("`log` matches");
+        let result = process(&path, &mut TestHandler { log: Some("err\n") });
+        assert!(matches!(result, Err(Error::Failure { key, .. }) if key == "log"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_example_tags() {
+        let example = |name: &'static str| Example::<String> {
+            level: HeadingLevel::H2,
+            name,
+            heading: name.to_string(),
+            path: None,
+            when: TrackedWhen::default(),
+            when_hints: HashMap::new(),
+            then: HashMap::new(),
+            then_hints: HashMap::new(),
+            optional_then: HashSet::new(),
+            notes: None,
+        };
+
+        let tagged = example("Example: Queries [slow][db] [row 1]");
+        assert_eq!(tagged.level_u8(), 2);
+        assert_eq!(tagged.tags(), vec!["slow", "db"]);
+        assert_eq!(tagged.bare_name(), "Example: Queries");
+
+        let untagged = example("Example: Queries [row 1] (ignored)");
+        assert!(untagged.tags().is_empty());
+        assert_eq!(untagged.bare_name(), untagged.name);
+    }
+
+    #[test]
+    fn test_handler2_active_stack() -> std::io::Result<()> {
+        #[derive(Default)]
+        struct TestHandler {
+            calls: Vec<String>,
+        }
+
+        impl Handler2 for TestHandler {
+            type Error = String;
+
+            fn enter(&mut self, bg: &Background, active: &[&Background]) -> Result<(), String> {
+                let keys = active.iter().flat_map(|bg| bg.given.keys());
+                let keys = keys.copied().collect::<Vec<_>>().join(",");
+                let key = bg.given.keys().copied().collect::<String>();
+                self.calls.push(format!("enter {key} [{keys}]"));
+                Ok(())
+            }
+
+            fn leave(&mut self, bg: &Background, active: &[&Background]) -> Result<(), String> {
+                let keys = active.iter().flat_map(|bg| bg.given.keys());
+                let keys = keys.copied().collect::<Vec<_>>().join(",");
+                let key = bg.given.keys().copied().collect::<String>();
+                self.calls.push(format!("leave {key} [{keys}]"));
+                Ok(())
+            }
+
+            fn example(&mut self, _example: &mut Example) -> Result<(), String> {
+                Ok(())
+            }
+        }
+
+        let path = write_spec(indoc::indoc! {r"
+            ## Background
+
+            Given `x` as:
+
+            ```
+            1
+            ```
+
+            ### Background
+
+            Given `y` as:
+
+            ```
+            2
+            ```
+
+            ## Raw
+        "})?;
+
+        let mut handler = TestHandler::default();
+        process(path, &mut handler).expect("`process` call completes cleanly");
+
+        assert_eq!(
+            handler.calls,
+            vec!["enter x []", "enter y [x]", "leave y [x]", "leave x []"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_with_stats() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                if let Some(code) = example.then.get_mut("output") {
+                    *code = String::from(OUTPUT_SQL);
+                }
+                Ok(())
+            }
+        }
+
+        let spec = make_spec(INPUT_SQL, OUTPUT_SQL);
+        let spec = spec.clone() + "\n" + &spec.replace("queries", "queries (ignored)");
+        let path = write_spec(&spec)?;
+
+        let stats = process_with_stats(path, &mut TestHandler).expect("stats");
+
+        assert_eq!(
+            stats,
+            RunStats {
+                total: 2,
+                passed: 1,
+                skipped: 1,
+                failed: 0,
+                slow: 0,
+                elapsed: stats.elapsed,
+            }
+        );
+        assert_eq!(stats.to_string(), "1 passed, 0 failed, 1 skipped");
+
+        // The stats up to and including a failed example are returned with the error.
+        let failing = make_spec(INPUT_SQL, "<redacted>").replace("Simple", "Redacted");
+        let path = write_spec(&(spec + "\n" + &failing))?;
+
+        let err = process_with_stats(path, &mut TestHandler).expect_err("failure");
+        assert!(matches!(*err.error, Error::Failure { .. }));
+        assert_eq!((err.stats.total, err.stats.passed), (3, 1));
+        assert_eq!((err.stats.failed, err.stats.skipped), (1, 1));
+        assert_eq!(err.stats.to_string(), "1 passed, 1 failed, 1 skipped");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_many() -> std::io::Result<()> {
+        #[derive(Default)]
+        struct TestHandler {
+            examples: usize,
+            resets: usize,
+        }
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                self.examples += 1;
+                if self.examples > 1 {
+                    return Err(String::from("state was not reset"));
+                }
+                if let Some(code) = example.then.get_mut("output") {
+                    *code = String::from(OUTPUT_SQL);
+                }
+                Ok(())
+            }
+
+            fn reset(&mut self) {
+                self.examples = 0;
+                self.resets += 1;
+            }
+        }
+
+        let path1 = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?;
+        let path2 = write_spec(&make_spec(INPUT_SQL, "<redacted>"))?;
+        let paths = [&path1, &path1, &path2];
+
+        let mut handler = TestHandler::default();
+        let results = run_many(&paths, &mut handler);
+
+        assert_eq!(handler.resets, 2);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0, path1.to_path_buf());
+        assert!(matches!(results[0].1, Ok(RunStats { passed: 1, .. })));
+        assert!(matches!(results[1].1, Ok(RunStats { passed: 1, .. })));
+        assert!(
+            matches!(&results[2].1, Err(StatsError { error, .. }) if matches!(**error, Error::Failure { .. }))
+        );
+
         Ok(())
     }
 }
@@ -0,0 +1,109 @@
+//! A [`Handler`] adapter that runs two handlers in sequence.
+
+use std::path::Path;
+
+use super::{Background, Example, Handler};
+
+/// Chain two handlers so that each [`Handler`] call is first delegated to `h1`
+/// and then to `h2`.
+///
+/// See [`ChainedHandler`] for details.
+pub fn chain<H1, H2>(h1: H1, h2: H2) -> ChainedHandler<H1, H2>
+where
+    H1: Handler,
+    H2: Handler<Error = H1::Error>,
+{
+    ChainedHandler { h1, h2 }
+}
+
+/// A [`Handler`] that delegates each call to `h1` and then to `h2`,
+/// short-circuiting on the first error.
+///
+/// Since both handlers receive the same [`Example`] instance, the `then` values
+/// set by `h1` are visible to `h2`.
+#[derive(Debug)]
+pub struct ChainedHandler<H1, H2> {
+    pub h1: H1,
+    pub h2: H2,
+}
+
+impl<H1, H2> Handler for ChainedHandler<H1, H2>
+where
+    H1: Handler,
+    H2: Handler<Error = H1::Error>,
+{
+    type Error = H1::Error;
+
+    fn enter(&mut self, background: &Background) -> Result<(), Self::Error> {
+        self.h1.enter(background)?;
+        self.h2.enter(background)
+    }
+
+    fn leave(&mut self, background: &Background) -> Result<(), Self::Error> {
+        self.h1.leave(background)?;
+        self.h2.leave(background)
+    }
+
+    fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+        self.h1.example(example)?;
+        self.h2.example(example)
+    }
+
+    fn before_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        self.h1.before_file(path)?;
+        self.h2.before_file(path)
+    }
+
+    /// Call the `after_file` hooks of both handlers, returning the first
+    /// error (if any).
+    fn after_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        let result = self.h1.after_file(path);
+        result.and(self.h2.after_file(path))
+    }
+
+    fn reset(&mut self) {
+        self.h1.reset();
+        self.h2.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::examples::*;
+    use super::super::rewrite;
+    use super::*;
+
+    #[test]
+    fn test_chain() -> std::io::Result<()> {
+        struct Redact;
+
+        impl Handler for Redact {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                example.then.insert("output", String::from("<redacted>\n"));
+                Ok(())
+            }
+        }
+
+        struct Check;
+
+        impl Handler for Check {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                match example.then.get("output") {
+                    Some(output) if output == "<redacted>\n" => Ok(()),
+                    _ => Err(String::from("`output` not redacted")),
+                }
+            }
+        }
+
+        let path = write_spec(&make_spec(INPUT_SQL, OUTPUT_SQL))?;
+
+        rewrite(&path, &mut chain(Check, Redact)).expect_err("`Check` fails before `Redact`");
+        rewrite(&path, &mut chain(Redact, Check)).expect("`rewrite` call completes cleanly");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+//! Strategies for comparing expected and actual [`Example::then`] values.
+//!
+//! [`Example::then`]: super::Example::then
+
+use std::collections::HashMap;
+
+/// A trait for deciding whether an `actual` value produced by a handler
+/// matches the `expected` value defined in the spec file.
+pub trait Comparator {
+    fn matches(&self, key: &str, expected: &str, actual: &str) -> bool;
+}
+
+impl<C: Comparator + ?Sized> Comparator for &C {
+    fn matches(&self, key: &str, expected: &str, actual: &str) -> bool {
+        (**self).matches(key, expected, actual)
+    }
+}
+
+impl<C: Comparator + ?Sized> Comparator for Box<C> {
+    fn matches(&self, key: &str, expected: &str, actual: &str) -> bool {
+        (**self).matches(key, expected, actual)
+    }
+}
+
+/// A [`Comparator`] that requires both values to be identical.
+///
+/// This is the comparator used by [`process_with_config`] if
+/// [`Config::strict_trailing_newline`] is set.
+///
+/// [`process_with_config`]: super::process_with_config
+/// [`Config::strict_trailing_newline`]: super::Config::strict_trailing_newline
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExactComparator;
+
+impl Comparator for ExactComparator {
+    fn matches(&self, _key: &str, expected: &str, actual: &str) -> bool {
+        expected == actual
+    }
+}
+
+/// A [`Comparator`] that ignores a single trailing newline in both values.
+///
+/// This is the comparator used by [`process`](super::process), so a handler
+/// may omit the trailing newline that values defined in code blocks have.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TrailingNewlineComparator;
+
+impl Comparator for TrailingNewlineComparator {
+    fn matches(&self, _key: &str, expected: &str, actual: &str) -> bool {
+        let expected = expected.strip_suffix('\n').unwrap_or(expected);
+        let actual = actual.strip_suffix('\n').unwrap_or(actual);
+        expected == actual
+    }
+}
+
+/// A [`Comparator`] that ignores leading and trailing whitespace.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TrimmedComparator;
+
+impl Comparator for TrimmedComparator {
+    fn matches(&self, _key: &str, expected: &str, actual: &str) -> bool {
+        expected.trim() == actual.trim()
+    }
+}
+
+/// A [`Comparator`] that treats every run of whitespace characters (including
+/// line breaks) as a single space and ignores leading and trailing whitespace.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NormalizedWhitespaceComparator;
+
+impl Comparator for NormalizedWhitespaceComparator {
+    fn matches(&self, _key: &str, expected: &str, actual: &str) -> bool {
+        expected.split_whitespace().eq(actual.split_whitespace())
+    }
+}
+
+/// A [`Comparator`] that interprets the `expected` value as a regular
+/// expression that must match the entire `actual` value.
+///
+/// A single trailing line break is removed from both values before matching,
+/// and `expected` values that are not valid regular expressions never match.
+///
+/// Requires the `regex` feature.
+#[cfg(feature = "regex")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RegexComparator;
+
+#[cfg(feature = "regex")]
+impl Comparator for RegexComparator {
+    fn matches(&self, _key: &str, expected: &str, actual: &str) -> bool {
+        let expected = expected.strip_suffix('\n').unwrap_or(expected);
+        let actual = actual.strip_suffix('\n').unwrap_or(actual);
+        regex::Regex::new(&format!("^(?:{expected})$")).is_ok_and(|re| re.is_match(actual))
+    }
+}
+
+/// A [`Comparator`] adapter that selects a comparator based on the compared
+/// `then` key, falling back to a `default` comparator for all other keys.
+pub struct PerKeyComparator {
+    default: Box<dyn Comparator>,
+    overrides: HashMap<String, Box<dyn Comparator>>,
+}
+
+impl PerKeyComparator {
+    /// Create a new [`PerKeyComparator`] that uses the `default` comparator
+    /// for all keys.
+    pub fn new<C: Comparator + 'static>(default: C) -> Self {
+        Self {
+            default: Box::new(default),
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Use the given `comparator` for values with the given `key`.
+    pub fn with<C: Comparator + 'static>(mut self, key: &str, comparator: C) -> Self {
+        self.overrides.insert(key.to_string(), Box::new(comparator));
+        self
+    }
+}
+
+/// Uses the [`TrailingNewlineComparator`] of [`process`](super::process) for
+/// all keys without an override.
+impl Default for PerKeyComparator {
+    fn default() -> Self {
+        Self::new(TrailingNewlineComparator)
+    }
+}
+
+impl Comparator for PerKeyComparator {
+    fn matches(&self, key: &str, expected: &str, actual: &str) -> bool {
+        match self.overrides.get(key) {
+            Some(comparator) => comparator.matches(key, expected, actual),
+            None => self.default.matches(key, expected, actual),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_comparators() {
+        assert!(ExactComparator.matches("k", "a b\n", "a b\n"));
+        assert!(!ExactComparator.matches("k", "a b\n", "a b"));
+
+        assert!(TrailingNewlineComparator.matches("k", "a b\n", "a b"));
+        assert!(TrailingNewlineComparator.matches("k", "a b", "a b\n"));
+        assert!(!TrailingNewlineComparator.matches("k", "a b\n\n", "a b"));
+
+        assert!(TrimmedComparator.matches("k", "a b\n", " a b"));
+        assert!(!TrimmedComparator.matches("k", "a b\n", "a  b"));
+
+        assert!(NormalizedWhitespaceComparator.matches("k", "a b\n", "a\n  b"));
+        assert!(!NormalizedWhitespaceComparator.matches("k", "a b\n", "ab"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_comparator() {
+        assert!(RegexComparator.matches("k", "id: \\d+\n", "id: 42\n"));
+        assert!(!RegexComparator.matches("k", "id: \\d+\n", "id: 42 (x)\n"));
+        assert!(!RegexComparator.matches("k", "id: (\n", "id: (\n"));
+    }
+
+    #[test]
+    fn test_per_key_comparator() {
+        let comparator = PerKeyComparator::default().with("log", TrimmedComparator);
+
+        assert!(comparator.matches("log", " ok\n", "ok"));
+        assert!(comparator.matches("output", "ok\n", "ok"));
+        assert!(!comparator.matches("output", " ok\n", "ok"));
+    }
+}
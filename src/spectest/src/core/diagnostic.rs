@@ -0,0 +1,218 @@
+//! [`miette::Diagnostic`] implementations for spectest errors.
+//!
+//! Requires the `miette` feature.
+
+use std::fmt::{Debug, Display};
+use std::path::Path;
+
+use miette::{Diagnostic, LabeledSpan, MietteError, NamedSource, SourceCode, SourceSpan};
+
+use super::reader::{self, Pos};
+use super::Error;
+
+/// A [`SourceCode`] implementation that wraps the text of a spec file.
+///
+/// Attach it to a [`miette::Report`] created from an [`Error`] in order to
+/// render the labels of a malformed spec file:
+///
+/// ```no_run
+/// # use spectest::core::MietteMdSource;
+/// # fn check(path: &str, err: spectest::Error<String>) -> std::io::Result<()> {
+/// let source = MietteMdSource::from_path(path)?;
+/// let report = miette::Report::new(err).with_source_code(source);
+/// eprintln!("{report:?}");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MietteMdSource {
+    inner: NamedSource<String>,
+}
+
+impl MietteMdSource {
+    /// Wrap the given `source` text of the spec file at `path`.
+    pub fn new<P: AsRef<Path>>(path: P, source: String) -> Self {
+        let name = path.as_ref().display().to_string();
+        let inner = NamedSource::new(name, source).with_language("markdown");
+        Self { inner }
+    }
+
+    /// Read the source text of the spec file at `path`. Include directives
+    /// are expanded, so that the positions in reader errors match the text.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let source = crate::md::ExpandedSource::read(&path)?;
+        Ok(Self::new(path, source.source().to_string()))
+    }
+}
+
+impl SourceCode for MietteMdSource {
+    fn read_span<'a>(
+        &'a self,
+        span: &SourceSpan,
+        context_lines_before: usize,
+        context_lines_after: usize,
+    ) -> Result<Box<dyn miette::SpanContents<'a> + 'a>, MietteError> {
+        self.inner
+            .read_span(span, context_lines_before, context_lines_after)
+    }
+}
+
+impl Diagnostic for reader::Error<Pos> {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        use reader::Error::*;
+        let code = match self {
+            ExpectedSpecParagraph { .. } => "spectest::expected_spec_paragraph",
+            ExpectedCode { .. } => "spectest::expected_code",
+            ExpectedTable { .. } => "spectest::expected_table",
+            ExpectedTableCell { .. } => "spectest::expected_table_cell",
+            MismatchedRows { .. } => "spectest::mismatched_rows",
+            DuplicateKey { .. } => "spectest::duplicate_key",
+            MissingGiven { .. } => "spectest::missing_given",
+            MissingWhen { .. } => "spectest::missing_when",
+            MissingThen { .. } => "spectest::missing_then",
+        };
+        Some(Box::new(code))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        use reader::Error::*;
+        let help = match self {
+            ExpectedSpecParagraph { pattern, .. } => {
+                format!("spec paragraphs must have the form '{pattern}'")
+            }
+            ExpectedCode { .. } => "add a fenced code block after the paragraph".to_string(),
+            ExpectedTable { .. } => "add a table after the paragraph".to_string(),
+            ExpectedTableCell { .. } => {
+                "use a single text or inline code value in each table cell".to_string()
+            }
+            MismatchedRows { .. } => {
+                "use the same number of rows in all tables of the example".to_string()
+            }
+            DuplicateKey { .. } => "remove or rename one of the definitions".to_string(),
+            MissingGiven { .. } => "add a 'Given `<key>` as:' paragraph".to_string(),
+            MissingWhen { .. } => "add a 'When `<key>` is:' paragraph".to_string(),
+            MissingThen { .. } => "add a 'Then `<key>` is:' paragraph".to_string(),
+        };
+        Some(Box::new(help))
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        use reader::Error::*;
+        let label = |text: &str, pos: &Pos| {
+            let (offset, len) = pos.span().unwrap_or_default();
+            LabeledSpan::new(Some(text.to_string()), offset, len)
+        };
+        let labels = match self {
+            ExpectedSpecParagraph { pos, .. } => vec![label("malformed paragraph", pos)],
+            ExpectedCode { pos, at } => vec![
+                label("spec paragraph", pos),
+                label("expected code block here", at),
+            ],
+            ExpectedTable { pos } => vec![label("spec paragraph", pos)],
+            ExpectedTableCell { pos } => vec![label("table cell", pos)],
+            MismatchedRows { pos } => vec![label("spec paragraph", pos)],
+            DuplicateKey { first, second, .. } => vec![
+                label("first defined here", first),
+                label("redefined here", second),
+            ],
+            MissingGiven { pos } | MissingWhen { pos } | MissingThen { pos } => {
+                vec![label("section", pos)]
+            }
+        };
+        Some(Box::new(labels.into_iter()))
+    }
+}
+
+impl<H: Debug + Display> Diagnostic for Error<H> {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        let code = match self {
+            Error::SpecReader { error, .. } => return error.code(),
+            Error::MdWriter { .. } => "spectest::md_writer",
+            Error::Handler { .. } => "spectest::handler",
+            Error::Failure { .. } => "spectest::failure",
+            Error::UnexpectedKey { .. } => "spectest::unexpected_key",
+            Error::Timeout { .. } => "spectest::timeout",
+            Error::UnexpectedPass { .. } => "spectest::unexpected_pass",
+            Error::MultilineCell { .. } => "spectest::multiline_cell",
+            Error::IO(_) => "spectest::io",
+            Error::Unknown(_) => "spectest::unknown",
+        };
+        Some(Box::new(code))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        match self {
+            Error::SpecReader { error, .. } => error.help(),
+            Error::Failure { .. } => {
+                let help = "run with `REWRITE_SPECS=1` in order to accept the actual value";
+                Some(Box::new(help))
+            }
+            Error::UnexpectedPass { .. } => {
+                let help = "remove the `(failing)` suffix if the bug has been fixed";
+                Some(Box::new(help))
+            }
+            _ => None,
+        }
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        match self {
+            Error::SpecReader { error, .. } => error.labels(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use miette::{NarratableReportHandler, Report};
+
+    use super::*;
+    use crate::core::examples::*;
+    use crate::core::{process, Example, Handler};
+
+    #[test]
+    fn test_diagnostic() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, _example: &mut Example) -> Result<(), Self::Error> {
+                Ok(())
+            }
+        }
+
+        let spec = indoc::indoc! {r"
+            ## Example: (1)
+
+            When pipeline is:
+        "};
+        let path = write_spec(spec)?;
+
+        let err = process(&path, &mut TestHandler).expect_err("malformed spec");
+        assert_eq!(
+            err.code().map(|code| code.to_string()).as_deref(),
+            Some("spectest::expected_spec_paragraph")
+        );
+
+        let source = MietteMdSource::new("spec.md", spec.to_string());
+        let report = Report::new(err).with_source_code(source);
+        let mut out = String::new();
+        NarratableReportHandler::new()
+            .render_report(&mut out, report.as_ref())
+            .expect("rendered report");
+
+        let exp = indoc::indoc! {r"
+            snippet line 3: When pipeline is:
+                label at line 3, columns 1 to 17: malformed paragraph
+            diagnostic help: spec paragraphs must have the form 'When `<key>` is:'
+            diagnostic code: spectest::expected_spec_paragraph
+        "};
+        let header = format!("reader error in `{}`: expected 'When", path.display());
+        assert!(out.starts_with(&header), "unexpected report:\n{out}");
+        assert!(out.ends_with(exp), "unexpected report:\n{out}");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+//! An object-safe version of the [`Handler`] trait.
+
+use std::fmt::Display;
+use std::path::Path;
+
+use super::{process, rewrite, Background, Error, Example, Handler, RewriteStats};
+
+/// An object-safe version of [`Handler`] whose methods report errors as
+/// [`String`] values.
+///
+/// Every [`Handler`] whose error type implements [`Display`] is also a
+/// [`DynHandler`], so handlers of different types can be stored in the same
+/// collection as `Box<dyn DynHandler>` values. `Box<dyn DynHandler>` and
+/// `&mut dyn DynHandler` in turn implement [`Handler`] with `Error = String`
+/// (see also [`process_dyn`] and [`rewrite_dyn`]):
+///
+/// ```no_run
+/// use spectest::{DynHandler, Example, Handler};
+///
+/// struct NoopHandler;
+///
+/// impl Handler for NoopHandler {
+///     type Error = String;
+///
+///     fn example(&mut self, _example: &mut Example) -> Result<(), Self::Error> {
+///         Ok(())
+///     }
+/// }
+///
+/// let mut registry: Vec<(&str, Box<dyn DynHandler>)> = vec![
+///     ("tests/noop.md", Box::new(NoopHandler)),
+/// ];
+/// for (path, handler) in registry.iter_mut() {
+///     spectest::run(path, handler);
+/// }
+/// ```
+pub trait DynHandler {
+    fn enter_dyn(&mut self, background: &Background) -> Result<(), String>;
+
+    fn leave_dyn(&mut self, background: &Background) -> Result<(), String>;
+
+    fn example_dyn(&mut self, example: &mut Example) -> Result<(), String>;
+
+    fn before_file_dyn(&mut self, path: &Path) -> Result<(), String>;
+
+    fn after_file_dyn(&mut self, path: &Path) -> Result<(), String>;
+
+    fn reset_dyn(&mut self);
+}
+
+impl<H> DynHandler for H
+where
+    H: Handler,
+    H::Error: Display,
+{
+    fn enter_dyn(&mut self, background: &Background) -> Result<(), String> {
+        self.enter(background).map_err(|err| err.to_string())
+    }
+
+    fn leave_dyn(&mut self, background: &Background) -> Result<(), String> {
+        self.leave(background).map_err(|err| err.to_string())
+    }
+
+    fn example_dyn(&mut self, example: &mut Example) -> Result<(), String> {
+        self.example(example).map_err(|err| err.to_string())
+    }
+
+    fn before_file_dyn(&mut self, path: &Path) -> Result<(), String> {
+        self.before_file(path).map_err(|err| err.to_string())
+    }
+
+    fn after_file_dyn(&mut self, path: &Path) -> Result<(), String> {
+        self.after_file(path).map_err(|err| err.to_string())
+    }
+
+    fn reset_dyn(&mut self) {
+        self.reset();
+    }
+}
+
+/// Implement [`Handler`] with `Error = String` for a pointer to a
+/// [`DynHandler`] trait object.
+macro_rules! impl_handler {
+    ($handler:ty) => {
+        impl Handler for $handler {
+            type Error = String;
+
+            fn enter(&mut self, background: &Background) -> Result<(), Self::Error> {
+                (**self).enter_dyn(background)
+            }
+
+            fn leave(&mut self, background: &Background) -> Result<(), Self::Error> {
+                (**self).leave_dyn(background)
+            }
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                (**self).example_dyn(example)
+            }
+
+            fn before_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+                (**self).before_file_dyn(path)
+            }
+
+            fn after_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+                (**self).after_file_dyn(path)
+            }
+
+            fn reset(&mut self) {
+                (**self).reset_dyn();
+            }
+        }
+    };
+}
+
+impl_handler!(Box<dyn DynHandler + '_>);
+impl_handler!(&mut (dyn DynHandler + '_));
+
+/// A version of [`process`] for a type-erased `handler`.
+pub fn process_dyn<P: AsRef<Path>>(
+    path: P,
+    mut handler: &mut dyn DynHandler,
+) -> Result<(), Error<String>> {
+    process(path, &mut handler)
+}
+
+/// A version of [`rewrite`] for a type-erased `handler`.
+pub fn rewrite_dyn<P: AsRef<Path>>(
+    path: P,
+    mut handler: &mut dyn DynHandler,
+) -> Result<RewriteStats, Error<String>> {
+    rewrite(path, &mut handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::examples::*;
+    use super::*;
+    use crate::fixtures::{EchoHandler, IdentityHandler};
+
+    #[test]
+    fn test_dyn_handler() -> std::io::Result<()> {
+        let spec = indoc::indoc! {r"
+            ## Example: Identity
+
+            When `output` is:
+
+            ```
+            hello
+            ```
+
+            Then `output` is:
+
+            ```
+            hello
+            ```
+        "};
+        let path = write_spec(spec)?;
+
+        let mut handlers: Vec<Box<dyn DynHandler>> =
+            vec![Box::new(IdentityHandler), Box::new(EchoHandler)];
+        for handler in handlers.iter_mut() {
+            process(&path, handler).expect("`process` call completes cleanly");
+        }
+
+        let mut handler = IdentityHandler;
+        let handler: &mut dyn DynHandler = &mut handler;
+        process_dyn(&path, handler).expect("`process_dyn` call completes cleanly");
+        let stats = rewrite_dyn(&path, handler).expect("`rewrite_dyn` call completes cleanly");
+        assert_eq!(stats.unchanged, 1);
+
+        Ok(())
+    }
+}
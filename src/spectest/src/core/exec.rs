@@ -0,0 +1,1001 @@
+//! The section-processing loop shared by all sync and async variants of
+//! [`process`](super::process) and [`rewrite`](super::rewrite).
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use pulldown_cmark::{CowStr, HeadingLevel};
+
+use super::{
+    find_tags, sections, AsyncHandler, Background, Comparator, Config, Error, Example, ExampleInfo,
+    ExampleOutcome, Handler2, RewriteStats, RunStats, Section, StatsError,
+};
+use crate::md;
+
+/// The way [`execute`] treats the `then` values produced by a handler.
+pub(super) enum Mode<'c> {
+    /// Compare the values with the expected ones using the given comparator.
+    Process {
+        comparator: &'c dyn Comparator,
+        /// See [`Config::interpolate`].
+        interpolate: bool,
+        /// See [`Config::keep_going`].
+        keep_going: bool,
+        /// See [`Config::warn_unused_when`].
+        warn_unused_when: bool,
+    },
+    /// Replace the expected values that don't match the actual ones under the
+    /// given comparator and write the spec file if any changed.
+    Rewrite {
+        config: &'c Config,
+        comparator: &'c dyn Comparator,
+    },
+}
+
+impl<'c> Mode<'c> {
+    /// Compare the values using the given `comparator` without interpolation.
+    pub(super) fn process(comparator: &'c dyn Comparator) -> Self {
+        Mode::Process {
+            comparator,
+            interpolate: false,
+            keep_going: false,
+            warn_unused_when: false,
+        }
+    }
+
+    /// Compare the values using the comparator and flags of the given
+    /// `config`.
+    pub(super) fn process_with(config: &Config) -> Self {
+        Mode::Process {
+            comparator: config.comparator(),
+            interpolate: config.interpolate,
+            keep_going: config.keep_going,
+            warn_unused_when: config.warn_unused_when,
+        }
+    }
+
+    /// Rewrite using the given `config` and its comparator.
+    pub(super) fn rewrite(config: &'c Config) -> Self {
+        Mode::Rewrite {
+            config,
+            comparator: config.comparator(),
+        }
+    }
+
+    /// Whether `${key}` references in `when` values are replaced with the
+    /// `given` values of the active backgrounds (see [`Config::interpolate`]).
+    fn interpolate(&self) -> bool {
+        match self {
+            Mode::Process { interpolate, .. } => *interpolate,
+            Mode::Rewrite { config, .. } => config.interpolate,
+        }
+    }
+
+    /// Whether examples are still executed after one of them fails (see
+    /// [`Config::keep_going`]).
+    fn keep_going(&self) -> bool {
+        match self {
+            Mode::Process { keep_going, .. } => *keep_going,
+            Mode::Rewrite { config, .. } => config.keep_going,
+        }
+    }
+
+    /// Whether unused `when` keys are reported after each passing example
+    /// (see [`Config::warn_unused_when`]).
+    fn warn_unused_when(&self) -> bool {
+        match self {
+            Mode::Process {
+                warn_unused_when, ..
+            } => *warn_unused_when,
+            Mode::Rewrite { config, .. } => config.warn_unused_when,
+        }
+    }
+}
+
+/// The callback that [`execute`] calls after each [`Example`] completes (or is
+/// skipped), with the error that failed the example (if any).
+pub(super) type OnExample<'f, E> = dyn FnMut(&ExampleInfo, ExampleOutcome, Option<&Error<E>>) + 'f;
+
+/// A common interface for driving a [`Handler2`] or an [`AsyncHandler`] from
+/// [`execute`].
+///
+/// Calls to a [`SyncExecutor`] never suspend, so the future returned by
+/// [`execute`] can be polled to completion with [`block_on`].
+pub(super) trait Executor {
+    type Error;
+
+    async fn enter(
+        &mut self,
+        background: &Background<'_>,
+        active: &[&Background<'_>],
+    ) -> Result<(), Error<Self::Error>>;
+
+    async fn leave(
+        &mut self,
+        background: &Background<'_>,
+        active: &[&Background<'_>],
+    ) -> Result<(), Error<Self::Error>>;
+
+    async fn example(&mut self, example: &mut Example<'_>) -> Result<(), Error<Self::Error>>;
+
+    async fn before_file(&mut self, path: &Path) -> Result<(), Error<Self::Error>>;
+
+    async fn after_file(&mut self, path: &Path) -> Result<(), Error<Self::Error>>;
+}
+
+/// An [`Executor`] for a [`Handler2`].
+pub(super) struct SyncExecutor<'h, H>(pub(super) &'h mut H);
+
+impl<H: Handler2> Executor for SyncExecutor<'_, H> {
+    type Error = H::Error;
+
+    async fn enter(
+        &mut self,
+        background: &Background<'_>,
+        active: &[&Background<'_>],
+    ) -> Result<(), Error<Self::Error>> {
+        self.0.enter(background, active).map_err(Error::background)
+    }
+
+    async fn leave(
+        &mut self,
+        background: &Background<'_>,
+        active: &[&Background<'_>],
+    ) -> Result<(), Error<Self::Error>> {
+        self.0.leave(background, active).map_err(Error::background)
+    }
+
+    async fn example(&mut self, example: &mut Example<'_>) -> Result<(), Error<Self::Error>> {
+        let abort = super::env_flag("SPECTEST_TIMEOUT_ABORT");
+        self.example_with_timeout(example, env_timeout(), abort)
+    }
+
+    async fn before_file(&mut self, path: &Path) -> Result<(), Error<Self::Error>> {
+        self.0.before_file(path).map_err(Error::background)
+    }
+
+    async fn after_file(&mut self, path: &Path) -> Result<(), Error<Self::Error>> {
+        self.0.after_file(path).map_err(Error::background)
+    }
+}
+
+impl<H: Handler2> SyncExecutor<'_, H> {
+    /// Run the handler for the given `example` and fail with an
+    /// [`Error::Timeout`] if the call takes longer than the `timeout` limit.
+    ///
+    /// Synchronous handler calls can't be interrupted, so a watchdog thread
+    /// prints a `TIMEOUT` warning as soon as the limit is exceeded and the
+    /// error is returned once the call completes. If `abort` is set, the
+    /// watchdog aborts the process instead, which also ends all other tests
+    /// that run in it, but doesn't wait for calls that never complete.
+    fn example_with_timeout(
+        &mut self,
+        example: &mut Example<'_>,
+        timeout: Option<Duration>,
+        abort: bool,
+    ) -> Result<(), Error<H::Error>> {
+        let Some(duration) = timeout else {
+            let result = self.0.example(example);
+            return result.map_err(|error| Error::example(error, example));
+        };
+
+        let name = example.name;
+        let started = Instant::now();
+        let result = std::thread::scope(|scope| {
+            let (done, finished) = std::sync::mpsc::channel::<()>();
+            scope.spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(duration) {
+                    let millis = duration.as_millis();
+                    if abort {
+                        eprintln!("TIMEOUT [{millis}ms] {name} is still running, aborting");
+                        std::process::abort();
+                    }
+                    eprintln!("TIMEOUT [{millis}ms] {name} is still running");
+                }
+            });
+            let result = self.0.example(example);
+            drop(done); // Stop the watchdog.
+            result
+        });
+
+        if started.elapsed() > duration {
+            return Err(Error::Timeout {
+                example: name.to_string(),
+                duration,
+            });
+        }
+        result.map_err(|error| Error::example(error, example))
+    }
+}
+
+/// An [`Executor`] for an [`AsyncHandler`] that enforces the
+/// [`AsyncHandler::timeout`] limit (or the `SPECTEST_TIMEOUT_MS` limit if the
+/// handler doesn't define one).
+pub(super) struct AsyncExecutor<'h, H>(pub(super) &'h mut H);
+
+impl<H: AsyncHandler> Executor for AsyncExecutor<'_, H> {
+    type Error = H::Error;
+
+    async fn enter(
+        &mut self,
+        background: &Background<'_>,
+        _active: &[&Background<'_>],
+    ) -> Result<(), Error<Self::Error>> {
+        self.0.enter(background).await.map_err(Error::background)
+    }
+
+    async fn leave(
+        &mut self,
+        background: &Background<'_>,
+        _active: &[&Background<'_>],
+    ) -> Result<(), Error<Self::Error>> {
+        self.0.leave(background).await.map_err(Error::background)
+    }
+
+    async fn example(&mut self, example: &mut Example<'_>) -> Result<(), Error<Self::Error>> {
+        let Some(duration) = self.0.timeout().or_else(env_timeout) else {
+            let result = self.0.example(example).await;
+            return result.map_err(|error| Error::example(error, example));
+        };
+        let result = {
+            let mut call = pin!(self.0.example(example));
+            let mut timer = Timer::new(duration);
+            let result = std::future::poll_fn(|cx| match call.as_mut().poll(cx) {
+                Poll::Ready(result) => Poll::Ready(Some(result)),
+                Poll::Pending => timer.poll(cx).map(|()| None),
+            });
+            result.await
+        };
+        match result {
+            Some(result) => result.map_err(|error| Error::example(error, example)),
+            None => Err(Error::Timeout {
+                example: example.name.to_string(),
+                duration,
+            }),
+        }
+    }
+
+    async fn before_file(&mut self, path: &Path) -> Result<(), Error<Self::Error>> {
+        self.0.before_file(path).await.map_err(Error::background)
+    }
+
+    async fn after_file(&mut self, path: &Path) -> Result<(), Error<Self::Error>> {
+        self.0.after_file(path).await.map_err(Error::background)
+    }
+}
+
+/// Process or rewrite (depending on the `mode`) the spec file at the given
+/// `path` using the given `exec`.
+///
+/// This is the section-processing loop shared by all sync and async variants
+/// of [`process`] and [`rewrite`].
+pub(super) async fn execute<P, X>(
+    path: P,
+    exec: X,
+    mode: Mode<'_>,
+    on_example: &mut OnExample<'_, X::Error>,
+) -> Result<(RunStats, RewriteStats), StatsError<X::Error>>
+where
+    P: AsRef<Path>,
+    X: Executor,
+{
+    #[cfg(feature = "tracing")]
+    let span = process_span(path.as_ref());
+
+    let future = execute_file(path.as_ref(), None, exec, mode, on_example);
+
+    #[cfg(feature = "tracing")]
+    let future = tracing::Instrument::instrument(future, span);
+
+    future.await
+}
+
+/// A version of [`execute`] that processes the given Markdown `source` instead
+/// of reading it from a file. The `name` is used in place of the spec file path
+/// in errors and [`Handler::before_file`](super::Handler::before_file) calls.
+///
+/// Only [`Mode::Process`] is supported, as there is no file to rewrite.
+pub(super) async fn execute_str<X: Executor>(
+    name: &Path,
+    source: &str,
+    exec: X,
+    comparator: &dyn Comparator,
+) -> Result<RunStats, StatsError<X::Error>> {
+    #[cfg(feature = "tracing")]
+    let span = process_span(name);
+
+    let mode = Mode::process(comparator);
+    let on_example = &mut |_: &ExampleInfo, _, _: Option<&_>| ();
+    let future = execute_file(name, Some(source), exec, mode, on_example);
+
+    #[cfg(feature = "tracing")]
+    let future = tracing::Instrument::instrument(future, span);
+
+    future.await.map(|(stats, _)| stats)
+}
+
+/// Call the `before_file` and `after_file` hooks of the `exec` around
+/// [`execute_sections`].
+///
+/// The `after_file` hook is called even if processing the sections failed, in
+/// which case the processing error takes precedence over an `after_file`
+/// error. Errors are returned with the [`RunStats`] collected up to the
+/// failure.
+async fn execute_file<X: Executor>(
+    path: &Path,
+    source: Option<&str>,
+    mut exec: X,
+    mode: Mode<'_>,
+    on_example: &mut OnExample<'_, X::Error>,
+) -> Result<(RunStats, RewriteStats), StatsError<X::Error>> {
+    let mut stats = RunStats::default();
+    if let Err(error) = exec.before_file(path).await {
+        let error = Box::new(error);
+        return Err(StatsError { stats, error });
+    }
+    let result = execute_sections(path, source, &mut exec, mode, on_example, &mut stats).await;
+    let after_file = exec.after_file(path).await;
+    match result.and_then(|rewrite_stats| after_file.map(|()| rewrite_stats)) {
+        Ok(rewrite_stats) => Ok((stats, rewrite_stats)),
+        Err(error) => Err(StatsError {
+            stats,
+            error: Box::new(error),
+        }),
+    }
+}
+
+async fn execute_sections<X: Executor>(
+    path: &Path,
+    source: Option<&str>,
+    exec: &mut X,
+    mode: Mode<'_>,
+    on_example: &mut OnExample<'_, X::Error>,
+    stats: &mut RunStats,
+) -> Result<RewriteStats, Error<X::Error>> {
+    // Read Markdown source into a String buffer and expand its include
+    // directives (unless it was given inline).
+    let expanded = match source {
+        Some(source) => md::ExpandedSource::from(source),
+        None => md::ExpandedSource::read(path)?,
+    };
+    let md_source = expanded.source();
+    let path_buf = || path.to_path_buf();
+
+    // Parse Markdown source.
+    let mut md_doc = md::MdDocument::from_expanded(&expanded);
+
+    const EMPTY_VEC: Vec<Background<'_>> = Vec::<Background>::new();
+    let mut active = [EMPTY_VEC; HeadingLevel::H6 as usize];
+
+    let mut rewrite_stats = RewriteStats::default();
+    let filter = env_filter();
+    let tags = env_tags();
+    let slow_threshold = env_slow_threshold();
+
+    // Process the sections in an async block, so that the backgrounds that are
+    // still active are left below even if processing fails early.
+    let result = async {
+        // The first failure of an example if `mode.keep_going()` is set.
+        let mut first_error = None;
+
+        // Iterate over spec-style sections in the parsed input.
+        for section in sections(&mut md_doc) {
+            let Ok(section) = section else {
+                let error = section.unwrap_err().map_span(md_source);
+                return Err(Error::SpecReader {
+                    path: path_buf(),
+                    error,
+                });
+            };
+
+            match section {
+                Section::Background(mut background) => {
+                    let level = background.level as usize - 1;
+                    // Leave the deeper backgrounds (innermost first) before
+                    // the prior background at the same level.
+                    for level in (level + 1..active.len()).rev() {
+                        while let Some(background) = active[level].pop() {
+                            exec.leave(&background, &stack(&active)).await?;
+                        }
+                    }
+                    if let Some(prior) = active[level].pop() {
+                        exec.leave(&prior, &stack(&active)).await?;
+                        background.merge(prior);
+                    }
+                    exec.enter(&background, &stack(&active)).await?;
+                    active[level].push(background);
+                }
+                section @ (Section::Example(_) | Section::Tabular(_)) => {
+                    let tabular = matches!(section, Section::Tabular(_));
+                    for (name, example) in examples(section) {
+                        let name = name.as_str();
+                        let Example {
+                            level,
+                            heading,
+                            when,
+                            when_hints,
+                            mut then,
+                            then_hints,
+                            optional_then,
+                            notes,
+                            ..
+                        } = example;
+
+                        let mut info = ExampleInfo {
+                            name,
+                            index: stats.total,
+                            duration: None,
+                        };
+
+                        stats.total += 1;
+                        if is_skipped(name, filter.as_deref(), tags.as_deref()) {
+                            stats.skipped += 1;
+                            on_example(&info, ExampleOutcome::Skipped, None);
+                            continue;
+                        }
+
+                        // Substitute `${key}` references in the `when` values.
+                        let interpolated = mode.interpolate().then(|| {
+                            let active = stack(&active);
+                            let when = when.iter();
+                            when.map(|(k, v)| (*k, interpolate(v, &active)))
+                                .collect::<HashMap<_, _>>()
+                        });
+                        let when = match &interpolated {
+                            Some(when) => when.iter().map(|(k, v)| (*k, v.as_str())).collect(),
+                            None => when,
+                        };
+
+                        let mut example = Example {
+                            level,
+                            name,
+                            heading,
+                            path: Some(path),
+                            when,
+                            when_hints,
+                            then: prefill(&then, &optional_then),
+                            then_hints,
+                            optional_then: optional_then.clone(),
+                            notes,
+                        };
+
+                        #[cfg(feature = "tracing")]
+                        example_started(name);
+                        let started = Instant::now();
+                        let result = exec.example(&mut example).await;
+                        let duration = started.elapsed();
+                        #[cfg(feature = "tracing")]
+                        example_finished(name, duration);
+
+                        info.duration = Some(duration);
+                        stats.elapsed += duration;
+                        if slow_threshold.is_some_and(|threshold| duration > threshold) {
+                            stats.slow += 1;
+                            let secs = duration.as_secs_f64();
+                            eprintln!("SLOW [{secs:.1}s] {name} in {}", path.display());
+                        }
+
+                        let checked = 'check: {
+                            // Examples marked as `(failing)` pass if the
+                            // handler fails or if any `then` value differs.
+                            // Their `then` values are never rewritten.
+                            if is_failing(name) {
+                                let comparator = match mode {
+                                    Mode::Process { comparator, .. } => comparator,
+                                    Mode::Rewrite { comparator, .. } => comparator,
+                                };
+                                let failed = result.is_err()
+                                    || !then_matches(
+                                        &then,
+                                        &optional_then,
+                                        example.then,
+                                        comparator,
+                                    );
+                                if !failed {
+                                    break 'check Err(Error::UnexpectedPass {
+                                        example: name.to_string(),
+                                    });
+                                }
+                                if let Mode::Rewrite { .. } = mode {
+                                    rewrite_stats.unchanged += 1;
+                                }
+                                break 'check Ok(());
+                            }
+
+                            if let Err(error) = result {
+                                break 'check Err(error);
+                            }
+
+                            let mut changed = false;
+                            for (key, expect) in then.iter_mut() {
+                                if optional_then.contains(key) && !example.then.contains_key(key) {
+                                    continue; // The handler didn't set an optional value.
+                                }
+                                let actual = example.then.remove(key).expect("actual");
+                                match mode {
+                                    Mode::Process { comparator, .. } => {
+                                        if !comparator.matches(key, expect, &actual) {
+                                            break 'check Err(Error::Failure {
+                                                key: key.to_string(),
+                                                example: name.to_string(),
+                                                expected: expect.to_string(),
+                                                actual,
+                                            });
+                                        }
+                                    }
+                                    Mode::Rewrite { comparator, .. } => {
+                                        // Keep values that already match, such
+                                        // as patterns checked by a
+                                        // `RegexComparator`.
+                                        if !comparator.matches(key, expect, &actual) {
+                                            // Values of tabular examples are
+                                            // written into single-line table
+                                            // cells.
+                                            let actual = match tabular {
+                                                true => {
+                                                    actual.strip_suffix('\n').unwrap_or(&actual)
+                                                }
+                                                false => &actual,
+                                            };
+                                            if tabular && actual.contains('\n') {
+                                                break 'check Err(Error::MultilineCell {
+                                                    key: key.to_string(),
+                                                    example: name.to_string(),
+                                                });
+                                            }
+                                            **expect = CowStr::from(actual.to_string());
+                                            changed = true;
+                                        }
+                                    }
+                                }
+                            }
+
+                            // Reject keys set by the handler that are not in
+                            // the spec.
+                            if let Some(key) = example.then.keys().min() {
+                                break 'check Err(Error::UnexpectedKey {
+                                    key: key.to_string(),
+                                    example: name.to_string(),
+                                });
+                            }
+
+                            if let Mode::Rewrite { .. } = mode {
+                                if changed {
+                                    rewrite_stats.changed += 1;
+                                } else {
+                                    rewrite_stats.unchanged += 1;
+                                }
+                            }
+                            Ok(())
+                        };
+
+                        match checked {
+                            Ok(()) => {
+                                if mode.warn_unused_when() {
+                                    example.when.warn_unused(name);
+                                }
+                                stats.passed += 1;
+                                on_example(&info, ExampleOutcome::Passed, None);
+                            }
+                            Err(error) => {
+                                stats.failed += 1;
+                                on_example(&info, ExampleOutcome::Failed, Some(&error));
+                                if !mode.keep_going() {
+                                    return Err(error);
+                                }
+                                first_error.get_or_insert(error);
+                            }
+                        }
+                    }
+                }
+                Section::Raw(section) => {
+                    for level in (raw_scope(section.level)..active.len()).rev() {
+                        while let Some(background) = active[level].pop() {
+                            exec.leave(&background, &stack(&active)).await?;
+                        }
+                    }
+                }
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+    .await;
+
+    // Leave all backgrounds that are still active at the end of the file (or
+    // at the point of failure). Processing errors take precedence.
+    let left = leave_all(exec, &mut active).await;
+    result?;
+    left?;
+
+    // Leave the file untouched if no `then` values were updated or if the
+    // updated document is identical to the current file contents.
+    if let (Mode::Rewrite { config, .. }, true) = (mode, rewrite_stats.changed > 0) {
+        let md_writer_error = |error| Error::MdWriter {
+            path: path_buf(),
+            error,
+        };
+        let md_output = md_doc.write_to_string().map_err(md_writer_error)?;
+        if md_output != expanded.original() {
+            if config.backup {
+                std::fs::write(backup_path(path), expanded.original().as_bytes())?;
+            }
+            md::writer::write_locked(path, md_output.as_bytes()).map_err(md_writer_error)?;
+            rewrite_stats.written = true;
+        }
+    }
+
+    Ok(rewrite_stats)
+}
+
+/// Leave all `active` backgrounds in reverse order.
+///
+/// All backgrounds are left even if one of the `leave` calls fails, in which
+/// case the first error is returned.
+async fn leave_all<X: Executor>(
+    exec: &mut X,
+    active: &mut [Vec<Background<'_>>],
+) -> Result<(), Error<X::Error>> {
+    let mut result = Ok(());
+    for level in (0..active.len()).rev() {
+        while let Some(background) = active[level].pop() {
+            let left = exec.leave(&background, &stack(active)).await;
+            if result.is_ok() {
+                result = left;
+            }
+        }
+    }
+    result
+}
+
+/// Poll the given `future` to completion on the current thread.
+///
+/// Only suitable for futures that never suspend, such as the ones returned by
+/// [`execute`] for a [`SyncExecutor`].
+pub(super) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut cx = Context::from_waker(Waker::noop());
+    match pin!(future).poll(&mut cx) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("synchronous handlers never suspend"),
+    }
+}
+
+// Helpers
+// =======
+
+/// A runtime-agnostic timer for enforcing [`AsyncHandler::timeout`] limits.
+///
+/// A helper thread wakes the task once the deadline has passed, so the timer
+/// works with any executor. The thread exits early if the timer is dropped.
+struct Timer {
+    deadline: Instant,
+    /// The waker of the task that polled the timer last.
+    waker: Arc<Mutex<Option<Waker>>>,
+    thread: Option<std::thread::Thread>,
+}
+
+impl Timer {
+    fn new(duration: Duration) -> Self {
+        Self {
+            deadline: Instant::now() + duration,
+            waker: Arc::default(),
+            thread: None,
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        let mut waker = self.waker.lock().expect("timer lock");
+        *waker = Some(cx.waker().clone());
+        drop(waker);
+        if self.thread.is_none() {
+            let deadline = self.deadline;
+            let waker = Arc::downgrade(&self.waker);
+            let thread = std::thread::spawn(move || loop {
+                // Stop if the timer was dropped in the meantime.
+                let Some(waker) = waker.upgrade() else { break };
+                let now = Instant::now();
+                if now >= deadline {
+                    let waker = waker.lock().expect("timer lock").take();
+                    waker.into_iter().for_each(Waker::wake);
+                    break;
+                }
+                drop(waker);
+                std::thread::park_timeout(deadline - now);
+            });
+            self.thread = Some(thread.thread().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            // Release the waker before unparking, so the thread stops.
+            self.waker = Arc::default();
+            thread.unpark();
+        }
+    }
+}
+
+/// The path of the backup copy of the spec file at `path`.
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(".spectest.bak");
+    PathBuf::from(backup_path)
+}
+
+/// Pre-fill the `then` values passed to a handler with the expected values of
+/// all keys that are not optional.
+fn prefill<'a>(
+    then: &HashMap<&'a str, &'a mut CowStr<'_>>,
+    optional_then: &HashSet<&str>,
+) -> HashMap<&'a str, String> {
+    let then = then.iter().filter(|(k, _)| !optional_then.contains(*k));
+    then.map(|(k, v)| (*k, v.to_string())).collect()
+}
+
+/// Check whether the `actual` values set by a handler match the expected
+/// `then` values and don't contain any keys that are not in the spec.
+fn then_matches<'a>(
+    then: &HashMap<&'a str, &mut CowStr<'_>>,
+    optional_then: &HashSet<&str>,
+    mut actual: HashMap<&'a str, String>,
+    comparator: &dyn Comparator,
+) -> bool {
+    let matches = then.iter().all(|(key, expect)| match actual.remove(key) {
+        Some(actual) => comparator.matches(key, expect, &actual),
+        None => optional_then.contains(key),
+    });
+    matches && actual.is_empty()
+}
+
+/// Split an [`Example`] or a [`Tabular`] section into named examples.
+fn examples<'a, 'input>(
+    section: Section<'a, 'input>,
+) -> Vec<(String, Example<'a, &'a mut CowStr<'input>>)> {
+    match section {
+        Section::Example(example) => vec![(example.name.to_string(), example)],
+        Section::Tabular(tabular) => tabular.into_examples(),
+        Section::Background(_) | Section::Raw(_) => vec![],
+    }
+}
+
+/// Replace the `${key}` references in `value` with the `given` value of the
+/// innermost `active` background that defines `key`, without its trailing
+/// newline. References to undefined keys are left as they are.
+fn interpolate(value: &str, active: &[&Background<'_>]) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let (reference, after) = rest[start..].split_at(len + 1);
+        let key = &reference[2..len];
+        let given = active.iter().rev().find_map(|bg| bg.given.get(key));
+        output.push_str(&rest[..start]);
+        match given {
+            Some(given) => output.push_str(given.strip_suffix('\n').unwrap_or(given)),
+            None => output.push_str(reference),
+        }
+        rest = after;
+    }
+    output.push_str(rest);
+    output
+}
+
+/// The index of the first `active` entry that goes out of scope when a
+/// [`Raw`] section with the given `level` is encountered.
+///
+/// [`Background`] sections at level H1 have a file-wide scope and are never
+/// left by a [`Raw`] section.
+fn raw_scope(level: HeadingLevel) -> usize {
+    usize::max(level as usize - 1, 1)
+}
+
+/// Flatten the `active` backgrounds into a stack ordered from the outermost to
+/// the innermost [`Background`].
+fn stack<'a, 'b>(active: &'b [Vec<Background<'a>>]) -> Vec<&'b Background<'a>> {
+    active.iter().flatten().collect()
+}
+
+/// Create the span that wraps the processing of the spec file at `path`.
+#[cfg(feature = "tracing")]
+fn process_span(path: &Path) -> tracing::Span {
+    tracing::info_span!("spectest::process", path = %path.display())
+}
+
+/// Emit a `tracing` event before running the example with the given `name`.
+#[cfg(feature = "tracing")]
+fn example_started(name: &str) {
+    tracing::debug!(example = name, "running example");
+}
+
+/// Emit a `tracing` event after running the example with the given `name`.
+#[cfg(feature = "tracing")]
+fn example_finished(name: &str, duration: Duration) {
+    tracing::debug!(example = name, ?duration, "example finished");
+}
+
+/// Read the lowercase `SPECTEST_FILTER` value (if set and not empty).
+fn env_filter() -> Option<String> {
+    let filter = std::env::var("SPECTEST_FILTER").ok()?;
+    (!filter.is_empty()).then(|| filter.to_lowercase())
+}
+
+/// Read the `SPECTEST_SLOW_THRESHOLD` value in milliseconds (if set and
+/// valid).
+fn env_slow_threshold() -> Option<Duration> {
+    let threshold = std::env::var("SPECTEST_SLOW_THRESHOLD").ok()?;
+    threshold.trim().parse().ok().map(Duration::from_millis)
+}
+
+/// Read the `SPECTEST_TIMEOUT_MS` value in milliseconds (if set and valid).
+fn env_timeout() -> Option<Duration> {
+    let timeout = std::env::var("SPECTEST_TIMEOUT_MS").ok()?;
+    timeout.trim().parse().ok().map(Duration::from_millis)
+}
+
+/// Read the comma-separated `SPECTEST_TAGS` value (if set and not empty).
+fn env_tags() -> Option<Vec<String>> {
+    let tags = std::env::var("SPECTEST_TAGS").ok()?;
+    let tags = tags
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    (!tags.is_empty()).then_some(tags)
+}
+
+/// Check whether the [`Example`] with the given `name` is expected to fail.
+fn is_failing(name: &str) -> bool {
+    name.ends_with("(failing)")
+}
+
+/// Check whether the [`Example`] with the given `name` should be skipped.
+///
+/// Examples are skipped if their name doesn't contain the (lowercase) `filter`
+/// substring, if none of their tags is contained in `tags`, or if their name
+/// ends with `(ignored)`.
+fn is_skipped(name: &str, filter: Option<&str>, tags: Option<&[String]>) -> bool {
+    let filtered = filter.is_some_and(|filter| !name.to_lowercase().contains(filter));
+    let untagged = tags.is_some_and(|tags| {
+        !find_tags(name).any(|(_, tag)| tags.iter().any(|t| t.as_str() == tag))
+    });
+    filtered || untagged || name.ends_with("(ignored)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::TrackedWhen;
+
+    #[test]
+    fn test_is_skipped() {
+        assert!(!is_skipped("Example: Simple queries", None, None));
+        assert!(!is_skipped("Example: Simple queries", Some("simple"), None));
+        assert!(is_skipped("Example: Simple queries", Some("complex"), None));
+        assert!(is_skipped("Example: Simple queries (ignored)", None, None));
+        assert!(is_skipped(
+            "Example: Simple queries (ignored)",
+            Some("simple"),
+            None
+        ));
+
+        let tags = ["slow".to_string(), "unit".to_string()];
+        assert!(!is_skipped(
+            "Example: Queries [db][slow]",
+            None,
+            Some(&tags)
+        ));
+        assert!(is_skipped("Example: Queries [db]", None, Some(&tags)));
+        assert!(is_skipped("Example: Queries", None, Some(&tags)));
+        assert!(is_skipped("Example: Queries [row 1]", None, Some(&tags)));
+        assert!(is_skipped(
+            "Example: Queries [slow] (ignored)",
+            None,
+            Some(&tags)
+        ));
+    }
+
+    #[test]
+    fn test_example_timeout() {
+        struct SleepHandler;
+
+        impl crate::Handler for SleepHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                let millis = example.when["sleep"].parse().map_err(|_| "invalid sleep")?;
+                std::thread::sleep(Duration::from_millis(millis));
+                Ok(())
+            }
+        }
+
+        let example = |sleep: &str, timeout: Option<u64>| {
+            let mut example = Example {
+                level: HeadingLevel::H2,
+                name: "Example: Sleep",
+                heading: String::from("Example: Sleep"),
+                path: None,
+                when: HashMap::from([("sleep", sleep)]).into(),
+                when_hints: HashMap::new(),
+                then: HashMap::new(),
+                then_hints: HashMap::new(),
+                optional_then: HashSet::new(),
+                notes: None,
+            };
+            let timeout = timeout.map(Duration::from_millis);
+            SyncExecutor(&mut SleepHandler).example_with_timeout(&mut example, timeout, false)
+        };
+
+        assert!(example("0", None).is_ok());
+        assert!(example("0", Some(1000)).is_ok());
+        assert!(matches!(
+            example("x", Some(1000)),
+            Err(Error::Handler { .. })
+        ));
+        let result = example("50", Some(10));
+        assert!(
+            matches!(result, Err(Error::Timeout { duration, .. }) if duration.as_millis() == 10)
+        );
+    }
+
+    #[test]
+    fn test_example_timeout_abort() {
+        struct HangHandler;
+
+        impl crate::Handler for HangHandler {
+            type Error = String;
+
+            fn example(&mut self, _example: &mut Example) -> Result<(), Self::Error> {
+                loop {
+                    std::thread::park();
+                }
+            }
+        }
+
+        // The hanging example runs in a child process because it aborts it.
+        if std::env::var_os("SPECTEST_TEST_HANG").is_some() {
+            let mut example = Example {
+                level: HeadingLevel::H2,
+                name: "Example: Hang",
+                heading: String::from("Example: Hang"),
+                path: None,
+                when: TrackedWhen::default(),
+                when_hints: HashMap::new(),
+                then: HashMap::new(),
+                then_hints: HashMap::new(),
+                optional_then: HashSet::new(),
+                notes: None,
+            };
+            let timeout = Some(Duration::from_millis(10));
+            let _ =
+                SyncExecutor(&mut HangHandler).example_with_timeout(&mut example, timeout, true);
+            unreachable!("the watchdog aborts the process");
+        }
+
+        let test = "core::exec::tests::test_example_timeout_abort";
+        let output = std::process::Command::new(std::env::current_exe().expect("test binary"))
+            .args([test, "--exact", "--nocapture"])
+            .env("SPECTEST_TEST_HANG", "1")
+            .output()
+            .expect("child process runs");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "{stderr}");
+        assert!(
+            stderr.contains("TIMEOUT [10ms] Example: Hang is still running, aborting"),
+            "{stderr}"
+        );
+    }
+}
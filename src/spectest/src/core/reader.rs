@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::fs::OpenOptions;
 use std::io::Read;
@@ -11,14 +11,28 @@ use thiserror::Error;
 use crate::md::MdDocument;
 use crate::{event, span, Token, Tokens};
 
-use super::{Background, Example, Raw, Section};
+use super::{Background, Example, Raw, Section, Tabular};
 
 /// Read file contents into a String using a shared lock.
+///
+/// This is the function used by [`process`](super::process) to read spec
+/// files. The shared lock coordinates with the exclusive lock acquired by
+/// [`rewrite`](super::rewrite), so concurrent readers never observe a
+/// partially written spec file.
+///
+/// ```no_run
+/// use spectest::{core::read_to_string, sections, MdDocument};
+///
+/// let source = read_to_string("tests/specs/example.md")?;
+/// let mut doc = MdDocument::from_string(&source);
+/// println!("{} section(s)", sections(&mut doc).count());
+/// # Ok::<(), std::io::Error>(())
+/// ```
 pub fn read_to_string<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
     let mut file_buff = String::new();
 
     let mut file = OpenOptions::new().read(true).open(&path)?;
-    file.lock_shared()?;
+    FileExt::lock_shared(&file)?;
     file.read_to_string(&mut file_buff)?;
 
     Ok(file_buff)
@@ -31,21 +45,126 @@ pub fn read_to_string<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
 ///
 /// The `input` parameter is a mutable reference because [`Example`] sections
 /// bind their `then` values to the original [`CowStr`] event of the backing
-/// document. This allows the [`crate::spec::process`] function to handle
-/// rewrite requests.
+/// document. This allows the [`rewrite`](super::rewrite) function to update
+/// the document in place.
+///
+/// The `Background` and `Example:` heading prefixes and the `Given`, `When`,
+/// `Then`, and `And` paragraph prefixes are matched case-insensitively, so
+/// `## EXAMPLE: Addition` and ``when `x` is:`` are recognized as well.
+///
+/// Errors report byte offsets into the source string. Use
+/// [`Error::map_span`] in order to convert them into line and column
+/// positions.
+///
+/// Iterating over `&mut doc` is equivalent to iterating over
+/// `sections(&mut doc)`.
+///
+/// ```
+/// use spectest::{sections, MdDocument, Section};
+///
+/// let source = "## Background\n\nGiven `x` as:\n\n```\n5\n```\n";
+/// let mut doc = MdDocument::from_string(source);
+///
+/// let mut given = Vec::new();
+/// for section in sections(&mut doc) {
+///     let section = section.map_err(|err| err.map_span(source))?;
+///     if let Section::Background(background) = section {
+///         given.extend(background.given.into_keys());
+///     }
+/// }
+/// assert_eq!(given, vec!["x"]);
+/// # Ok::<(), spectest::core::ReaderError<spectest::core::Pos>>(())
+/// ```
 pub fn sections<'a, 'input>(input: &'a mut MdDocument<'input>) -> SectionsIter<'a, 'input> {
     SectionsIter {
         tokens: &mut input.tokens[..],
     }
 }
 
+impl<'a, 'input> IntoIterator for &'a mut MdDocument<'input> {
+    type Item = Result<Section<'a, 'input>, Error<usize>>;
+    type IntoIter = SectionsIter<'a, 'input>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        sections(self)
+    }
+}
+
 /// An iterator over the [`Sections`](Section) contained in a [`MdDocument`].
 ///
 /// See [`sections`] for details.
+///
+/// Similar to [`std::slice::IterMut`], the iterator holds the mutable slice of
+/// tokens that haven't been consumed yet and splits sections off both of its
+/// ends, so it can also be traversed in reverse order.
 pub struct SectionsIter<'a, 'input> {
     tokens: Tokens<'a, 'input>,
 }
 
+impl<'a, 'input> SectionsIter<'a, 'input> {
+    /// Interpret the tokens of a `section` that starts with a heading.
+    fn section(section: Tokens<'a, 'input>) -> Result<Section<'a, 'input>, Error<usize>> {
+        match SectionType::of(section) {
+            SectionType::Background => Background::try_from(section).map(Section::Background),
+            SectionType::Tabular => Tabular::try_from(section).map(Section::Tabular),
+            SectionType::Example => Example::try_from(section).map(Section::Example),
+            SectionType::Raw => Ok(Section::Raw(Raw::from(section))),
+        }
+    }
+}
+
+/// The type of [`Section`] that a section of a [`MdDocument`] is read as.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum SectionType {
+    Background,
+    Tabular,
+    Example,
+    Raw,
+}
+
+impl SectionType {
+    /// Classify the tokens of a `section` that starts with a heading.
+    fn of(section: &mut [Token<'_>]) -> Self {
+        if Background::check_header(section) {
+            Self::Background
+        } else if Example::check_header(section) && Tabular::check_body(section) {
+            Self::Tabular
+        } else if Example::check_header(section) {
+            Self::Example
+        } else {
+            Self::Raw
+        }
+    }
+}
+
+impl Display for SectionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Background => write!(f, "background"),
+            Self::Tabular => write!(f, "tabular"),
+            Self::Example => write!(f, "example"),
+            Self::Raw => write!(f, "raw"),
+        }
+    }
+}
+
+/// The heading level, type, and name of each section in the `tokens`.
+///
+/// Unlike [`sections`], this doesn't check whether the sections are
+/// well-formed.
+pub(crate) fn outline(mut tokens: Tokens<'_, '_>) -> Vec<(HeadingLevel, SectionType, String)> {
+    let mut outline = Vec::new();
+    while advance::section(&mut tokens) {
+        let Some(section) = expect::section(&mut tokens) else {
+            continue;
+        };
+        let level = util::heading_level(section);
+        let name = util::heading_text(section);
+        outline.push((level, SectionType::of(section), name));
+    }
+    outline
+}
+
 impl<'a, 'input> Iterator for SectionsIter<'a, 'input> {
     type Item = Result<Section<'a, 'input>, Error<usize>>;
 
@@ -54,34 +173,47 @@ impl<'a, 'input> Iterator for SectionsIter<'a, 'input> {
             let Some(section) = expect::section(&mut self.tokens) else {
                 continue;
             };
-            if Background::check_header(section) {
-                let section = Background::try_from(section);
-                return Some(section.map(Section::Background));
-            } else if Example::check_header(section) {
-                let section = Example::try_from(section);
-                return Some(section.map(Section::Example));
-            } else {
-                let section = Raw::from(section);
-                return Some(Ok(Section::Raw(section)));
-            };
+            return Some(Self::section(section));
         }
 
         None
     }
 }
 
+impl<'a, 'input> DoubleEndedIterator for SectionsIter<'a, 'input> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        use pulldown_cmark::{Event::*, Tag as S};
+
+        // Split the last section off the remaining tokens. Tokens before the
+        // first heading don't belong to any section and are dropped.
+        let Some(start) = self
+            .tokens
+            .iter()
+            .rposition(|token| matches!(event(token), Start(S::Heading { .. })))
+        else {
+            self.tokens = &mut [];
+            return None;
+        };
+        let section = util::take_mut(&mut self.tokens, start);
+        let section = std::mem::replace(&mut self.tokens, section);
+
+        Some(Self::section(section))
+    }
+}
+
 // Section from Token slice constructors
 // =====================================
 
 impl<'a> Background<'a> {
-    /// Check if the section header starting with the `Background` string.
+    /// Check if the section header starts with the `Background` string
+    /// (ignoring case).
     fn check_header<'input>(section: &'a mut [Token<'input>]) -> bool {
         use pulldown_cmark::{CowStr::*, Event::*};
 
         if let Some((Text(Borrowed(heading)), _)) = section.get(1) {
-            heading.starts_with("Background")
+            crate::starts_with_keyword(heading, "Background")
         } else {
-            unreachable!("Asserted by `TokenSlice::next_section()`")
+            false // For example, a heading that starts with inline code.
         }
     }
 
@@ -89,11 +221,13 @@ impl<'a> Background<'a> {
         use pulldown_cmark::Event::*;
 
         let level = util::heading_level(section);
+        let end = util::end(section);
 
         // Skip the section header.
         let (heading, mut body) = section.split_at_mut(3);
 
         let mut given = HashMap::<&'a str, &'a str>::new();
+        let mut keys = HashMap::new();
         while !body.is_empty() {
             let mut pos = span(&body[0]).start;
             if let Some(key) = {
@@ -108,18 +242,20 @@ impl<'a> Background<'a> {
                 // Debug detected slice:
                 // crate::debug("background:given:val", body);
 
+                let at = util::start_or(body, end);
                 let val = expect::code_block(&mut body, |c| match c {
                     [(Text(val), _span)] => Ok(val),
-                    _ => Err(Error::ExpectedCode { pos }),
+                    _ => Err(Error::ExpectedCode { pos, at }),
                 })?;
 
+                util::check_key(&mut keys, key, pos, SectionKind::Given)?;
                 given.insert(key, val);
             }
         }
 
         if given.is_empty() {
             let pos = span(&heading[0]).start;
-            return Err(Error::MissingWhen { pos });
+            return Err(Error::MissingGiven { pos });
         }
 
         Ok(Self { level, given })
@@ -127,20 +263,23 @@ impl<'a> Background<'a> {
 }
 
 impl<'a, 'input> Example<'a, &'a mut CowStr<'input>> {
-    /// Check if the section header starting with the `Example` string.
+    /// Check if the section header starts with the `Example:` string
+    /// (ignoring case).
     fn check_header(section: &'a mut [Token<'input>]) -> bool {
         use pulldown_cmark::{CowStr::*, Event::*};
 
         if let Some((Text(Borrowed(heading)), _)) = section.get(1) {
-            heading.starts_with("Example:")
+            crate::starts_with_keyword(heading, "Example:")
         } else {
-            unreachable!("Asserted by `TokenSlice::next_section()`")
+            false // For example, a heading that starts with inline code.
         }
     }
 
     fn try_from(section: &'a mut [Token<'input>]) -> Result<Self, Error<usize>> {
         use pulldown_cmark::{CowStr::*, Event::*};
 
+        let end = util::end(section);
+        let heading_text = util::heading_text(section);
         let (heading, mut body) = section.split_at_mut(3);
 
         let level = util::heading_level(heading);
@@ -150,6 +289,9 @@ impl<'a, 'input> Example<'a, &'a mut CowStr<'input>> {
         };
 
         let mut when = HashMap::<&'a str, &'a str>::new();
+        let mut when_hints = HashMap::<&'a str, &'a str>::new();
+        let mut notes = Vec::new();
+        let mut keys = HashMap::new();
         while !body.is_empty() {
             let mut pos = span(&body[0]).start;
             if let Some(key) = {
@@ -159,24 +301,42 @@ impl<'a, 'input> Example<'a, &'a mut CowStr<'input>> {
                 if advance::paragraph(&mut body) {
                     pos = span(&body[0]).start;
                 }
-                if body.len() >= 5 && util::is_then(&mut body[1..4], true).is_some() {
+                if util::is_then(util::paragraph_body(body), true).is_some() {
                     break;
                 }
-                expect::paragraph(&mut body, |p| util::is_when(p, when.is_empty())).transpose()?
+                expect::paragraph(&mut body, |p| {
+                    // Collect the prose paragraphs before the first `When`.
+                    let text = util::text(p);
+                    let key = util::is_when(p, when.is_empty());
+                    if key.is_none() && when.is_empty() {
+                        notes.push(text);
+                    }
+                    key
+                })
+                .transpose()?
             } {
                 // Debug detected slice:
                 // crate::debug("example:when:key", body);
 
+                let (key, hint) = key;
+                let at = util::start_or(body, end);
                 let val = expect::code_block(&mut body, |c| match c {
                     [(Text(val), _span)] => Ok(val),
-                    _ => Err(Error::ExpectedCode { pos }),
+                    _ => Err(Error::ExpectedCode { pos, at }),
                 })?;
 
+                util::check_key(&mut keys, key, pos, SectionKind::When)?;
                 when.insert(key, val);
+                if let Some(hint) = hint {
+                    when_hints.insert(key, hint);
+                }
             }
         }
 
         let mut then = HashMap::<&'a str, &'a mut CowStr<'input>>::new();
+        let mut then_hints = HashMap::<&'a str, &'a str>::new();
+        let mut optional_then = HashSet::new();
+        let mut keys = HashMap::new();
         while !body.is_empty() {
             let mut pos = span(&body[0]).start;
             if let Some(key) = {
@@ -191,12 +351,147 @@ impl<'a, 'input> Example<'a, &'a mut CowStr<'input>> {
                 // Debug detected slice:
                 // crate::debug("example:then:val", body);
 
+                let (key, hint) = key;
+                let info = util::fence_info(body);
+                let at = util::start_or(body, end);
                 let val = expect::code_block(&mut body, |c| match c {
                     [(Text(val), _span)] => Ok(val),
-                    _ => Err(Error::ExpectedCode { pos }),
+                    _ => Err(Error::ExpectedCode { pos, at }),
                 })?;
 
+                let key = util::optional_key(key, &mut optional_then);
+                util::check_key(&mut keys, key, pos, SectionKind::Then)?;
                 then.insert(key, val);
+                if let Some(hint) = hint.or(info) {
+                    then_hints.insert(key, hint);
+                }
+            }
+        }
+
+        if when.is_empty() {
+            let pos = span(&heading[0]).start;
+            return Err(Error::MissingWhen { pos });
+        }
+        if then.is_empty() {
+            let pos = span(&heading[0]).start;
+            return Err(Error::MissingThen { pos });
+        }
+
+        Ok(Self {
+            level,
+            name,
+            heading: heading_text,
+            path: None,
+            when: when.into(),
+            when_hints,
+            then,
+            then_hints,
+            optional_then,
+            notes: (!notes.is_empty()).then(|| notes.join("\n\n")),
+        })
+    }
+}
+
+impl<'a, 'input> Tabular<'a, &'a mut CowStr<'input>> {
+    /// Check if the first `When` paragraph of the section body is directly
+    /// followed by a table. Tables elsewhere (for example, in the notes of a
+    /// regular example) don't make a section tabular.
+    fn check_body(section: &mut [Token<'input>]) -> bool {
+        use pulldown_cmark::{Event::*, Tag as S, TagEnd as E};
+
+        let mut body = &mut section[3..];
+        while advance::paragraph(&mut body) {
+            let is_when = util::is_when(util::paragraph_body(body), true).is_some();
+            let Some(end) = body
+                .iter()
+                .position(|token| matches!(event(token), End(E::Paragraph)))
+            else {
+                return false;
+            };
+            util::take_mut(&mut body, end + 1);
+            if is_when {
+                return matches!(body.first().map(event), Some(Start(S::Table(_))));
+            }
+        }
+        false
+    }
+
+    fn try_from(section: &'a mut [Token<'input>]) -> Result<Self, Error<usize>> {
+        use pulldown_cmark::{CowStr::*, Event::*};
+
+        let heading_text = util::heading_text(section);
+        let (heading, mut body) = section.split_at_mut(3);
+
+        let level = util::heading_level(heading);
+
+        let Some((Text(Borrowed(name)), _)) = heading.get(1) else {
+            unreachable!("Asserted by `TokenSlice::next_section()`")
+        };
+
+        let mut rows = None;
+
+        let mut when = Vec::<HashMap<&'a str, &'a str>>::new();
+        let mut when_hints = HashMap::<&'a str, &'a str>::new();
+        let mut keys = HashMap::new();
+        let mut first_par = true;
+        while !body.is_empty() {
+            let mut pos = span(&body[0]).start;
+            if let Some((_key, hint)) = {
+                if advance::paragraph(&mut body) {
+                    pos = span(&body[0]).start;
+                }
+                if util::is_then(util::paragraph_body(body), true).is_some() {
+                    break;
+                }
+                expect::paragraph(&mut body, |p| util::is_when(p, first_par)).transpose()?
+            } {
+                first_par = false;
+                let table = expect::table(&mut body, pos)?;
+                util::check_rows(&mut rows, table.len(), pos)?;
+                for (key, _) in table.first().into_iter().flatten() {
+                    util::check_key(&mut keys, key, pos, SectionKind::When)?;
+                    if let Some(hint) = hint {
+                        when_hints.insert(key, hint);
+                    }
+                }
+                when.resize_with(table.len(), HashMap::new);
+                for (row, values) in when.iter_mut().zip(table) {
+                    for (key, val) in values {
+                        row.insert(key, val);
+                    }
+                }
+            }
+        }
+
+        let mut then = Vec::<HashMap<&'a str, &'a mut CowStr<'input>>>::new();
+        let mut then_hints = HashMap::<&'a str, &'a str>::new();
+        let mut optional_then = HashSet::new();
+        let mut keys = HashMap::new();
+        let mut first_par = true;
+        while !body.is_empty() {
+            let mut pos = span(&body[0]).start;
+            if let Some((_key, hint)) = {
+                if advance::paragraph(&mut body) {
+                    pos = span(&body[0]).start;
+                }
+                expect::paragraph(&mut body, |p| util::is_then(p, first_par)).transpose()?
+            } {
+                first_par = false;
+                let mut table = expect::table(&mut body, pos)?;
+                util::check_rows(&mut rows, table.len(), pos)?;
+                for (key, _) in table.iter_mut().flatten() {
+                    *key = util::optional_key(key, &mut optional_then);
+                }
+                for (key, _) in table.first().into_iter().flatten() {
+                    util::check_key(&mut keys, key, pos, SectionKind::Then)?;
+                    if let Some(hint) = hint {
+                        then_hints.insert(key, hint);
+                    }
+                }
+                then.resize_with(table.len(), HashMap::new);
+                for (row, values) in then.iter_mut().zip(table) {
+                    row.extend(values);
+                }
             }
         }
 
@@ -212,8 +507,12 @@ impl<'a, 'input> Example<'a, &'a mut CowStr<'input>> {
         Ok(Self {
             level,
             name,
+            heading: heading_text,
             when,
+            when_hints,
             then,
+            then_hints,
+            optional_then,
         })
     }
 }
@@ -263,6 +562,9 @@ mod advance {
 mod expect {
     use super::*;
 
+    /// The `(key, value)` pairs of a table row.
+    type TableRow<'a, 'input> = Vec<(&'a str, &'a mut CowStr<'input>)>;
+
     /// Assert that the `tokens` sequence is non-empty and starts with a
     /// `Heading` start tag. Consume and return a mutable slice that includes
     /// everything until the next heading or the end of the sequence.
@@ -303,7 +605,7 @@ mod expect {
         predicate: P,
     ) -> Option<T>
     where
-        P: Fn(Tokens<'a, 'input>) -> Option<T>,
+        P: FnOnce(Tokens<'a, 'input>) -> Option<T>,
     {
         use pulldown_cmark::{Event::*, Tag as S, TagEnd as E};
 
@@ -385,11 +687,116 @@ mod expect {
 
         predicate(&mut code[start + 1..=end - 1])
     }
+
+    /// Consume a table and return its body rows as `(key, value)` pairs, where
+    /// the keys are taken from the corresponding header cells.
+    pub(super) fn table<'a, 'input>(
+        tokens: &mut Tokens<'a, 'input>,
+        pos: usize,
+    ) -> Result<Vec<TableRow<'a, 'input>>, Error<usize>> {
+        use pulldown_cmark::{Event::*, Tag as S, TagEnd as E};
+
+        let mut finger = 0;
+
+        // Ensure that the finger is the start of the next table, returning
+        // an error elsewhere.
+        let Some(Start(S::Table(_))) = tokens.get(finger).map(event) else {
+            return Err(Error::ExpectedTable { pos });
+        };
+
+        // Advance the finger to the end of the current table, asserting that
+        // we have stopped at the corresponding closing tag.
+        if !util::advance(tokens, &mut finger, |token| {
+            matches!(event(token), End(E::Table))
+        }) {
+            unreachable!("token stream is not well-formed (missing closing table tag)");
+        }
+        finger += 1;
+
+        let table = util::take_mut(tokens, finger);
+        let len = table.len();
+        let mut rest = &mut table[1..len - 1];
+
+        // Split the table into rows (including the header) of cell values.
+        let mut rows = Vec::new();
+        while !rest.is_empty() {
+            let mut finger = 0;
+            util::advance(rest, &mut finger, |token| {
+                matches!(event(token), End(E::TableHead | E::TableRow))
+            });
+            let row = util::take_mut(&mut rest, finger + 1);
+            let len = row.len();
+            let mut cells = &mut row[1..len - 1];
+
+            let mut values = Vec::new();
+            while !cells.is_empty() {
+                let mut finger = 0;
+                util::advance(cells, &mut finger, |token| {
+                    matches!(event(token), End(E::TableCell))
+                });
+                let cell = util::take_mut(&mut cells, finger + 1);
+                let len = cell.len();
+                let pos = span(&cell[0]).start;
+                match &mut cell[1..len - 1] {
+                    [(Text(val), _)] | [(Code(val), _)] => values.push(val),
+                    // Escaped `|` characters split the text of a cell into
+                    // several runs. Merge them into the first one.
+                    [(Text(val), _), rest @ ..]
+                        if rest.iter().all(|token| matches!(event(token), Text(_))) =>
+                    {
+                        let mut text = val.to_string();
+                        for (event, _) in rest {
+                            if let Text(run) = event {
+                                text.push_str(run);
+                                *run = CowStr::Borrowed("");
+                            }
+                        }
+                        *val = CowStr::from(text);
+                        values.push(val);
+                    }
+                    _ => return Err(Error::ExpectedTableCell { pos }),
+                }
+            }
+            rows.push(values);
+        }
+
+        let mut rows = rows.into_iter();
+        let header = rows.next().unwrap_or_default();
+        let keys = header
+            .into_iter()
+            .map(|key| &**key)
+            .collect::<Vec<&'a str>>();
+        let rows = rows.map(|values| keys.iter().copied().zip(values).collect());
+
+        Ok(rows.collect())
+    }
 }
 
 mod util {
     use super::*;
 
+    /// The end offset of the last token in `tokens`.
+    pub(crate) fn end(tokens: &[Token<'_>]) -> usize {
+        tokens.last().map_or(0, |token| span(token).end)
+    }
+
+    /// The start offset of the first token in `tokens`, or `end` if `tokens`
+    /// is empty.
+    pub(crate) fn start_or(tokens: &[Token<'_>], end: usize) -> usize {
+        tokens.first().map_or(end, |token| span(token).start)
+    }
+
+    /// The text of the heading at the start of `section`, including the
+    /// content of code spans.
+    pub(crate) fn heading_text(section: &[Token<'_>]) -> String {
+        use pulldown_cmark::{Event::*, TagEnd as E};
+
+        let end = section
+            .iter()
+            .position(|token| matches!(event(token), End(E::Heading(_))));
+        text(&section[1..end.unwrap_or(section.len())])
+    }
+
     pub(crate) fn heading_level(section: Tokens<'_, '_>) -> HeadingLevel {
         use pulldown_cmark::{Event::*, Tag as S};
 
@@ -440,54 +847,170 @@ mod util {
         *finger < tokens.len()
     }
 
-    pub(crate) fn starts_with(tokens: &[Token<'_>], pat: &str) -> bool {
-        match tokens.first().map(event) {
-            Some(Event::Text(t)) => t.starts_with(pat),
-            _ => false,
+    /// The text content of the inline `tokens` of a paragraph.
+    pub(crate) fn text(tokens: &[Token<'_>]) -> String {
+        let mut text = String::new();
+        for token in tokens {
+            match event(token) {
+                Event::Text(s) | Event::Code(s) => text.push_str(s),
+                Event::SoftBreak | Event::HardBreak => text.push('\n'),
+                _ => (),
+            }
         }
+        text
+    }
+
+    /// The number of consecutive `Text` events at the start of `tokens`.
+    pub(crate) fn text_run<'a, 'input: 'a, I>(tokens: I) -> usize
+    where
+        I: IntoIterator<Item = &'a Token<'input>>,
+    {
+        let is_text = |token: &&Token| matches!(event(token), Event::Text(_));
+        tokens.into_iter().take_while(is_text).count()
     }
 
-    pub(crate) fn ends_with(tokens: &[Token<'_>], pat: &str) -> bool {
-        match tokens.last().map(event) {
-            Some(Event::Text(t)) => t.ends_with(pat),
-            _ => false,
+    /// The tokens between the enclosing tags of the paragraph at the start of
+    /// `tokens`, or an empty slice if `tokens` doesn't start with a paragraph.
+    pub(crate) fn paragraph_body<'a, 'input>(
+        tokens: &'a mut [Token<'input>],
+    ) -> &'a mut [Token<'input>] {
+        use pulldown_cmark::{Event::*, Tag as S, TagEnd as E};
+
+        let Some(Start(S::Paragraph)) = tokens.first().map(event) else {
+            return &mut [];
+        };
+        let end = tokens
+            .iter()
+            .position(|token| matches!(event(token), End(E::Paragraph)));
+        match end {
+            Some(end) => &mut tokens[1..end],
+            None => &mut [],
         }
     }
 
+    /// The key (and optional hint) of a matched spec paragraph, or an error if
+    /// the paragraph starts and ends as expected but is malformed.
+    type KeyParagraph<'a, 'input> =
+        Option<Result<(&'a CowStr<'input>, Option<&'a str>), Error<usize>>>;
+
     pub(crate) fn is_given<'a, 'input>(
         paragraph: Tokens<'a, 'input>,
         first_par: bool,
     ) -> Option<Result<&'a CowStr<'input>, Error<usize>>> {
         let exp_prefix = if first_par { "Given " } else { "And " };
-        key_paragraph(exp_prefix, " as:", paragraph)
+        let key = key_paragraph(exp_prefix, " as:", false, paragraph);
+        key.map(|key| key.map(|(key, _)| key))
     }
 
+    /// Like [`is_given`], but also accepts paragraphs with an optional
+    /// `(hint)` before the trailing colon (for example,
+    /// ``When `input` is (sql):``) and returns the hint along with the key.
     pub(crate) fn is_when<'a, 'input>(
         paragraph: Tokens<'a, 'input>,
         first_par: bool,
-    ) -> Option<Result<&'a CowStr<'input>, Error<usize>>> {
+    ) -> KeyParagraph<'a, 'input> {
         let exp_prefix = if first_par { "When " } else { "And " };
-        key_paragraph(exp_prefix, " is:", paragraph)
+        key_paragraph(exp_prefix, " is:", true, paragraph)
     }
 
+    /// Like [`is_when`], but for `Then` paragraphs.
     pub(crate) fn is_then<'a, 'input>(
         paragraph: Tokens<'a, 'input>,
         first_par: bool,
-    ) -> Option<Result<&'a CowStr<'input>, Error<usize>>> {
+    ) -> KeyParagraph<'a, 'input> {
         let exp_prefix = if first_par { "Then " } else { "And " };
-        key_paragraph(exp_prefix, " is:", paragraph)
+        key_paragraph(exp_prefix, " is:", true, paragraph)
+    }
+
+    /// The info string of the fenced code block at the start of `tokens`, or
+    /// `None` if there is no such block or if its info string is empty.
+    pub(crate) fn fence_info<'input>(tokens: &[Token<'input>]) -> Option<&'input str> {
+        use pulldown_cmark::{CodeBlockKind::Fenced, CowStr::Borrowed, Event::Start, Tag as S};
+
+        let Some(Start(S::CodeBlock(Fenced(Borrowed(info))))) = tokens.first().map(event) else {
+            return None;
+        };
+        let info: &'input str = info;
+        Some(info.trim()).filter(|info| !info.is_empty())
+    }
+
+    /// Strip the `(optional)` suffix from a `then` key, recording stripped keys
+    /// in `optional_then`.
+    pub(crate) fn optional_key<'a>(key: &'a str, optional_then: &mut HashSet<&'a str>) -> &'a str {
+        match key.strip_suffix("(optional)") {
+            Some(key) => {
+                let key = key.trim_end();
+                optional_then.insert(key);
+                key
+            }
+            None => key,
+        }
+    }
+
+    /// Record the position of the spec paragraph that defines a `key`, checking
+    /// that the `key` was not defined before in the same section.
+    pub(crate) fn check_key<'a>(
+        keys: &mut HashMap<&'a str, usize>,
+        key: &'a str,
+        pos: usize,
+        kind: SectionKind,
+    ) -> Result<(), Error<usize>> {
+        match keys.insert(key, pos) {
+            Some(first) => Err(Error::DuplicateKey {
+                kind,
+                key: key.to_string(),
+                first,
+                second: pos,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that a table with `len` rows has the same number of rows as the
+    /// previous tables in the same section.
+    pub(crate) fn check_rows(
+        rows: &mut Option<usize>,
+        len: usize,
+        pos: usize,
+    ) -> Result<(), Error<usize>> {
+        match rows {
+            Some(rows) if *rows != len => Err(Error::MismatchedRows { pos }),
+            Some(_) => Ok(()),
+            None => {
+                *rows = Some(len);
+                Ok(())
+            }
+        }
     }
 
     fn key_paragraph<'a, 'input>(
         exp_prefix: &str,
         exp_suffix: &str,
+        with_hint: bool,
         paragraph: Tokens<'a, 'input>,
-    ) -> Option<Result<&'a CowStr<'input>, Error<usize>>> {
-        if !util::starts_with(paragraph, exp_prefix) || !util::ends_with(paragraph, exp_suffix) {
+    ) -> KeyParagraph<'a, 'input> {
+        let paragraph = &*paragraph;
+        // Soft-wrapped or escaped text might be split into several `Text`
+        // events, so match against the concatenated leading and trailing runs.
+        let prefix_len = util::text_run(paragraph.iter());
+        let suffix_len = util::text_run(paragraph.iter().rev());
+        let prefix = util::text(&paragraph[..prefix_len]);
+        let suffix = util::text(&paragraph[paragraph.len() - suffix_len..]);
+        let hint = match (with_hint, paragraph.last().map(event)) {
+            (true, Some(Event::Text(text))) => hint(text, exp_suffix),
+            _ => None,
+        };
+        if !crate::starts_with_keyword(&prefix, exp_prefix)
+            || !(suffix.ends_with(exp_suffix) || hint.is_some())
+        {
             // crate::debug("skip:0", &*paragraph);
             return None; // Ignore paragraphs that don't start or end as expected.
         }
-        let [_prefix, key, _suffix] = paragraph else {
+        let middle = match prefix_len < paragraph.len() {
+            true => &paragraph[prefix_len..paragraph.len() - suffix_len],
+            false => &[],
+        };
+        let [key] = middle else {
             let pattern = format!("{exp_prefix}`<key>`{exp_suffix}");
             let pos = span(&paragraph[0]).start;
             return Some(Err(Error::ExpectedSpecParagraph { pattern, pos }));
@@ -498,7 +1021,18 @@ mod util {
             return Some(Err(Error::ExpectedSpecParagraph { pattern, pos }));
         };
 
-        Some(Ok(key))
+        Some(Ok((key, hint)))
+    }
+
+    /// Extract the `hint` from a spec paragraph `text` that ends with the
+    /// `exp_suffix` with a `(hint)` before its colon, for example `sql` from
+    /// `" is (sql):"`.
+    fn hint<'a>(text: &'a str, exp_suffix: &str) -> Option<&'a str> {
+        let exp_verb = exp_suffix.strip_suffix(':')?;
+        let (verb, hint) = text.strip_suffix("):")?.rsplit_once(" (")?;
+        let valid = |c: char| !c.is_whitespace() && c != '(' && c != ')';
+        let is_valid = !hint.is_empty() && hint.chars().all(valid);
+        (verb.ends_with(exp_verb) && is_valid).then_some(hint)
     }
 }
 
@@ -508,18 +1042,53 @@ mod util {
 /// An error with a generic type `P` that represents the position.
 #[derive(Error, Debug, Eq, PartialEq)]
 pub enum Error<P: Display> {
-    #[error("expected '{pattern}' spec paragraph at {pos}")]
+    #[error("expected '{pattern}' spec paragraph at {pos:#}")]
     ExpectedSpecParagraph { pattern: String, pos: P },
-    #[error("expected code block after spec paragraph starting at {pos}")]
-    ExpectedCode { pos: P },
-    #[error("background section at {pos} needs at least one 'Given' paragraph")]
+    #[error("expected code block at {at} after spec paragraph starting at {pos:#}")]
+    ExpectedCode {
+        pos: P,
+        /// The position where the missing code block was expected.
+        at: P,
+    },
+    #[error("expected table after spec paragraph starting at {pos:#}")]
+    ExpectedTable { pos: P },
+    #[error("expected a single text or code value in table cell at {pos:#}")]
+    ExpectedTableCell { pos: P },
+    #[error("table with a different number of rows after spec paragraph starting at {pos:#}")]
+    MismatchedRows { pos: P },
+    #[error("duplicate '{kind}' key `{key}` (first defined at {first}) at {second:#}")]
+    DuplicateKey {
+        kind: SectionKind,
+        key: String,
+        first: P,
+        second: P,
+    },
+    #[error("background section needs at least one 'Given' paragraph at {pos:#}")]
     MissingGiven { pos: P },
-    #[error("example section at {pos} needs at least one 'When' paragraph")]
+    #[error("example section needs at least one 'When' paragraph at {pos:#}")]
     MissingWhen { pos: P },
-    #[error("example section at {pos} needs at least one 'Then' paragraph")]
+    #[error("example section needs at least one 'Then' paragraph at {pos:#}")]
     MissingThen { pos: P },
 }
 
+/// The kind of spec paragraph that defines a key.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SectionKind {
+    Given,
+    When,
+    Then,
+}
+
+impl Display for SectionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Given => write!(f, "Given"),
+            Self::When => write!(f, "When"),
+            Self::Then => write!(f, "Then"),
+        }
+    }
+}
+
 impl Error<usize> {
     pub fn map_span(self, input: &str) -> Error<Pos> {
         use Error::*;
@@ -532,9 +1101,30 @@ impl Error<usize> {
                 pattern,
                 pos: pos_of(offset),
             },
-            ExpectedCode { pos: offset } => ExpectedCode {
+            ExpectedCode { pos: offset, at } => ExpectedCode {
+                pos: pos_of(offset),
+                at: pos_of(at),
+            },
+            ExpectedTable { pos: offset } => ExpectedTable {
+                pos: pos_of(offset),
+            },
+            ExpectedTableCell { pos: offset } => ExpectedTableCell {
                 pos: pos_of(offset),
             },
+            MismatchedRows { pos: offset } => MismatchedRows {
+                pos: pos_of(offset),
+            },
+            DuplicateKey {
+                kind,
+                key,
+                first,
+                second,
+            } => DuplicateKey {
+                kind,
+                key,
+                first: pos_of(first),
+                second: pos_of(second),
+            },
             MissingGiven { pos: offset } => MissingGiven {
                 pos: pos_of(offset),
             },
@@ -548,18 +1138,49 @@ impl Error<usize> {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+/// A line and column position in a spec file.
+///
+/// Positions created by [`Error::map_span`] also carry a snippet of up to three
+/// lines of the surrounding source text. The snippet is displayed with the
+/// alternate `{:#}` format and ignored when comparing positions.
+#[derive(Debug, Eq)]
 pub struct Pos {
     line: usize,
     column: usize,
+    context: Option<Box<PosContext>>,
+}
+
+/// The source context of a [`Pos`].
+#[derive(Debug, Eq, PartialEq)]
+struct PosContext {
+    /// The lines around the position.
+    snippet: String,
+    /// The byte offset and the length of the remaining line in the source.
+    span: (usize, usize),
 }
 
 impl Pos {
     fn new(line: usize, column: usize) -> Self {
-        Self { line, column }
+        let context = None;
+        Self {
+            line,
+            column,
+            context,
+        }
+    }
+
+    /// The byte offset and the length of the remaining line in the source
+    /// (if known).
+    #[cfg_attr(not(feature = "miette"), allow(unused))]
+    pub(super) fn span(&self) -> Option<(usize, usize)> {
+        self.context.as_ref().map(|context| context.span)
     }
 
     fn from(mut offset: usize, input: &str) -> Pos {
+        let start = offset;
+        let length = input.get(start..).and_then(|rest| rest.find('\n'));
+        let length = length.unwrap_or(input.len().saturating_sub(start));
+
         let mut rest = input;
 
         let mut line = 0;
@@ -576,14 +1197,53 @@ impl Pos {
             }
         }
 
-        Pos::new(line + 1, column + 1)
+        let mut pos = Pos::new(line + 1, column + 1);
+        let snippet = pos.snippet(input);
+        let span = (start, length);
+        pos.context = Some(Box::new(PosContext { snippet, span }));
+        pos
+    }
+
+    /// Render the lines around this position in `input` with a gutter that
+    /// shows the line numbers and a caret under the current column.
+    fn snippet(&self, input: &str) -> String {
+        let first = usize::max(self.line, 2) - 1;
+        let lines = input.lines().enumerate().map(|(i, line)| (i + 1, line));
+        let lines = lines.skip(first - 1).take(self.line + 2 - first);
+        let width = (self.line + 1).to_string().len();
+
+        let mut snippet = String::new();
+        for (number, line) in lines {
+            let line = format!("{number:>width$} | {line}");
+            snippet.push_str(line.trim_end());
+            snippet.push('\n');
+            if number == self.line {
+                let padding = " ".repeat(self.column - 1);
+                snippet.push_str(&format!("{:width$} | {padding}^\n", ""));
+            }
+        }
+        snippet
+    }
+}
+
+impl PartialEq for Pos {
+    fn eq(&self, other: &Self) -> bool {
+        (self.line, self.column) == (other.line, other.column)
     }
 }
 
 impl Display for Pos {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let Self { line, column } = self;
-        write!(f, "line {line}, column {column}")
+        let Self {
+            line,
+            column,
+            context,
+        } = self;
+        write!(f, "line {line}, column {column}")?;
+        match context {
+            Some(context) if f.alternate() => write!(f, ":\n{}", context.snippet.trim_end()),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -592,7 +1252,7 @@ mod tests {
     use indoc;
 
     use super::super::examples::*;
-    use super::{sections, Error, Pos, Section};
+    use super::{sections, Error, Pos, Section, SectionKind};
     use crate::md;
 
     #[test]
@@ -617,6 +1277,9 @@ mod tests {
                     assert_eq!(example.then.len(), 1);
                     assert!(example.then.contains_key("output"));
                 }
+                Ok(Section::Tabular(tabular)) => {
+                    panic!("unexpected tabular section: {tabular:?}");
+                }
                 Ok(Section::Raw(_raw)) => {
                     // println!("{raw:#?}");
                     // todo
@@ -630,6 +1293,411 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_example_notes() {
+        let md_source = indoc::indoc! {r"
+            ## Example: Notes
+
+            Motivating prose with `code`
+            and a line break.
+
+            Another paragraph.
+
+            When `x` is:
+
+            ```
+            1
+            ```
+
+            Prose between values is not a note.
+
+            Then `x` is:
+
+            ```
+            1
+            ```
+
+            ## Example: No notes
+
+            When `x` is:
+
+            ```
+            1
+            ```
+
+            Then `x` is:
+
+            ```
+            1
+            ```
+        "};
+        let mut md_doc = md::MdDocument::from_string(md_source);
+
+        let notes = sections(&mut md_doc)
+            .map(|section| match section {
+                Ok(Section::Example(example)) => example.notes,
+                _ => panic!("unexpected section: {section:?}"),
+            })
+            .collect::<Vec<_>>();
+        let exp = "Motivating prose with code\nand a line break.\n\nAnother paragraph.";
+        assert_eq!(notes, vec![Some(exp.to_string()), None]);
+    }
+
+    #[test]
+    fn test_split_key_paragraphs() {
+        // Brackets and character references split the paragraph text into
+        // several `Text` events.
+        let md_source = indoc::indoc! {r"
+            ## Background
+
+            Given [the] `x` as:
+
+            ```
+            1
+            ```
+
+            ## Example: Split
+
+            When&#32;`input` is:
+
+            ```
+            1
+            ```
+
+            Then `output` is&#58;
+
+            ```
+            1
+            ```
+        "};
+        let mut md_doc = md::MdDocument::from_string(md_source);
+        let mut sections = sections(&mut md_doc);
+
+        let Some(Ok(Section::Background(background))) = sections.next() else {
+            panic!("expected a background section");
+        };
+        assert_eq!(background.given["x"], "1\n");
+        let section = sections.next();
+        let Some(Ok(Section::Example(example))) = section else {
+            panic!("expected an example section, got {section:?}");
+        };
+        assert_eq!(example.when["input"], "1\n");
+        assert_eq!(example.then["output"].as_ref(), "1\n");
+    }
+
+    #[test]
+    fn test_hints() {
+        let md_source = indoc::indoc! {r"
+            ## Example: Hints
+
+            When `input` is (sql):
+
+            ```
+            SELECT 1;
+            ```
+
+            And `env` is:
+
+            ```
+            x=1
+            ```
+
+            Then `output` is:
+
+            ```text
+            1
+            ```
+
+            And `plan` is (sql):
+
+            ```json
+            {}
+            ```
+
+            And `rows` is:
+
+            ```
+            1
+            ```
+
+            ## Example: Table
+
+            When `rows` is (csv):
+
+            | a | b |
+            | - | - |
+            | 1 | 2 |
+
+            Then `result` is (csv):
+
+            | c |
+            | - |
+            | 3 |
+        "};
+        let mut md_doc = md::MdDocument::from_string(md_source);
+        let mut sections = sections(&mut md_doc);
+
+        let section = sections.next();
+        let Some(Ok(Section::Example(example))) = section else {
+            panic!("expected an example section, got {section:?}");
+        };
+        assert_eq!(example.when["input"], "SELECT 1;\n");
+        assert_eq!(example.when["env"], "x=1\n");
+        assert_eq!(example.when_hints, [("input", "sql")].into());
+        assert_eq!(
+            example.then_hints,
+            [("output", "text"), ("plan", "sql")].into()
+        );
+
+        let section = sections.next();
+        let Some(Ok(Section::Tabular(tabular))) = section else {
+            panic!("expected a tabular section, got {section:?}");
+        };
+        assert_eq!(tabular.when_hints, [("a", "csv"), ("b", "csv")].into());
+        assert_eq!(tabular.then_hints, [("c", "csv")].into());
+    }
+
+    #[test]
+    fn test_case_insensitive_keywords() {
+        let md_source = indoc::indoc! {r"
+            # BACKGROUND
+
+            given `x` as:
+
+            ```
+            1
+            ```
+
+            ## example: Lowercase
+
+            WHEN `input` is:
+
+            ```
+            x
+            ```
+
+            and `other` is:
+
+            ```
+            y
+            ```
+
+            then `output` is:
+
+            ```
+            1
+            ```
+        "};
+        let mut md_doc = md::MdDocument::from_string(md_source);
+        let mut iter = sections(&mut md_doc);
+
+        let Some(Ok(Section::Background(background))) = iter.next() else {
+            panic!("expected background");
+        };
+        assert_eq!(background.given, [("x", "1\n")].into());
+
+        let Some(Ok(Section::Example(example))) = iter.next() else {
+            panic!("expected example");
+        };
+        assert_eq!(example.name, "example: Lowercase");
+        assert_eq!(*example.when, [("input", "x\n"), ("other", "y\n")].into());
+        assert_eq!(example.then.len(), 1);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_sections_rev() {
+        fn name(section: Result<Section, Error<usize>>) -> String {
+            match section.expect("valid section") {
+                Section::Background(_) => "Background".to_string(),
+                Section::Example(example) => example.name.to_string(),
+                Section::Tabular(tabular) => tabular.name.to_string(),
+                Section::Raw(raw) => format!("Raw {:?}", raw.level),
+            }
+        }
+
+        let md_source = make_spec(INPUT_SQL, OUTPUT_SQL);
+        let mut md_doc = md::MdDocument::from_string(&md_source);
+        let forward = sections(&mut md_doc).map(name).collect::<Vec<_>>();
+        let mut md_doc = md::MdDocument::from_string(&md_source);
+        let mut backward = sections(&mut md_doc).rev().map(name).collect::<Vec<_>>();
+        backward.reverse();
+
+        assert!(forward.len() > 2);
+        assert_eq!(forward, backward);
+
+        // Iterate via `IntoIterator for &mut MdDocument`.
+        let mut md_doc = md::MdDocument::from_string(&md_source);
+        let mut into_iter = Vec::new();
+        for section in &mut md_doc {
+            into_iter.push(name(section));
+        }
+        assert_eq!(forward, into_iter);
+
+        // Consume sections from both ends.
+        let mut md_doc = md::MdDocument::from_string(&md_source);
+        let mut iter = sections(&mut md_doc);
+        let first = iter.next().map(name);
+        let last = iter.next_back().map(name);
+        let rest = iter.map(name).collect::<Vec<_>>();
+        assert_eq!(first.as_ref(), forward.first());
+        assert_eq!(last.as_ref(), forward.last());
+        assert_eq!(rest, forward[1..forward.len() - 1]);
+    }
+
+    #[test]
+    fn test_tabular_sections() {
+        let md_source = indoc::indoc! {r"
+            ## Example: Addition
+
+            When `input` is:
+
+            | x | y |
+            | --- | --- |
+            | 1 | 2 |
+            | `3` | 4 |
+
+            Then `result` is:
+
+            | sum |
+            | --- |
+            | 3 |
+            | 7 |
+        "};
+        let mut md_doc = md::MdDocument::from_string(md_source);
+
+        let mut sections = sections(&mut md_doc);
+        let Some(Ok(Section::Tabular(tabular))) = sections.next() else {
+            panic!("expected a tabular section");
+        };
+        assert!(sections.next().is_none());
+
+        assert_eq!(tabular.name, "Example: Addition");
+        assert_eq!(tabular.when.len(), 2);
+        assert_eq!(tabular.when[1]["x"], "3");
+        assert_eq!(tabular.when[1]["y"], "4");
+        assert_eq!(tabular.then.len(), 2);
+        assert_eq!(tabular.then[0]["sum"].as_ref(), "3");
+        assert_eq!(tabular.then[1]["sum"].as_ref(), "7");
+    }
+
+    #[test]
+    fn test_table_in_notes() {
+        let md_source = indoc::indoc! {r"
+            ## Example: Notes
+
+            The input is one of:
+
+            | x |
+            | --- |
+            | 1 |
+
+            When `input` is:
+
+            ```
+            1
+            ```
+
+            Then `output` is:
+
+            ```
+            1
+            ```
+
+            | y |
+            | --- |
+            | 2 |
+        "};
+        let mut md_doc = md::MdDocument::from_string(md_source);
+
+        let mut sections = sections(&mut md_doc);
+        let Some(Ok(Section::Example(example))) = sections.next() else {
+            panic!("expected an example section");
+        };
+        assert!(sections.next().is_none());
+        assert_eq!(example.when["input"], "1\n");
+        assert_eq!(example.then["output"].as_ref(), "1\n");
+    }
+
+    #[test]
+    fn test_example_heading() {
+        let md_source = indoc::indoc! {r"
+            ## Example: `pi` is *about* 3
+
+            When `x` is:
+
+            ```
+            pi
+            ```
+
+            Then `y` is:
+
+            ```
+            3
+            ```
+        "};
+        let mut md_doc = md::MdDocument::from_string(md_source);
+
+        let mut sections = sections(&mut md_doc);
+        let Some(Ok(Section::Example(example))) = sections.next() else {
+            panic!("expected an example section");
+        };
+        assert_eq!(example.name, "Example: ");
+        assert_eq!(example.heading, "Example: pi is about 3");
+    }
+
+    #[test]
+    fn test_code_heading() {
+        let md_source = indoc::indoc! {r"
+            ## `Example:` headings
+
+            Start with inline code.
+        "};
+        let mut md_doc = md::MdDocument::from_string(md_source);
+
+        let mut sections = sections(&mut md_doc);
+        assert!(matches!(sections.next(), Some(Ok(Section::Raw(_)))));
+        assert!(sections.next().is_none());
+        assert_eq!(md_doc.to_string(), "h2 [raw] Example: headings\n");
+    }
+
+    #[test]
+    fn test_empty_code_blocks() {
+        let md_source = indoc::indoc! {r"
+            ## Background
+
+            Given `schema` as:
+
+            ```
+            ```
+
+            ## Example: Empty
+
+            When `input` is:
+
+            ```
+            ```
+
+            Then `output` is:
+
+            ```sql
+            ```
+        "};
+        let mut md_doc = md::MdDocument::from_string(md_source);
+
+        let mut sections = sections(&mut md_doc);
+        let Some(Ok(Section::Background(background))) = sections.next() else {
+            panic!("expected a background section");
+        };
+        assert_eq!(background.given["schema"], "");
+        let Some(Ok(Section::Example(example))) = sections.next() else {
+            panic!("expected an example section");
+        };
+        assert_eq!(example.when["input"], "");
+        assert_eq!(example.then["output"].as_ref(), "");
+        assert!(sections.next().is_none());
+    }
+
     #[test]
     fn bad_sections() {
         struct TestCase {
@@ -645,6 +1713,24 @@ mod tests {
                 "},
                 exp_error: Error::ExpectedCode {
                     pos: Pos::new(3, 1),
+                    at: Pos::new(4, 1),
+                },
+            },
+            TestCase {
+                md_source: indoc::indoc! {r"
+                    ## Example: (1)
+
+                    When `input` is:
+
+                    Then `output` is:
+
+                    ```
+                    2
+                    ```
+                "},
+                exp_error: Error::ExpectedCode {
+                    pos: Pos::new(3, 1),
+                    at: Pos::new(5, 1),
                 },
             },
             TestCase {
@@ -669,6 +1755,50 @@ mod tests {
                     pos: Pos::new(3, 1),
                 },
             },
+            TestCase {
+                md_source: indoc::indoc! {r"
+                    ## Example: (5)
+
+                    When `input` is:
+
+                    ```
+                    5
+                    ```
+
+                    And `input` is:
+
+                    ```
+                    6
+                    ```
+                "},
+                exp_error: Error::DuplicateKey {
+                    kind: SectionKind::When,
+                    key: String::from("input"),
+                    first: Pos::new(3, 1),
+                    second: Pos::new(9, 1),
+                },
+            },
+            TestCase {
+                md_source: indoc::indoc! {r"
+                    ## Example: (4)
+
+                    When `input` is:
+
+                    | x |
+                    | --- |
+                    | 1 |
+
+                    Then `result` is:
+
+                    | y |
+                    | --- |
+                    | 1 |
+                    | 2 |
+                "},
+                exp_error: Error::MismatchedRows {
+                    pos: Pos::new(9, 1),
+                },
+            },
             TestCase {
                 md_source: indoc::indoc! {r"
                     ## Example: (3)
@@ -683,6 +1813,14 @@ mod tests {
                     pos: Pos::new(1, 1),
                 },
             },
+            TestCase {
+                md_source: indoc::indoc! {r"
+                    ## Background (2)
+                "},
+                exp_error: Error::MissingGiven {
+                    pos: Pos::new(1, 1),
+                },
+            },
         ];
 
         for test_case in test_cases {
@@ -700,4 +1838,29 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_error_context() {
+        let md_source = indoc::indoc! {r"
+            ## Example: (1)
+
+            When pipeline is:
+
+            ```
+            5
+            ```
+        "};
+        let mut md_doc = md::MdDocument::from_string(md_source);
+
+        let section = sections(&mut md_doc).next().expect("section");
+        let error = section.expect_err("example error").map_span(md_source);
+
+        let exp = indoc::indoc! {r"
+            expected 'When `<key>` is:' spec paragraph at line 3, column 1:
+            2 |
+            3 | When pipeline is:
+              | ^
+            4 |"};
+        assert_eq!(error.to_string(), exp);
+    }
 }
@@ -0,0 +1,144 @@
+//! A wrapper for [`Example::when`] values that detects unused keys.
+//!
+//! [`Example::when`]: super::Example::when
+
+use std::collections::HashMap;
+use std::ops::{Deref, Index};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The [`Example::when`](super::Example::when) values of an example, which
+/// record the keys that were looked up by a handler.
+///
+/// Unused `when` keys often indicate a typo in the handler, for example
+/// `when.get("inpt")` instead of `when.get("input")`. Only lookups through
+/// [`get`](TrackedWhen::get), [`contains_key`](TrackedWhen::contains_key), and
+/// indexing are recorded. Other [`HashMap`] methods are available through
+/// [`Deref`] but don't mark any keys as used.
+///
+/// Set [`Config::warn_unused_when`](super::Config::warn_unused_when) in order
+/// to print a warning for the unused keys of each example that passes.
+/// Handlers can also check the keys themselves:
+///
+/// ```
+/// use spectest::{Example, Handler};
+///
+/// struct EvalHandler;
+///
+/// impl Handler for EvalHandler {
+///     type Error = String;
+///
+///     fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+///         let input = example.when.get("input").ok_or("missing `input`")?;
+///         example.then.insert("output", input.to_uppercase());
+///         example.when.warn_unused(example.name);
+///         Ok(())
+///     }
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct TrackedWhen<'a> {
+    when: HashMap<&'a str, &'a str>,
+    /// Whether each key was looked up. Flags keep the type covariant in `'a`
+    /// (unlike a set of keys behind a lock).
+    used: HashMap<&'a str, AtomicBool>,
+}
+
+impl<'a> TrackedWhen<'a> {
+    /// Wrap the given `when` values.
+    pub fn new(when: HashMap<&'a str, &'a str>) -> Self {
+        let used = when.keys().map(|key| (*key, AtomicBool::new(false)));
+        let used = used.collect();
+        Self { when, used }
+    }
+
+    /// Unwrap the `when` values.
+    pub fn into_inner(self) -> HashMap<&'a str, &'a str> {
+        self.when
+    }
+
+    /// Look up the value for the given `key` and mark the key as used.
+    pub fn get(&self, key: &str) -> Option<&&'a str> {
+        let value = self.when.get(key)?;
+        self.used[key].store(true, Ordering::Relaxed);
+        Some(value)
+    }
+
+    /// Check whether a value for the given `key` exists and mark the key as
+    /// used.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// The keys that were not looked up so far, in alphabetical order.
+    pub fn unused_keys(&self) -> Vec<&'a str> {
+        let mut unused = self
+            .used
+            .iter()
+            .filter(|(_, used)| !used.load(Ordering::Relaxed))
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        unused.sort();
+        unused
+    }
+
+    /// Print an `UNUSED` warning for each key that was not looked up so far
+    /// in the example with the given `name`.
+    pub fn warn_unused(&self, name: &str) {
+        for key in self.unused_keys() {
+            eprintln!("UNUSED `{key}` in {name}");
+        }
+    }
+}
+
+impl<'a> From<HashMap<&'a str, &'a str>> for TrackedWhen<'a> {
+    fn from(when: HashMap<&'a str, &'a str>) -> Self {
+        Self::new(when)
+    }
+}
+
+impl<'a> FromIterator<(&'a str, &'a str)> for TrackedWhen<'a> {
+    fn from_iter<I: IntoIterator<Item = (&'a str, &'a str)>>(iter: I) -> Self {
+        Self::new(HashMap::from_iter(iter))
+    }
+}
+
+impl<'a> Deref for TrackedWhen<'a> {
+    type Target = HashMap<&'a str, &'a str>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.when
+    }
+}
+
+impl<'a> Index<&str> for TrackedWhen<'a> {
+    type Output = &'a str;
+
+    /// Look up the value for the given `key` and mark the key as used.
+    ///
+    /// # Panics
+    ///
+    /// If the `key` is not present.
+    fn index(&self, key: &str) -> &Self::Output {
+        self.get(key).expect("`when` key not found")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unused_keys() {
+        let values = HashMap::from([("input", "1 + 2"), ("env", "x=1"), ("mode", "fast")]);
+        let when = TrackedWhen::new(values);
+        assert_eq!(when.unused_keys(), vec!["env", "input", "mode"]);
+
+        assert_eq!(when.get("inpt"), None);
+        assert!(when.contains_key("mode"));
+        assert_eq!(when.len(), 3);
+        assert_eq!(when.unused_keys(), vec!["env", "input"]);
+
+        assert_eq!(when["input"], "1 + 2");
+        assert_eq!(when.unused_keys(), vec!["env"]);
+    }
+}
@@ -0,0 +1,150 @@
+//! Find spec files that don't contribute any passing examples.
+//!
+//! Spec files tend to accumulate over time. Files that no longer contain any
+//! examples, whose examples are all skipped, or that are not matched by any
+//! [`glob_test`](crate::glob_test) pattern still look like tests but don't
+//! check anything. Collect the [`RunStats`] of each processed file and pass
+//! them to [`report`] together with the list of all spec files in order to
+//! find such files.
+//!
+//! With the `serde` feature enabled, [`CoverageReport`] also implements
+//! `serde::Serialize`.
+
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use crate::core::RunStats;
+
+/// The spec files passed to [`report`], grouped by their contribution to the
+/// test suite.
+///
+/// Each group preserves the order of the paths passed to [`report`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CoverageReport {
+    /// Files with at least one example that was run.
+    pub covered: Vec<PathBuf>,
+    /// Files that were processed but don't contain any examples.
+    pub empty: Vec<PathBuf>,
+    /// Files where every example was skipped (e.g., `(ignored)` examples).
+    pub all_skipped: Vec<PathBuf>,
+    /// Files without any [`RunStats`], i.e. files that were never processed.
+    pub never_run: Vec<PathBuf>,
+}
+
+impl CoverageReport {
+    /// Check whether every file contributed at least one example that was run.
+    pub fn is_complete(&self) -> bool {
+        self.empty.is_empty() && self.all_skipped.is_empty() && self.never_run.is_empty()
+    }
+}
+
+impl Display for CoverageReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            covered,
+            empty,
+            all_skipped,
+            never_run,
+        } = self;
+        let total = covered.len() + empty.len() + all_skipped.len() + never_run.len();
+        writeln!(
+            f,
+            "{total} spec files: {} covered, {} empty, {} all skipped, {} never run",
+            covered.len(),
+            empty.len(),
+            all_skipped.len(),
+            never_run.len(),
+        )?;
+        for (label, paths) in [
+            ("EMPTY", empty),
+            ("ALL SKIPPED", all_skipped),
+            ("NEVER RUN", never_run),
+        ] {
+            for path in paths {
+                writeln!(f, "{label} `{}`", path.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Classify each of the given spec file `paths` based on the `stats` collected
+/// while processing them.
+///
+/// Paths are compared as given, so make sure that `paths` and `stats` use the
+/// same (e.g., relative to the crate root) form. If a path occurs more than once
+/// in `stats`, the corresponding statistics are combined. Entries in `stats`
+/// for paths that are not in `paths` are ignored.
+pub fn report(paths: &[PathBuf], stats: &[(PathBuf, RunStats)]) -> CoverageReport {
+    let mut report = CoverageReport::default();
+    for path in paths {
+        let Some((total, skipped)) = combined(path, stats) else {
+            report.never_run.push(path.clone());
+            continue;
+        };
+        let group = if total == 0 {
+            &mut report.empty
+        } else if skipped == total {
+            &mut report.all_skipped
+        } else {
+            &mut report.covered
+        };
+        group.push(path.clone());
+    }
+    report
+}
+
+/// The `total` and `skipped` counts of all `stats` for the given `path`, or
+/// `None` if there are no such `stats`.
+fn combined(path: &Path, stats: &[(PathBuf, RunStats)]) -> Option<(usize, usize)> {
+    stats
+        .iter()
+        .filter(|(other, _)| other == path)
+        .map(|(_, stats)| (stats.total, stats.skipped))
+        .reduce(|(t1, s1), (t2, s2)| (t1 + t2, s1 + s2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let stats = |total, skipped| RunStats {
+            total,
+            passed: total - skipped,
+            skipped,
+            ..Default::default()
+        };
+        let paths = ["a.md", "b.md", "c.md", "d.md", "e.md"].map(PathBuf::from);
+        let stats = [
+            (PathBuf::from("a.md"), stats(2, 1)),
+            (PathBuf::from("b.md"), stats(0, 0)),
+            (PathBuf::from("c.md"), stats(1, 1)),
+            (PathBuf::from("d.md"), stats(1, 1)),
+            (PathBuf::from("d.md"), stats(1, 0)),
+            (PathBuf::from("x.md"), stats(1, 0)),
+        ];
+
+        let report = report(&paths, &stats);
+        assert_eq!(
+            report,
+            CoverageReport {
+                covered: vec![PathBuf::from("a.md"), PathBuf::from("d.md")],
+                empty: vec![PathBuf::from("b.md")],
+                all_skipped: vec![PathBuf::from("c.md")],
+                never_run: vec![PathBuf::from("e.md")],
+            }
+        );
+        assert!(!report.is_complete());
+
+        let exp = indoc::indoc! {"
+            5 spec files: 2 covered, 1 empty, 1 all skipped, 1 never run
+            EMPTY `b.md`
+            ALL SKIPPED `c.md`
+            NEVER RUN `e.md`
+        "};
+        assert_eq!(report.to_string(), exp);
+    }
+}
@@ -0,0 +1,348 @@
+//! Utilities for computing diffs between expected and actual values.
+
+/// The number of unchanged lines shown around each change.
+#[cfg(not(feature = "diff"))]
+const CONTEXT: usize = 3;
+
+/// Compute a line-based unified diff between the `expected` and the `actual`
+/// strings, using `label_expected` and `label_actual` as file names in the
+/// `---` and `+++` header lines.
+///
+/// If the `diff` feature is enabled, the diff is computed with the
+/// [`similar`](https://docs.rs/similar) crate. Otherwise, a built-in
+/// implementation that produces the same format is used.
+pub fn unified(label_expected: &str, label_actual: &str, expected: &str, actual: &str) -> String {
+    #[cfg(feature = "diff")]
+    {
+        similar::TextDiff::from_lines(expected, actual)
+            .unified_diff()
+            .header(label_expected, label_actual)
+            .to_string()
+    }
+
+    #[cfg(not(feature = "diff"))]
+    {
+        builtin::unified(label_expected, label_actual, expected, actual)
+    }
+}
+
+#[cfg(not(feature = "diff"))]
+mod builtin {
+    use std::fmt::Write;
+    use std::ops::Range;
+
+    use super::CONTEXT;
+
+    /// The maximum number of steps in each direction of a [`middle_snake`]
+    /// search, which bounds the time needed for very different values.
+    const MAX_STEPS: isize = 1000;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(super) enum Op {
+        Equal(usize, usize),
+        Delete(usize),
+        Insert(usize),
+    }
+
+    impl Op {
+        fn is_insert(&self) -> bool {
+            matches!(self, Op::Insert(_))
+        }
+
+        fn is_delete(&self) -> bool {
+            matches!(self, Op::Delete(_))
+        }
+    }
+
+    pub(super) fn unified(
+        label_expected: &str,
+        label_actual: &str,
+        expected: &str,
+        actual: &str,
+    ) -> String {
+        let old = expected.split_inclusive('\n').collect::<Vec<_>>();
+        let new = actual.split_inclusive('\n').collect::<Vec<_>>();
+        let ops = diff(&old, &new);
+
+        let mut result = String::new();
+        for (i, hunk) in hunks(&ops).into_iter().enumerate() {
+            if i == 0 {
+                writeln!(result, "--- {label_expected}").unwrap();
+                writeln!(result, "+++ {label_actual}").unwrap();
+            }
+
+            // The number of old and new lines before the hunk.
+            let old_start = ops[..hunk.start]
+                .iter()
+                .filter(|op| !op.is_insert())
+                .count();
+            let new_start = ops[..hunk.start]
+                .iter()
+                .filter(|op| !op.is_delete())
+                .count();
+
+            let ops = &ops[hunk];
+            let old_len = ops.iter().filter(|op| !op.is_insert()).count();
+            let new_len = ops.iter().filter(|op| !op.is_delete()).count();
+            writeln!(
+                result,
+                "@@ -{} +{} @@",
+                header_range(old_start, old_len),
+                header_range(new_start, new_len),
+            )
+            .unwrap();
+
+            for op in ops {
+                let (prefix, line) = match *op {
+                    Op::Equal(i, _) => (' ', old[i]),
+                    Op::Delete(i) => ('-', old[i]),
+                    Op::Insert(j) => ('+', new[j]),
+                };
+                result.push(prefix);
+                result.push_str(line);
+                if !line.ends_with('\n') {
+                    result.push_str("\n\\ No newline at end of file\n");
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Compute a minimal edit script between `old` and `new` with the
+    /// linear-space variant of Myers' algorithm.
+    pub(super) fn diff(old: &[&str], new: &[&str]) -> Vec<Op> {
+        let mut ops = Vec::with_capacity(old.len() + new.len());
+        diff_range(old, new, 0..old.len(), 0..new.len(), &mut ops);
+        ops
+    }
+
+    /// Append the edit script between `old[a]` and `new[b]` to `ops`.
+    fn diff_range(old: &[&str], new: &[&str], a: Range<usize>, b: Range<usize>, ops: &mut Vec<Op>) {
+        let (mut a, mut b) = (a, b);
+
+        // Strip the common prefix and suffix.
+        while !a.is_empty() && !b.is_empty() && old[a.start] == new[b.start] {
+            ops.push(Op::Equal(a.start, b.start));
+            a.start += 1;
+            b.start += 1;
+        }
+        let mut suffix = 0;
+        while !a.is_empty() && !b.is_empty() && old[a.end - 1] == new[b.end - 1] {
+            a.end -= 1;
+            b.end -= 1;
+            suffix += 1;
+        }
+
+        if a.is_empty() {
+            ops.extend(b.clone().map(Op::Insert));
+        } else if b.is_empty() {
+            ops.extend(a.clone().map(Op::Delete));
+        } else {
+            // The ranges differ in their first and last lines, so each side
+            // of the middle snake has fewer edits than the whole range.
+            match middle_snake(old, new, a.clone(), b.clone()) {
+                Some((x0, y0, x1, y1)) => {
+                    diff_range(old, new, a.start..x0, b.start..y0, ops);
+                    ops.extend((x0..x1).zip(y0..y1).map(|(i, j)| Op::Equal(i, j)));
+                    diff_range(old, new, x1..a.end, y1..b.end, ops);
+                }
+                None => {
+                    // Replace the whole range if it differs too much.
+                    ops.extend(a.clone().map(Op::Delete));
+                    ops.extend(b.clone().map(Op::Insert));
+                }
+            }
+        }
+
+        ops.extend((0..suffix).map(|k| Op::Equal(a.end + k, b.end + k)));
+    }
+
+    /// Find the middle snake of an optimal edit script between `old[a]` and
+    /// `new[b]` by searching forward from the start and backward from the end
+    /// at the same time. Returns the start and end points of the snake, or
+    /// `None` if the search takes more than [`MAX_STEPS`] steps.
+    fn middle_snake(
+        old: &[&str],
+        new: &[&str],
+        a: Range<usize>,
+        b: Range<usize>,
+    ) -> Option<(usize, usize, usize, usize)> {
+        let (n, m) = (a.len() as isize, b.len() as isize);
+        let delta = n - m;
+        let max = (n + m + 1) / 2;
+        let offset = max + 1;
+        let index = |k: isize| (k + offset) as usize;
+        let old_at = |x: isize| old[a.start + x as usize];
+        let new_at = |y: isize| new[b.start + y as usize];
+        let old_rev = |x: isize| old[a.end - 1 - x as usize];
+        let new_rev = |y: isize| new[b.end - 1 - y as usize];
+
+        // The furthest reaching `x` on each diagonal `k = x - y`, measured from
+        // the start (`forward`) and from the end (`backward`).
+        let mut forward = vec![0isize; 2 * offset as usize + 1];
+        let mut backward = vec![0isize; 2 * offset as usize + 1];
+        let absolute = |x: isize, y: isize| (a.start + x as usize, b.start + y as usize);
+
+        for d in 0..=isize::min(max, MAX_STEPS) {
+            for k in (-d..=d).step_by(2) {
+                let mut x = if k == -d || (k != d && forward[index(k - 1)] < forward[index(k + 1)])
+                {
+                    forward[index(k + 1)]
+                } else {
+                    forward[index(k - 1)] + 1
+                };
+                let mut y = x - k;
+                let start = (x, y);
+                while x < n && y < m && old_at(x) == new_at(y) {
+                    x += 1;
+                    y += 1;
+                }
+                forward[index(k)] = x;
+                let kb = delta - k;
+                if delta % 2 != 0 && kb.abs() < d && x + backward[index(kb)] >= n {
+                    let (start, end) = (absolute(start.0, start.1), absolute(x, y));
+                    return Some((start.0, start.1, end.0, end.1));
+                }
+            }
+            for k in (-d..=d).step_by(2) {
+                let mut x =
+                    if k == -d || (k != d && backward[index(k - 1)] < backward[index(k + 1)]) {
+                        backward[index(k + 1)]
+                    } else {
+                        backward[index(k - 1)] + 1
+                    };
+                let mut y = x - k;
+                let end = (n - x, m - y);
+                while x < n && y < m && old_rev(x) == new_rev(y) {
+                    x += 1;
+                    y += 1;
+                }
+                backward[index(k)] = x;
+                let kf = delta - k;
+                if delta % 2 == 0 && kf.abs() <= d && x + forward[index(kf)] >= n {
+                    let (start, end) = (absolute(n - x, m - y), absolute(end.0, end.1));
+                    return Some((start.0, start.1, end.0, end.1));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Group the changed `ops` together with up to [`CONTEXT`] surrounding
+    /// unchanged lines into hunks.
+    fn hunks(ops: &[Op]) -> Vec<Range<usize>> {
+        let mut hunks = Vec::<Range<usize>>::new();
+        for (k, op) in ops.iter().enumerate() {
+            if matches!(op, Op::Equal(..)) {
+                continue;
+            }
+            let start = k.saturating_sub(CONTEXT);
+            let end = usize::min(k + 1 + CONTEXT, ops.len());
+            match hunks.last_mut() {
+                Some(hunk) if hunk.end >= start => hunk.end = end,
+                _ => hunks.push(start..end),
+            }
+        }
+        hunks
+    }
+
+    /// Format a hunk header range in the GNU diff format.
+    fn header_range(start: usize, len: usize) -> String {
+        match len {
+            0 => format!("{start},0"),
+            1 => format!("{}", start + 1),
+            _ => format!("{},{len}", start + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified() {
+        let expected = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let actual = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk";
+
+        let exp = indoc::indoc! {r"
+            --- expected
+            +++ actual
+            @@ -1,5 +1,5 @@
+             a
+            -b
+            +B
+             c
+             d
+             e
+            @@ -8,3 +8,4 @@
+             h
+             i
+             j
+            +k
+            \ No newline at end of file
+        "};
+        assert_eq!(unified("expected", "actual", expected, actual), exp);
+
+        assert_eq!(unified("expected", "actual", expected, expected), "");
+    }
+
+    #[cfg(not(feature = "diff"))]
+    #[test]
+    fn test_builtin_diff() {
+        // Lines of pseudo-random values from a small alphabet.
+        let mut seed = 1u64;
+        let mut lines = |len: u64| {
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    format!("{}\n", (seed >> 33) % 4)
+                })
+                .collect::<String>()
+        };
+
+        for len in 0..40 {
+            let (old, new) = (lines(len), lines(40 - len));
+            let old = old.split_inclusive('\n').collect::<Vec<_>>();
+            let new = new.split_inclusive('\n').collect::<Vec<_>>();
+            let ops = builtin::diff(&old, &new);
+
+            // The ops are a valid edit script.
+            let mut restored = (Vec::new(), Vec::new());
+            for op in ops.iter() {
+                match *op {
+                    builtin::Op::Equal(i, j) => {
+                        assert_eq!(old[i], new[j]);
+                        restored.0.push(i);
+                        restored.1.push(j);
+                    }
+                    builtin::Op::Delete(i) => restored.0.push(i),
+                    builtin::Op::Insert(j) => restored.1.push(j),
+                }
+            }
+            assert_eq!(restored.0, (0..old.len()).collect::<Vec<_>>());
+            assert_eq!(restored.1, (0..new.len()).collect::<Vec<_>>());
+
+            // The script is minimal, i.e. it keeps a longest common subsequence.
+            let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+            for i in (0..old.len()).rev() {
+                for j in (0..new.len()).rev() {
+                    lcs[i][j] = match old[i] == new[j] {
+                        true => lcs[i + 1][j + 1] + 1,
+                        false => usize::max(lcs[i + 1][j], lcs[i][j + 1]),
+                    };
+                }
+            }
+            let equal = ops.iter().filter(|op| matches!(op, builtin::Op::Equal(..)));
+            assert_eq!(equal.count(), lcs[0][0]);
+        }
+
+        // Large, completely different values are diffed in reasonable time.
+        let expected = (0..20_000).map(|i| format!("a{i}\n")).collect::<String>();
+        let actual = (0..20_000).map(|i| format!("b{i}\n")).collect::<String>();
+        let diff = unified("expected", "actual", &expected, &actual);
+        assert_eq!(diff.lines().count(), 40_003);
+    }
+}
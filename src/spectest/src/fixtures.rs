@@ -0,0 +1,248 @@
+//! Ready-made handlers for common spec patterns.
+//!
+//! Each handler implements both [`Handler`] and [`AsyncHandler`] (except for
+//! [`CommandHandler`], which implements [`AsyncHandler`] only if the `tokio`
+//! feature is enabled), and only sets the [`Example::then`] values that are
+//! defined in the spec file.
+
+use std::process::Output;
+
+use crate::core::{AsyncHandler, Example, Handler};
+
+/// A handler that runs the `input` value of each example as a shell command
+/// and stores its standard output in the `output` value.
+///
+/// Commands are run with `sh -c` (or `cmd /C` on Windows) in the current
+/// working directory. Commands that exit with a non-zero status fail with an
+/// error that contains their standard error output.
+///
+/// The [`AsyncHandler`] implementation requires the `tokio` feature and has to
+/// be driven by a `tokio` runtime.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CommandHandler;
+
+impl CommandHandler {
+    fn command<C: From<std::process::Command>>(input: &str) -> C {
+        let mut command = if cfg!(windows) {
+            let mut command = std::process::Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = std::process::Command::new("sh");
+            command.arg("-c");
+            command
+        };
+        command.arg(input);
+        C::from(command)
+    }
+
+    fn input<'a>(example: &Example<'a>) -> Result<&'a str, String> {
+        let Some(input) = example.when.get("input") else {
+            return Err("missing `input` definition in the 'When' spec".to_string());
+        };
+        Ok(input)
+    }
+
+    fn output(example: &mut Example, output: std::io::Result<Output>) -> Result<(), String> {
+        let output = output.map_err(|err| format!("cannot run command: {err}"))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("command failed with {}: {stderr}", output.status));
+        }
+        if expects(example, "output") {
+            let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+            example.then.insert("output", stdout);
+        }
+        Ok(())
+    }
+}
+
+impl Handler for CommandHandler {
+    type Error = String;
+
+    fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+        let input = Self::input(example)?;
+        let output = Self::command::<std::process::Command>(input).output();
+        Self::output(example, output)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncHandler for CommandHandler {
+    type Error = String;
+
+    async fn example(&mut self, example: &mut Example<'_>) -> Result<(), Self::Error> {
+        let input = Self::input(example)?;
+        let output = Self::command::<tokio::process::Command>(input).output();
+        Self::output(example, output.await)
+    }
+}
+
+/// A handler that copies each `when` value into the `then` value with the same
+/// key.
+///
+/// This is mostly useful for testing spec files and tools built on top of
+/// `spectest` itself.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IdentityHandler;
+
+impl IdentityHandler {
+    fn copy(example: &mut Example) {
+        for (key, value) in example.when.iter() {
+            if expects(example, key) {
+                example.then.insert(key, value.to_string());
+            }
+        }
+    }
+}
+
+impl Handler for IdentityHandler {
+    type Error = String;
+
+    fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+        Self::copy(example);
+        Ok(())
+    }
+}
+
+impl AsyncHandler for IdentityHandler {
+    type Error = String;
+
+    async fn example(&mut self, example: &mut Example<'_>) -> Result<(), Self::Error> {
+        Self::copy(example);
+        Ok(())
+    }
+}
+
+/// A handler that concatenates all `when` values (ordered by key) and stores
+/// the result in the `output` value.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EchoHandler;
+
+impl EchoHandler {
+    fn echo(example: &mut Example) {
+        if expects(example, "output") {
+            let mut when = example.when.iter().collect::<Vec<_>>();
+            when.sort();
+            let output = when.into_iter().map(|(_, value)| *value).collect();
+            example.then.insert("output", output);
+        }
+    }
+}
+
+impl Handler for EchoHandler {
+    type Error = String;
+
+    fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+        Self::echo(example);
+        Ok(())
+    }
+}
+
+impl AsyncHandler for EchoHandler {
+    type Error = String;
+
+    async fn example(&mut self, example: &mut Example<'_>) -> Result<(), Self::Error> {
+        Self::echo(example);
+        Ok(())
+    }
+}
+
+/// Check whether the spec defines a (possibly optional) `then` value with the
+/// given `key`.
+fn expects(example: &Example, key: &str) -> bool {
+    example.then.contains_key(key) || example.optional_then.contains(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::examples::*;
+    use crate::core::{async_process, process, Error};
+
+    #[test]
+    fn test_identity_handler() -> std::io::Result<()> {
+        let spec = indoc::indoc! {r"
+            ## Example: Identity
+
+            When `x` is:
+
+            ```
+            1
+            ```
+
+            Then `x` is:
+
+            ```
+            1
+            ```
+        "};
+        let path = write_spec(spec)?;
+
+        process(&path, &mut IdentityHandler).expect("`process` call completes cleanly");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_echo_handler() -> std::io::Result<()> {
+        let spec = indoc::indoc! {r"
+            ## Example: Echo
+
+            When `b` is:
+
+            ```
+            world
+            ```
+
+            And `a` is:
+
+            ```
+            hello
+            ```
+
+            Then `output` is:
+
+            ```
+            hello
+            world
+            ```
+        "};
+        let path = write_spec(spec)?;
+
+        process(&path, &mut EchoHandler).expect("`process` call completes cleanly");
+        let result = async_process(&path, &mut EchoHandler).await;
+        result.expect("`async_process` call completes cleanly");
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_handler() -> std::io::Result<()> {
+        let spec = |input: &str| {
+            format!(
+                "## Example: Command\n\n\
+                 When `input` is:\n\n```\n{input}\n```\n\n\
+                 Then `output` is:\n\n```\nhello\n```\n"
+            )
+        };
+
+        let path = write_spec(&spec("echo hello"))?;
+        process(&path, &mut CommandHandler).expect("`process` call completes cleanly");
+        #[cfg(feature = "tokio")]
+        {
+            let result = async_process(&path, &mut CommandHandler).await;
+            result.expect("`async_process` call completes cleanly");
+        }
+
+        let path = write_spec(&spec("echo oops >&2; exit 3"))?;
+        let result = process(&path, &mut CommandHandler);
+        let Err(Error::Handler { error: err, .. }) = result else {
+            panic!("expected a handler error, got {result:?}");
+        };
+        assert!(err.ends_with(": oops\n"), "unexpected error: {err}");
+
+        Ok(())
+    }
+}
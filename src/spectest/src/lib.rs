@@ -88,11 +88,167 @@ use std::ops::Range;
 use pulldown_cmark::Event;
 
 pub mod core;
+pub mod coverage;
+pub mod diff;
+pub mod fixtures;
 pub mod md;
+pub mod normalize;
+pub mod owned;
+#[cfg(feature = "proptest")]
+pub mod proptest;
+pub mod reporters;
+pub mod testing;
+#[cfg(feature = "toml")]
+pub mod toml;
+#[cfg(feature = "watch")]
+pub mod watch;
 
-pub use core::{async_run, run, AsyncHandler, Background, Error, Example, Handler};
+#[cfg(feature = "toml")]
+pub use crate::toml::run_toml;
+#[cfg(feature = "watch")]
+pub use crate::watch::watch;
+#[cfg(feature = "regex")]
+pub use core::RegexComparator;
+pub use core::{
+    async_run, chain, run, run_debug, run_many, run_parallel, sections, AsyncHandler, Background,
+    ChainedHandler, Comparator, Config, DynHandler, Error, ExactComparator, Example, ExampleInfo,
+    ExampleOutcome, Handler, Handler2, HeadingLevel, NormalizedWhitespaceComparator,
+    PerKeyComparator, RewriteStats, RunStats, Section, SectionsIter, StatsError, TrackedWhen,
+    TrailingNewlineComparator, TrimmedComparator,
+};
+pub use md::MdDocument;
 #[cfg(feature = "macros")]
-pub use spectest_macros::glob_test;
+pub use spectest_macros::{glob_test, spec_file};
+
+/// Assert that a [`Handler`] produces the expected `then` values for the given
+/// `when` values.
+///
+/// This processes a single inline [`Example`] with [`core::check_example`] and
+/// panics if the handler fails or if a `then` value doesn't match exactly. The
+/// example is named after the location of the macro call.
+///
+/// ```
+/// use spectest::fixtures::IdentityHandler;
+///
+/// spectest::assert_example!(
+///     when: { "x" => "1", "y" => "2" },
+///     then: { "y" => "2" },
+///     handler = IdentityHandler,
+/// );
+/// ```
+#[macro_export]
+macro_rules! assert_example {
+    (
+        when: { $($when_key:expr => $when_value:expr),* $(,)? },
+        then: { $($then_key:expr => $then_value:expr),* $(,)? },
+        handler = $handler:expr $(,)?
+    ) => {{
+        let mut handler = $handler;
+        let name = concat!("Example: ", file!(), ":", line!());
+        let when = &[$(($when_key, $when_value)),*];
+        let then = &[$(($then_key, $then_value)),*];
+        if let Err(err) = $crate::core::check_example(&mut handler, name, when, then) {
+            panic!("{err}");
+        }
+    }};
+}
+
+/// Define a spec inline as a Markdown string instead of a separate file.
+///
+/// This returns a [`core::InlineSpec`] named after the location of the macro
+/// call, which can be processed with [`run`](core::InlineSpec::run) or
+/// [`process`](core::InlineSpec::process). The Markdown is parsed when the spec
+/// is processed, so malformed specs are reported at runtime.
+///
+/// ```
+/// use spectest::fixtures::IdentityHandler;
+///
+/// spectest::inline_spec!(indoc::indoc! {"
+///     ## Example: Identity
+///
+///     When `x` is:
+///
+///     ```
+///     1
+///     ```
+///
+///     Then `x` is:
+///
+///     ```
+///     1
+///     ```
+/// "})
+/// .run(&mut IdentityHandler);
+/// ```
+#[macro_export]
+macro_rules! inline_spec {
+    ($source:expr $(,)?) => {
+        $crate::core::InlineSpec::new(concat!(file!(), ":", line!()), $source)
+    };
+}
+
+/// Assert that the `actual` value of the given `key` in an [`Example`] matches
+/// an [`insta`](https://docs.rs/insta) snapshot named after the spec file, the
+/// example heading, and the key.
+///
+/// This allows handlers to store selected values in `insta` snapshot files
+/// instead of the spec file. The snapshots are resolved relative to the file
+/// that calls the macro and can be reviewed with `cargo insta review`.
+///
+/// Requires the `insta` feature.
+///
+/// ```ignore
+/// fn example(&mut self, example: &mut spectest::Example) -> Result<(), Self::Error> {
+///     let plan = self.explain(&example.when["query"]);
+///     spectest::assert_snapshot!(example, "plan", plan);
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "insta")]
+#[macro_export]
+macro_rules! assert_snapshot {
+    ($example:expr, $key:expr, $actual:expr $(,)?) => {
+        $crate::__private::insta::assert_snapshot!(
+            $crate::__private::snapshot_name(&$example, $key),
+            $actual
+        )
+    };
+}
+
+#[cfg(feature = "insta")]
+#[doc(hidden)]
+pub mod __private {
+    pub use insta;
+
+    use crate::Example;
+
+    /// The name of the snapshot for the given `key` in the given `example`.
+    ///
+    /// The name consists of the stem of the spec file, the full heading of the
+    /// example, and the `key`. Characters that are not valid in file names are
+    /// replaced by `_`, so the name ends with a hash of the original parts in
+    /// order to tell apart headings such as `a*b` and `a/b`.
+    pub fn snapshot_name<T>(example: &Example<'_, T>, key: &str) -> String {
+        let stem = example.path.and_then(|path| path.file_stem());
+        let stem = stem.map(|stem| stem.to_string_lossy()).unwrap_or_default();
+        let name = format!("{stem}__{}__{key}", example.heading);
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        let sanitized = name.chars().map(|c| if valid(c) { c } else { '_' });
+        let sanitized = sanitized.collect::<String>().to_lowercase();
+        format!("{sanitized}-{:08x}", fnv1a(name.as_bytes()) as u32)
+    }
+
+    /// The 64-bit FNV-1a hash of the given `bytes`, which (unlike the hashers
+    /// in `std`) is stable across Rust versions and platforms.
+    fn fnv1a(bytes: &[u8]) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        hash
+    }
+}
 
 // Common private helper types
 // ===========================
@@ -112,6 +268,13 @@ fn span<'a>(token: &'a Token<'_>) -> &'a Range<usize> {
     &token.1
 }
 
+/// Check if `text` starts with a spec `keyword` such as `Example:` or `Given `,
+/// ignoring ASCII case.
+fn starts_with_keyword(text: &str, keyword: &str) -> bool {
+    text.get(..keyword.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(keyword))
+}
+
 /// Print a tokens sequence for debugging purposes.
 #[allow(unused)]
 pub(crate) fn debug(tag: &str, tokens: &[Token<'_>]) {
@@ -1,21 +1,90 @@
 //! Support for BDD-files written in Markdown.
 
+mod include;
 pub(crate) mod reader;
 pub(crate) mod writer;
 
-use crate::Token;
+use std::fmt::{Debug, Display};
+use std::ops::Range;
+
+use pulldown_cmark::{CowStr, Event, Tag};
+
+pub use self::include::ExpandedSource;
+use crate::{event, Token};
 
 /// A parsed version of a Markdown source.
 ///
 /// The struct is opaque and encapsulates the result of parsing at the Markdown
-/// level. See the contents of [`crate::spec`] for extracting sections from an
-/// [`MdDocument`] instance.
+/// level. Use [`sections`](crate::sections) in order to extract the spec
+/// sections from an [`MdDocument`] instance.
+///
+/// The [`Debug`] representation shows the number of sections and tokens in the
+/// document, and the [`Display`] representation lists the heading level, the
+/// kind, and the name of each section.
 pub struct MdDocument<'input> {
     pub(crate) tokens: Vec<Token<'input>>,
+    /// The byte ranges of included contents in the source (see
+    /// [`ExpandedSource`]), which are skipped when writing the document.
+    pub(crate) included: Vec<Range<usize>>,
+}
+
+impl<'input> MdDocument<'input> {
+    /// The Markdown events of the document together with their byte range in
+    /// the source string.
+    ///
+    /// Changes to the events are reflected when the document is written back
+    /// (see [`MdDocument::write_to_string`]). The source ranges are not
+    /// updated.
+    pub fn tokens_mut(&mut self) -> &mut [(Event<'input>, Range<usize>)] {
+        &mut self.tokens
+    }
+
+    /// Replace the content of the `Text` or `Code` event at the given `idx`
+    /// with `new_text`.
+    ///
+    /// Returns `false` and leaves the document unchanged if the event at `idx`
+    /// is not a `Text` or a `Code` event.
+    ///
+    /// # Panics
+    ///
+    /// If `idx` is out of bounds.
+    pub fn set_token_text(&mut self, idx: usize, new_text: impl Into<CowStr<'input>>) -> bool {
+        match &mut self.tokens[idx].0 {
+            Event::Text(text) | Event::Code(text) => {
+                *text = new_text.into();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Debug for MdDocument<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sections = self
+            .tokens
+            .iter()
+            .filter(|token| matches!(event(token), Event::Start(Tag::Heading { .. })));
+        f.debug_struct("MdDocument")
+            .field("sections", &sections.count())
+            .field("tokens", &self.tokens.len())
+            .finish()
+    }
+}
+
+impl Display for MdDocument<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut tokens = self.tokens.clone();
+        for (level, kind, name) in crate::core::outline(&mut tokens) {
+            writeln!(f, "{level} [{kind}] {name}")?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod roundtrip_tests {
+    use pulldown_cmark::Event;
     use spectest_macros::glob_test;
 
     use crate::core;
@@ -35,4 +104,128 @@ mod roundtrip_tests {
 
         assert_eq!(&md_src, &md_out)
     }
+
+    #[test]
+    fn test_debug_and_display() {
+        let md_src = indoc::indoc! {r"
+            # Feature: `meval`
+
+            ## Background
+
+            ## Example: Addition
+
+            ## Example: Table
+
+            When `a` is:
+
+            | a |
+            | - |
+            | 1 |
+
+            ## Example: Notes
+
+            | a |
+            | - |
+            | 1 |
+        "};
+        let md_doc = md::MdDocument::from_string(md_src);
+
+        assert_eq!(
+            format!("{md_doc:?}"),
+            format!(
+                "MdDocument {{ sections: 5, tokens: {} }}",
+                md_doc.tokens.len()
+            )
+        );
+        assert_eq!(
+            md_doc.to_string(),
+            indoc::indoc! {r"
+                h1 [raw] Feature: meval
+                h2 [background] Background
+                h2 [example] Example: Addition
+                h2 [tabular] Example: Table
+                h2 [example] Example: Notes
+            "}
+        );
+    }
+
+    #[test]
+    fn test_set_token_text() {
+        let md_src = "## Example: `add`\n\nGiven `x` as:\n\n```\n5\n```\n";
+        let mut md_doc = md::MdDocument::from_string(md_src);
+
+        let find = |md_doc: &mut md::MdDocument, text: &str| {
+            let mut tokens = md_doc.tokens_mut().iter();
+            tokens.position(
+                |(event, _)| matches!(event, Event::Text(t) | Event::Code(t) if t.as_ref() == text),
+            )
+        };
+        let name = find(&mut md_doc, "add").expect("heading code");
+        let value = find(&mut md_doc, "5\n").expect("code block text");
+        assert!(md_doc.set_token_text(name, "sub"));
+        assert!(md_doc.set_token_text(value, String::from("7\n")));
+        assert!(!md_doc.set_token_text(0, "heading"));
+
+        let md_out = md_doc.write_to_string().expect("output string");
+        assert_eq!(
+            md_out,
+            "## Example: `sub`\n\nGiven `x` as:\n\n```\n7\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_create_spec_file() -> std::io::Result<()> {
+        let md_src = "## Example: New\n\nWhen `x` is:\n\n```\n1\n```\n";
+        let dir = tempfile::tempdir()?;
+
+        let path = dir.path().join("written.md");
+        let md_doc = md::MdDocument::from_string(md_src);
+        md_doc.write_to_path(&path).expect("written file");
+        assert_eq!(core::read_to_string(&path)?, md_src);
+
+        let path = dir.path().join("created.md");
+        let md_doc = md::MdDocument::from_string(md_src);
+        md_doc.create_spec_file(&path).expect("created file");
+        assert_eq!(core::read_to_string(&path)?, md_src);
+
+        let md_doc = md::MdDocument::from_string(md_src);
+        let result = md_doc.create_spec_file(&path);
+        let Err(md::writer::Error::IO(err)) = result else {
+            panic!("expected an IO error, got {result:?}");
+        };
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_soft_break() {
+        let md_src = "Given `x`\nas:\n\n```\n1\n```\n";
+        let md_doc = md::MdDocument::from_string(md_src);
+        let md_out = md_doc.write_to_string().expect("output string");
+        assert_eq!(md_out, md_src);
+
+        // The re-parsed paragraph still separates the words with a soft break.
+        let mut md_doc = md::MdDocument::from_string(&md_out);
+        let events = md_doc.tokens_mut().iter().map(|(event, _)| event);
+        let events = events.skip(1).take(4).cloned().collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                Event::Text("Given ".into()),
+                Event::Code("x".into()),
+                Event::SoftBreak,
+                Event::Text("as:".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_indented_code_block() {
+        let md_src = "Code:\n\n    fn main() {}\n\n    // end\n";
+        let md_doc = md::MdDocument::from_string(md_src);
+        let md_out = md_doc.write_to_string().expect("output string");
+
+        assert_eq!(md_out, "Code:\n\n```\nfn main() {}\n\n// end\n```\n");
+    }
 }
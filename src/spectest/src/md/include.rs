@@ -0,0 +1,205 @@
+//! Support for `<!-- include: path/to/shared.md -->` directives.
+
+use std::io::{Error, ErrorKind};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::{Event, Parser, Tag};
+
+use super::MdDocument;
+use crate::core::read_to_string;
+
+/// The source of a spec file with the contents of the files referenced by its
+/// include directives spliced in.
+///
+/// An include directive is an HTML comment on its own, for example:
+///
+/// ```markdown
+/// <!-- include: ../shared/database.md -->
+/// ```
+///
+/// The referenced file is resolved relative to the directory of the including
+/// file, and its contents are inserted right after the directive. Included
+/// files can include other files, but not (transitively) themselves.
+///
+/// Use [`MdDocument::from_expanded`] in order to parse the expanded source.
+/// When the document is written back, the included contents are skipped, so
+/// the directives are preserved and the included files are never changed.
+#[derive(Debug, Clone)]
+pub struct ExpandedSource {
+    /// The source with the included contents.
+    source: String,
+    /// The original source, if it differs from `source`.
+    original: Option<String>,
+    /// The byte ranges of the included contents in `source`.
+    included: Vec<Range<usize>>,
+}
+
+impl ExpandedSource {
+    /// Read the spec file at `path` and expand its include directives.
+    ///
+    /// # Errors
+    ///
+    /// - When the spec file or one of the included files can't be read.
+    /// - When a file includes itself, directly or through other files.
+    pub fn read<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let original = read_to_string(path)?;
+        let mut stack = vec![path.canonicalize()?];
+        let mut included = Vec::new();
+        let source = splice(path, &original, &mut stack, &mut included)?;
+        Ok(Self {
+            original: (source != original).then_some(original),
+            source,
+            included,
+        })
+    }
+
+    /// The source with the included contents. Positions in reader errors
+    /// refer to this string.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The original source of the spec file.
+    pub fn original(&self) -> &str {
+        self.original.as_deref().unwrap_or(&self.source)
+    }
+}
+
+/// A source without include directives, such as an inline spec.
+impl From<&str> for ExpandedSource {
+    fn from(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            original: None,
+            included: Vec::new(),
+        }
+    }
+}
+
+impl<'input> MdDocument<'input> {
+    /// Create an [`MdDocument`] from an [`ExpandedSource`].
+    #[must_use = "parsed MdDocument is discarded; did you forget `let doc = ...`?"]
+    pub fn from_expanded(source: &'input ExpandedSource) -> Self {
+        let mut md_doc = Self::from_string(&source.source);
+        md_doc.included = source.included.clone();
+        md_doc
+    }
+}
+
+/// Expand the include directives in the `source` of the file at `path`,
+/// recording the byte ranges of the top-level included contents in
+/// `included`. The `stack` holds the canonical paths of the including files.
+fn splice(
+    path: &Path,
+    source: &str,
+    stack: &mut Vec<PathBuf>,
+    included: &mut Vec<Range<usize>>,
+) -> std::io::Result<String> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut output = String::with_capacity(source.len());
+    let mut pos = 0;
+    for (end, target) in directives(source) {
+        let target = dir.join(target);
+        let canonical = target.canonicalize().map_err(|err| {
+            let msg = format!(
+                "can't include `{}` from `{}`: {err}",
+                target.display(),
+                path.display()
+            );
+            Error::new(err.kind(), msg)
+        })?;
+        if stack.contains(&canonical) {
+            let cycle = stack.iter().chain([&canonical]);
+            let cycle = cycle.map(|path| format!("`{}`", path.display()));
+            let msg = format!("include cycle: {}", cycle.collect::<Vec<_>>().join(" -> "));
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+
+        stack.push(canonical);
+        let contents = read_to_string(&target)?;
+        let contents = splice(&target, &contents, stack, &mut Vec::new())?;
+        stack.pop();
+
+        // Separate the included contents from the surrounding blocks.
+        output.push_str(&source[pos..end]);
+        let start = output.len();
+        output.push('\n');
+        output.push_str(&contents);
+        if !contents.ends_with('\n') {
+            output.push('\n');
+        }
+        output.push('\n');
+        included.push(start..output.len());
+        pos = end;
+    }
+    output.push_str(&source[pos..]);
+    Ok(output)
+}
+
+/// The end offsets and the paths of the include directives in `source`.
+fn directives(source: &str) -> Vec<(usize, &str)> {
+    let mut directives = Vec::new();
+    for (event, span) in Parser::new(source).into_offset_iter() {
+        if let Event::Start(Tag::HtmlBlock) = event {
+            if let Some(target) = directive(&source[span.clone()]) {
+                directives.push((span.end, target));
+            }
+        }
+    }
+    directives
+}
+
+/// The path of an HTML `block` that is an include directive.
+fn directive(block: &str) -> Option<&str> {
+    let comment = block.trim().strip_prefix("<!--")?.strip_suffix("-->")?;
+    let target = comment.trim().strip_prefix("include:")?.trim();
+    (!target.is_empty()).then_some(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expanded_source() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("shared"))?;
+        let shared = "# Background\n\nGiven `x` as:\n\n```\n1\n```\n";
+        std::fs::write(dir.path().join("shared/x.md"), shared)?;
+        let nested = "<!-- include: x.md -->\n";
+        std::fs::write(dir.path().join("shared/nested.md"), nested)?;
+        let spec = "<!-- include: shared/nested.md -->\n\n## Example: A\n";
+        std::fs::write(dir.path().join("spec.md"), spec)?;
+
+        let expanded = ExpandedSource::read(dir.path().join("spec.md"))?;
+        assert_eq!(expanded.original(), spec);
+        assert!(expanded.source().contains(shared));
+        assert!(expanded.source().ends_with("## Example: A\n"));
+
+        // The included contents are skipped when writing the document back.
+        let md_doc = MdDocument::from_expanded(&expanded);
+        assert_eq!(md_doc.to_string().lines().count(), 2);
+        let output = md_doc.write_to_string().expect("valid document");
+        assert!(
+            output.contains("<!-- include: shared/nested.md -->"),
+            "{output}"
+        );
+        assert!(!output.contains("Background"), "{output}");
+
+        std::fs::write(
+            dir.path().join("shared/x.md"),
+            "<!-- include: nested.md -->\n",
+        )?;
+        let err = ExpandedSource::read(dir.path().join("spec.md")).expect_err("cycle");
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("include cycle: "), "{err}");
+
+        std::fs::write(dir.path().join("spec.md"), "<!-- include: missing.md -->\n")?;
+        let err = ExpandedSource::read(dir.path().join("spec.md")).expect_err("missing");
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(err.to_string().contains("missing.md"), "{err}");
+        Ok(())
+    }
+}
@@ -1,20 +1,38 @@
 //! Utilities for reading [`MdDocument`] documents.
 
-use pulldown_cmark::{Options, Parser};
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag, TagEnd};
 
 use super::MdDocument;
 
 impl<'input> MdDocument<'input> {
     /// Create an [`MdDocument`] from a `source` string.
+    #[must_use = "parsed MdDocument is discarded; did you forget `let doc = ...`?"]
     pub fn from_string(source: &'input str) -> Self {
         // Set up options and parser.
         let mut options = Options::empty();
         options.insert(Options::ENABLE_STRIKETHROUGH);
+        // Tables are only interpreted in `Tabular` sections, but they are parsed
+        // (and written back by the writer) everywhere.
+        options.insert(Options::ENABLE_TABLES);
+        options.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
+        options.insert(Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS);
         let md_reader = Parser::new_ext(source, options);
 
-        // Tokenize input
-        let tokens = md_reader.into_offset_iter().collect::<Vec<_>>();
+        // Tokenize input, adding an empty `Text` event to each empty code block
+        // so that its (empty) value can be read and rewritten like any other.
+        let mut tokens = Vec::new();
+        for (event, span) in md_reader.into_offset_iter() {
+            if let (Event::End(TagEnd::CodeBlock), Some((Event::Start(Tag::CodeBlock(_)), _))) =
+                (&event, tokens.last())
+            {
+                tokens.push((Event::Text(CowStr::Borrowed("")), span.start..span.start));
+            }
+            tokens.push((event, span));
+        }
 
-        Self { tokens }
+        Self {
+            tokens,
+            included: Vec::new(),
+        }
     }
 }
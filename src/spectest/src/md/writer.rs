@@ -5,7 +5,9 @@ use std::io::Write;
 use std::path::Path;
 
 use fs2::FileExt;
-use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Tag, TagEnd};
+use pulldown_cmark::{
+    Alignment, CodeBlockKind, Event, HeadingLevel, MetadataBlockKind, Tag, TagEnd,
+};
 use thiserror::Error;
 
 use super::MdDocument;
@@ -46,6 +48,7 @@ macro_rules! unsupported_tag {
 
 impl<'input> MdDocument<'input> {
     /// Consume an [`MdDocument`] and write it back into a [`String`].
+    #[must_use = "the written Markdown is discarded"]
     pub fn write_to_string(self) -> Result<String, Error> {
         let mut md_writer = MdWriter::new(Vec::new());
         md_writer.write(self)?;
@@ -54,33 +57,78 @@ impl<'input> MdDocument<'input> {
     }
 
     /// Consume an [`MdDocument`] and write it back into the given `path`.
+    ///
+    /// The file is created if it doesn't exist yet.
     pub fn write_to_path<P>(self, path: P) -> Result<(), Error>
     where
         P: AsRef<Path>,
     {
         let mut md_writer = MdWriter::new(Vec::new());
         md_writer.write(self)?;
+        write_locked(path, md_writer.out.write.as_ref())
+    }
 
-        // Explicitly open with `OpenOptions` in order to avoid truncating the
-        // file before obtaining the lock.
-        let mut file = OpenOptions::new().write(true).open(&path)?;
+    /// Consume an [`MdDocument`] and write it into a new spec file at the
+    /// given `path`.
+    ///
+    /// Unlike [`write_to_path`](Self::write_to_path), this fails with an
+    /// [`AlreadyExists`](std::io::ErrorKind::AlreadyExists) error if the file
+    /// exists, so an existing spec file is never overwritten.
+    pub fn create_spec_file<P>(self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut md_writer = MdWriter::new(Vec::new());
+        md_writer.write(self)?;
+        // The file is locked right after creating it, so other processes can
+        // only observe it as empty or complete.
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
         file.lock_exclusive()?;
-        file.set_len(0)?;
-        file.write_all(md_writer.out.write.as_ref())?;
+        file.write_all(&md_writer.out.write)?;
 
         Ok(())
     }
 }
 
+/// Replace the contents of the file at `path` with `bytes` while holding an
+/// exclusive lock on the file. The file is created if it doesn't exist yet.
+pub(crate) fn write_locked<P: AsRef<Path>>(path: P, bytes: &[u8]) -> Result<(), Error> {
+    // Explicitly open with `OpenOptions` in order to avoid truncating the
+    // file before obtaining the lock.
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+    file.lock_exclusive()?;
+    file.set_len(0)?;
+    file.write_all(bytes)?;
+
+    Ok(())
+}
+
 pub struct MdWriter<W> {
     /// Output writer.
     out: Out<W>,
+    /// Column alignments of the table that is currently being written.
+    alignments: Vec<Alignment>,
+    /// Whether a table cell is currently being written.
+    in_table_cell: bool,
 }
 
 impl<W> MdWriter<W> {
     fn new(write: W) -> Self {
         Self {
-            out: Out { write, bytes: 0 },
+            out: Out {
+                write,
+                bytes: 0,
+                line_start: true,
+            },
+            alignments: Vec::new(),
+            in_table_cell: false,
         }
     }
 
@@ -88,7 +136,15 @@ impl<W> MdWriter<W> {
     where
         W: Write,
     {
-        for (event, _span) in input.tokens {
+        for (event, span) in input.tokens {
+            // Included contents are written to their own files.
+            if input
+                .included
+                .iter()
+                .any(|range| range.contains(&span.start))
+            {
+                continue;
+            }
             self.write_event(event)?;
         }
         Ok(())
@@ -106,12 +162,15 @@ impl<W> MdWriter<W> {
                 self.end(tag)?;
             }
             Event::Text(str) => {
-                self.out.write_all(str.as_bytes())?;
+                self.out.write_all(self.cell_escaped(&str).as_bytes())?;
             }
             Event::Code(str) => {
-                self.out.write_all("`".as_ref())?;
-                self.out.write_all(str.as_bytes())?;
-                self.out.write_all("`".as_ref())?;
+                let (fence, padding) = Self::code_span(&str);
+                self.out.write_all(fence.as_bytes())?;
+                self.out.write_all(padding.as_bytes())?;
+                self.out.write_all(self.cell_escaped(&str).as_bytes())?;
+                self.out.write_all(padding.as_bytes())?;
+                self.out.write_all(fence.as_bytes())?;
             }
             Event::InlineMath(_) => {
                 unsupported_event!("InlineMath");
@@ -129,6 +188,11 @@ impl<W> MdWriter<W> {
                 unsupported_event!("FootnoteReference");
             }
             Event::SoftBreak => {
+                // Keep the line break of the source. A newline inside a
+                // paragraph is parsed as a `SoftBreak` again (i.e., rendered
+                // as a space), so this preserves both the meaning and the
+                // layout of the spec file, whereas writing a space would
+                // reflow the paragraph on every rewrite.
                 self.out.write_all("\n".as_ref())?;
             }
             Event::HardBreak => {
@@ -160,7 +224,10 @@ impl<W> MdWriter<W> {
                 unsupported_tag!("BlockQuote");
             }
             Tag::CodeBlock(CodeBlockKind::Indented) => {
-                unsupported_tag!("CodeBlock(CodeBlockKind::Indented)");
+                // Write indented code blocks as fenced code blocks without an
+                // info string.
+                self.out.write_separator()?;
+                self.out.write_all("```\n".as_ref())?;
             }
             Tag::CodeBlock(CodeBlockKind::Fenced(html)) => {
                 self.out.write_separator()?;
@@ -180,17 +247,19 @@ impl<W> MdWriter<W> {
             Tag::FootnoteDefinition(_) => {
                 unsupported_tag!("FootnoteDefinition");
             }
-            Tag::Table(_) => {
-                unsupported_tag!("Table");
+            Tag::Table(alignments) => {
+                self.out.write_separator()?;
+                self.alignments = alignments;
             }
             Tag::TableHead => {
-                unsupported_tag!("TableHead");
+                // Do nothing.
             }
             Tag::TableRow => {
-                unsupported_tag!("TableRow");
+                // Do nothing.
             }
             Tag::TableCell => {
-                unsupported_tag!("TableCell");
+                self.out.write_all("| ".as_ref())?;
+                self.in_table_cell = true;
             }
             Tag::Emphasis => {
                 self.out.write_all("_".as_ref())?;
@@ -207,8 +276,10 @@ impl<W> MdWriter<W> {
             Tag::Image { .. } => {
                 unsupported_tag!("Image");
             }
-            Tag::MetadataBlock(_) => {
-                unsupported_tag!("MetadataBlock");
+            Tag::MetadataBlock(kind) => {
+                self.out.write_separator()?;
+                self.out
+                    .write_all(Self::metadata_delimiter(kind).as_ref())?;
             }
         }
         Ok(())
@@ -229,6 +300,7 @@ impl<W> MdWriter<W> {
                 unsupported_tag!("BlockQuote");
             }
             TagEnd::CodeBlock => {
+                self.out.write_line_end()?;
                 self.out.write_all("```\n".as_ref())?;
             }
             TagEnd::HtmlBlock => {
@@ -245,16 +317,21 @@ impl<W> MdWriter<W> {
                 unsupported_tag!("FootnoteDefinition");
             }
             TagEnd::Table => {
-                unsupported_tag!("Table");
+                self.alignments.clear();
             }
             TagEnd::TableHead => {
-                unsupported_tag!("TableHead");
+                self.out.write_all("|\n|".as_ref())?;
+                for alignment in self.alignments.iter() {
+                    self.out.write_all(Self::alignment(*alignment).as_ref())?;
+                }
+                self.out.write_all("\n".as_ref())?;
             }
             TagEnd::TableRow => {
-                unsupported_tag!("TableRow");
+                self.out.write_all("|\n".as_ref())?;
             }
             TagEnd::TableCell => {
-                unsupported_tag!("TableCell");
+                self.out.write_all(" ".as_ref())?;
+                self.in_table_cell = false;
             }
             TagEnd::Emphasis => {
                 self.out.write_all("_".as_ref())?;
@@ -271,13 +348,56 @@ impl<W> MdWriter<W> {
             TagEnd::Image => {
                 unsupported_tag!("Image");
             }
-            TagEnd::MetadataBlock(_) => {
-                unsupported_tag!("MetadataBlock");
+            TagEnd::MetadataBlock(kind) => {
+                self.out.write_line_end()?;
+                self.out
+                    .write_all(Self::metadata_delimiter(kind).as_ref())?;
             }
         }
         Ok(())
     }
 
+    /// Escape the `|` characters of text that is written into a table cell
+    /// (including code spans), so they don't end the cell.
+    fn cell_escaped<'a>(&self, str: &'a str) -> std::borrow::Cow<'a, str> {
+        match self.in_table_cell && str.contains('|') {
+            true => str.replace('|', "\\|").into(),
+            false => str.into(),
+        }
+    }
+
+    /// The backtick fence and the padding that delimit an inline code span
+    /// with the given `code` contents.
+    ///
+    /// The fence is one backtick longer than the longest run of backticks in
+    /// `code`. The padding is a single space if the reader would otherwise
+    /// merge a backtick in `code` with the fence or strip a surrounding space.
+    fn code_span(code: &str) -> (String, &'static str) {
+        let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+        let fence = "`".repeat(longest_run + 1);
+        let padded = code.starts_with('`')
+            || code.ends_with('`')
+            || (code.starts_with(' ') && code.ends_with(' ') && code.trim() != "");
+        (fence, if padded { " " } else { "" })
+    }
+
+    /// The line that opens and closes a metadata block of the given `kind`.
+    fn metadata_delimiter(kind: MetadataBlockKind) -> &'static str {
+        match kind {
+            MetadataBlockKind::YamlStyle => "---\n",
+            MetadataBlockKind::PlusesStyle => "+++\n",
+        }
+    }
+
+    fn alignment(alignment: Alignment) -> &'static str {
+        match alignment {
+            Alignment::None => " --- |",
+            Alignment::Left => " :-- |",
+            Alignment::Center => " :-: |",
+            Alignment::Right => " --: |",
+        }
+    }
+
     fn heading(level: HeadingLevel) -> &'static str
     where
         W: Write,
@@ -299,6 +419,8 @@ impl<W> MdWriter<W> {
 struct Out<W> {
     write: W,
     bytes: usize,
+    /// Whether the output is at the start of a line.
+    line_start: bool,
 }
 
 impl<W> Out<W> {
@@ -311,6 +433,18 @@ impl<W> Out<W> {
         }
         Ok(())
     }
+
+    /// Terminate the current line unless the output is already at the start
+    /// of a line.
+    fn write_line_end(&mut self) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        if !self.line_start {
+            self.write_all("\n".as_ref())?;
+        }
+        Ok(())
+    }
 }
 
 impl<W: Write> Write for Out<W> {
@@ -318,6 +452,9 @@ impl<W: Write> Write for Out<W> {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         let bytes = self.write.write(buf)?;
         self.bytes += bytes;
+        if let Some(last) = buf[..bytes].last() {
+            self.line_start = *last == b'\n';
+        }
         Ok(bytes)
     }
 
@@ -325,6 +462,9 @@ impl<W: Write> Write for Out<W> {
     fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
         self.write.write_all(buf)?;
         self.bytes += buf.len();
+        if let Some(last) = buf.last() {
+            self.line_start = *last == b'\n';
+        }
         Ok(())
     }
 
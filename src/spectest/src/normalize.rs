@@ -0,0 +1,142 @@
+//! Helper functions for normalizing the output of a system under test before
+//! it is stored in [`Example::then`](crate::Example::then).
+//!
+//! The functions in this module implement the [`Normalize`] trait and can be
+//! combined with [`chain`]:
+//!
+//! ```
+//! use spectest::normalize::{self, Normalize};
+//!
+//! let normalizer = normalize::chain(normalize::strip_ansi, normalize::trim_trailing_whitespace);
+//! assert_eq!(normalizer.normalize("\x1b[1mok\x1b[0m  \n"), "ok\n");
+//! ```
+
+/// A trait for string normalizers.
+///
+/// Implemented by all `Fn(&str) -> String` functions and closures.
+pub trait Normalize {
+    fn normalize(&self, s: &str) -> String;
+}
+
+impl<F> Normalize for F
+where
+    F: Fn(&str) -> String,
+{
+    fn normalize(&self, s: &str) -> String {
+        self(s)
+    }
+}
+
+/// Chain two normalizers so that the output of `n1` is passed to `n2`.
+pub fn chain<N1, N2>(n1: N1, n2: N2) -> Chain<N1, N2>
+where
+    N1: Normalize,
+    N2: Normalize,
+{
+    Chain { n1, n2 }
+}
+
+/// A [`Normalize`] implementation that applies `n1` and then `n2`.
+///
+/// See [`chain`] for details.
+#[derive(Debug, Clone, Copy)]
+pub struct Chain<N1, N2> {
+    pub n1: N1,
+    pub n2: N2,
+}
+
+impl<N1, N2> Normalize for Chain<N1, N2>
+where
+    N1: Normalize,
+    N2: Normalize,
+{
+    fn normalize(&self, s: &str) -> String {
+        self.n2.normalize(&self.n1.normalize(s))
+    }
+}
+
+/// Remove ANSI escape sequences (for example terminal colors) from `s`.
+pub fn strip_ansi(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI sequence: parameters and intermediates up to a final byte.
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC sequence: terminated by BEL or ST (`ESC \`).
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Any other two-character escape sequence.
+            _ => (),
+        }
+    }
+
+    result
+}
+
+/// Replace `\r\n` and `\r` line endings in `s` with `\n`.
+pub fn normalize_line_endings(s: &str) -> String {
+    s.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// Remove trailing whitespace from each line in `s`.
+pub fn trim_trailing_whitespace(s: &str) -> String {
+    map_lines(s, |line| line.trim_end().to_string())
+}
+
+/// Trim each line in `s` and replace every run of whitespace characters within
+/// a line with a single space.
+pub fn normalize_whitespace(s: &str) -> String {
+    map_lines(s, |line| {
+        line.split_whitespace().collect::<Vec<_>>().join(" ")
+    })
+}
+
+/// Apply `f` to each line in `s` while preserving the `\n` line breaks.
+fn map_lines(s: &str, f: impl Fn(&str) -> String) -> String {
+    s.split('\n').map(f).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalizers() {
+        let input = "\x1b[31;1mred\x1b[0m \x1b]0;title\x07text\r\n";
+        assert_eq!(strip_ansi(input), "red text\r\n");
+
+        assert_eq!(normalize_line_endings("a\r\nb\rc\n"), "a\nb\nc\n");
+        assert_eq!(trim_trailing_whitespace("a  \n\tb\t\n"), "a\n\tb\n");
+        assert_eq!(normalize_whitespace("  a   b \n c\td\n"), "a b\nc d\n");
+    }
+
+    #[test]
+    fn test_chain() {
+        let normalizer = chain(
+            chain(strip_ansi, normalize_line_endings),
+            normalize_whitespace,
+        );
+        assert_eq!(normalizer.normalize("\x1b[1ma  b\x1b[0m \r\n"), "a b\n");
+    }
+}
@@ -0,0 +1,124 @@
+//! Owned versions of the spec sections returned by
+//! [`parse_spec_file`](crate::core::parse_spec_file).
+//!
+//! Unlike [`Section`](crate::Section) and its variants, these types don't
+//! borrow from the parsed Markdown document, so they are `Send + 'static` and
+//! can be stored, cloned, and compared freely. Use [`Example::to_owned`] and
+//! [`Background::to_owned`] in order to convert a borrowed section. With the
+//! `serde` feature enabled they also implement `serde::Serialize`.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::core::{Background, Example};
+
+/// An owned version of a [`Section`](crate::Section).
+// Boxing the `Example` variant would make the enum awkward to match on.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum OwnedSection {
+    Background(OwnedBackground),
+    Example(OwnedExample),
+    /// A table-driven example with one [`OwnedExample`] per table row.
+    Tabular(Vec<OwnedExample>),
+    /// A section that is neither a `Background` nor an `Example`.
+    Raw {
+        level: u8,
+    },
+}
+
+/// An owned version of a [`Background`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct OwnedBackground {
+    /// The heading level of the section as a number between 1 and 6.
+    pub level: u8,
+    pub given: HashMap<String, String>,
+}
+
+/// An owned version of an [`Example`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct OwnedExample {
+    /// The heading level of the section as a number between 1 and 6.
+    pub level: u8,
+    pub name: String,
+    pub heading: String,
+    pub path: Option<PathBuf>,
+    pub when: HashMap<String, String>,
+    pub when_hints: HashMap<String, String>,
+    pub then: HashMap<String, String>,
+    pub then_hints: HashMap<String, String>,
+    pub optional_then: HashSet<String>,
+    pub notes: Option<String>,
+}
+
+impl From<&Background<'_>> for OwnedBackground {
+    fn from(background: &Background<'_>) -> Self {
+        Self {
+            level: background.level_u8(),
+            given: owned_map(&background.given),
+        }
+    }
+}
+
+impl<T: AsRef<str>> From<&Example<'_, T>> for OwnedExample {
+    fn from(example: &Example<'_, T>) -> Self {
+        Self {
+            level: example.level_u8(),
+            name: example.name.to_string(),
+            heading: example.heading.clone(),
+            path: example.path.map(Path::to_path_buf),
+            when: owned_map(&example.when),
+            when_hints: owned_map(&example.when_hints),
+            then: owned_map(&example.then),
+            then_hints: owned_map(&example.then_hints),
+            optional_then: example
+                .optional_then
+                .iter()
+                .map(|k| k.to_string())
+                .collect(),
+            notes: example.notes.clone(),
+        }
+    }
+}
+
+/// Convert a map with borrowed keys into a map with owned keys and values.
+fn owned_map<V: AsRef<str>>(map: &HashMap<&str, V>) -> HashMap<String, String> {
+    map.iter()
+        .map(|(k, v)| (k.to_string(), v.as_ref().to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::examples::*;
+    use crate::{sections, MdDocument, Section};
+
+    #[test]
+    fn test_to_owned() {
+        let md_source = make_spec(INPUT_SQL, OUTPUT_SQL);
+        let mut md_doc = MdDocument::from_string(&md_source);
+
+        let mut backgrounds = Vec::new();
+        let mut examples = Vec::new();
+        for section in sections(&mut md_doc) {
+            match section.expect("valid section") {
+                Section::Background(background) => backgrounds.push(background.to_owned()),
+                Section::Example(example) => examples.push(example.to_owned()),
+                Section::Tabular(_) | Section::Raw(_) => (),
+            }
+        }
+
+        // Owned sections can be moved to other threads.
+        let (backgrounds, examples) = std::thread::spawn(move || (backgrounds, examples))
+            .join()
+            .expect("thread");
+        assert_eq!(backgrounds.len(), 1);
+        assert!(backgrounds[0].given.contains_key("pipeline"));
+        assert_eq!(examples.len(), 1);
+        assert_eq!(examples[0].name, "Example: Simple queries");
+        assert_eq!(examples[0].then["output"], OUTPUT_SQL);
+    }
+}
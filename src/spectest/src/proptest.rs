@@ -0,0 +1,296 @@
+//! Property-based testing of spec examples with [`proptest`](::proptest).
+//!
+//! Requires the `proptest` feature.
+//!
+//! A [`ProptestHandler`] wraps a [`Handler2`] and runs each [`Example`] twice:
+//! first with the `when` values from the spec file, where the `then` values
+//! are checked as usual, and then with `when` values generated by a proptest
+//! [`Strategy`]. Generated values replace the spec values with the same key,
+//! so a strategy can vary some inputs and keep the others fixed.
+//!
+//! For generated inputs, the property is that the handler succeeds and only
+//! sets `then` keys that are defined in the spec. An optional oracle (for
+//! example, a reference implementation) can compute the expected `then`
+//! values of each generated input, which the handler output must match.
+//! Failing inputs are shrunk by proptest and reported in the error.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::path::Path;
+
+use ::proptest::strategy::Strategy;
+use ::proptest::test_runner::{Config as ProptestConfig, TestCaseError, TestRunner};
+
+use crate::core::{process, Background, Error, Example, Handler2};
+
+/// An oracle that computes the expected `then` values for the `when` values of
+/// an example.
+type Oracle = Box<dyn Fn(&HashMap<&str, &str>) -> HashMap<String, String>>;
+
+/// A [`Handler2`] that also checks each example against `when` values
+/// generated by a proptest [`Strategy`] (see the [module docs](self)).
+///
+/// ```no_run
+/// use std::collections::HashMap;
+///
+/// use proptest::prelude::*;
+/// use spectest::fixtures::IdentityHandler;
+/// use spectest::proptest::ProptestHandler;
+///
+/// let strategy = any::<u32>().prop_map(|x| HashMap::from([("x".into(), x.to_string())]));
+/// let mut handler = ProptestHandler::new(IdentityHandler, strategy)
+///     .with_oracle(|when| HashMap::from([("x".into(), when["x"].to_string())]));
+/// handler.run(ProptestConfig::with_cases(64), "testdata/identity.md");
+/// ```
+pub struct ProptestHandler<H, S> {
+    handler: H,
+    strategy: S,
+    oracle: Option<Oracle>,
+    config: ProptestConfig,
+}
+
+/// The error type of a [`ProptestHandler`].
+#[derive(Debug, thiserror::Error)]
+pub enum ProptestError<E> {
+    /// The wrapped handler failed for the `when` values from the spec file.
+    #[error("{0}")]
+    Handler(E),
+    /// The property failed for a generated input.
+    #[error("property failed: {0}")]
+    Property(String),
+}
+
+impl<H, S> ProptestHandler<H, S>
+where
+    H: Handler2,
+    H::Error: Display,
+    S: Strategy<Value = HashMap<String, String>>,
+{
+    /// Wrap the given `handler` and generate `when` values with `strategy`.
+    pub fn new(handler: H, strategy: S) -> Self {
+        Self {
+            handler,
+            strategy,
+            oracle: None,
+            config: ProptestConfig::default(),
+        }
+    }
+
+    /// Check the `then` values of generated inputs against the values
+    /// computed by `oracle`. Keys that the oracle doesn't return are not
+    /// checked.
+    pub fn with_oracle<F>(mut self, oracle: F) -> Self
+    where
+        F: Fn(&HashMap<&str, &str>) -> HashMap<String, String> + 'static,
+    {
+        self.oracle = Some(Box::new(oracle));
+        self
+    }
+
+    /// Process the spec file at `path` with the given proptest `config` (see
+    /// [`process`]).
+    pub fn process<P: AsRef<Path>>(
+        &mut self,
+        config: ProptestConfig,
+        path: P,
+    ) -> Result<(), Error<ProptestError<H::Error>>> {
+        self.config = config;
+        process(path, self)
+    }
+
+    /// Process the spec file at `path` with the given proptest `config` and
+    /// panic on the first error, including the shrunk failing input (see
+    /// [`run`](crate::run)).
+    pub fn run<P: AsRef<Path>>(&mut self, config: ProptestConfig, path: P) {
+        let path = path.as_ref();
+        if let Err(err) = self.process(config, path) {
+            panic!("proptest failed for spec at `{}`: {err}", path.display());
+        }
+    }
+
+    /// Run the wrapped handler for `when` values generated by the strategy.
+    fn check(&mut self, example: &Example) -> Result<(), String> {
+        let handler = RefCell::new(&mut self.handler);
+        let oracle = self.oracle.as_ref();
+        let mut runner = TestRunner::new(self.config.clone());
+        let result = runner.run(&self.strategy, |generated| {
+            let mut when = example.when.clone();
+            when.extend(generated.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+            let mut case = Example {
+                level: example.level,
+                name: example.name,
+                heading: example.heading.clone(),
+                path: example.path,
+                when: when.into(),
+                when_hints: example.when_hints.clone(),
+                then: example.then.clone(),
+                then_hints: example.then_hints.clone(),
+                optional_then: example.optional_then.clone(),
+                notes: None,
+            };
+
+            let result = handler.borrow_mut().example(&mut case);
+            result.map_err(|err| TestCaseError::fail(err.to_string()))?;
+
+            let is_defined =
+                |key: &&str| example.then.contains_key(key) || example.optional_then.contains(key);
+            if let Some(key) = case.then.keys().find(|key| !is_defined(key)) {
+                let msg = format!("unexpected `{key}`: the key is not defined in the spec");
+                return Err(TestCaseError::fail(msg));
+            }
+
+            for (key, expected) in oracle.map(|oracle| oracle(&case.when)).unwrap_or_default() {
+                let actual = case.then.get(key.as_str());
+                if actual != Some(&expected) {
+                    let msg = format!("`{key}`: expected {expected:?}, actual {actual:?}");
+                    return Err(TestCaseError::fail(msg));
+                }
+            }
+            Ok(())
+        });
+        result.map_err(|err| err.to_string())
+    }
+}
+
+impl<H, S> Handler2 for ProptestHandler<H, S>
+where
+    H: Handler2,
+    H::Error: Display,
+    S: Strategy<Value = HashMap<String, String>>,
+{
+    type Error = ProptestError<H::Error>;
+
+    fn enter(
+        &mut self,
+        background: &Background,
+        active: &[&Background],
+    ) -> Result<(), Self::Error> {
+        let result = self.handler.enter(background, active);
+        result.map_err(ProptestError::Handler)
+    }
+
+    fn leave(
+        &mut self,
+        background: &Background,
+        active: &[&Background],
+    ) -> Result<(), Self::Error> {
+        let result = self.handler.leave(background, active);
+        result.map_err(ProptestError::Handler)
+    }
+
+    fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+        // Check the generated inputs before the spec values are overwritten.
+        self.check(example).map_err(ProptestError::Property)?;
+        let result = self.handler.example(example);
+        result.map_err(ProptestError::Handler)
+    }
+
+    fn before_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        let result = self.handler.before_file(path);
+        result.map_err(ProptestError::Handler)
+    }
+
+    fn after_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        let result = self.handler.after_file(path);
+        result.map_err(ProptestError::Handler)
+    }
+
+    fn reset(&mut self) {
+        self.handler.reset();
+    }
+}
+
+impl<H: Debug, S: Debug> Debug for ProptestHandler<H, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProptestHandler")
+            .field("handler", &self.handler)
+            .field("strategy", &self.strategy)
+            .field("oracle", &self.oracle.as_ref().map(|_| "..."))
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::proptest::prelude::*;
+
+    use super::*;
+    use crate::core::examples::*;
+    use crate::core::Handler;
+
+    /// Adds `x` and `y`, but wraps around above `limit`.
+    struct AddHandler {
+        limit: i64,
+    }
+
+    impl Handler for AddHandler {
+        type Error = String;
+
+        fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+            let parse = |key| example.when[key].trim().parse::<i64>();
+            let (x, y) = (parse("x"), parse("y"));
+            let sum = x
+                .and_then(|x| y.map(|y| x + y))
+                .map_err(|e| e.to_string())?;
+            example
+                .then
+                .insert("sum", format!("{}\n", sum % self.limit));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_proptest_handler() -> std::io::Result<()> {
+        let spec = indoc::indoc! {r"
+            ## Example: Addition
+
+            When `x` is:
+
+            ```
+            1
+            ```
+
+            And `y` is:
+
+            ```
+            2
+            ```
+
+            Then `sum` is:
+
+            ```
+            3
+            ```
+        "};
+        let path = write_spec(spec)?;
+
+        let strategy = (0..1000i64).prop_map(|x| HashMap::from([("x".into(), x.to_string())]));
+        let oracle = |when: &HashMap<&str, &str>| {
+            let sum = when["x"].parse::<i64>().expect("x") + 2;
+            HashMap::from([("sum".to_string(), format!("{sum}\n"))])
+        };
+        let config = || ProptestConfig {
+            failure_persistence: None,
+            ..ProptestConfig::with_cases(32)
+        };
+
+        let handler = AddHandler { limit: i64::MAX };
+        let mut handler = ProptestHandler::new(handler, strategy.clone()).with_oracle(oracle);
+        handler.process(config(), &path).expect("property holds");
+
+        // The generated inputs expose the bug, which is shrunk to the minimal
+        // failing input.
+        let handler = AddHandler { limit: 100 };
+        let mut handler = ProptestHandler::new(handler, strategy).with_oracle(oracle);
+        let err = handler
+            .process(config(), &path)
+            .expect_err("property fails");
+        let err = err.to_string();
+        assert!(err.contains("property failed"), "{err}");
+        assert!(err.contains("\"98\""), "{err}");
+
+        Ok(())
+    }
+}
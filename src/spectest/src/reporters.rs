@@ -0,0 +1,47 @@
+//! Reporters that record the outcome of each [`Example`] and report it in a
+//! format understood by CI systems.
+//!
+//! A reporter processes spec files like [`process`](crate::process) and
+//! considers an example failed for the same reasons, comparing the
+//! [`Example::then`] values with the
+//! [`TrailingNewlineComparator`](crate::TrailingNewlineComparator). Unlike
+//! `process`, it continues after a failing example (see
+//! [`Config::keep_going`]) in order to report the outcome of all examples.
+
+use std::fmt::Display;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::core::{
+    process_with_outcomes, Config, Error, ExampleOutcome, Handler2, RunStats, StatsError,
+};
+
+pub use crate::reporters::junit::{JUnitReporter, TestCase};
+pub use crate::reporters::tap::TapReporter;
+
+mod junit;
+mod tap;
+
+/// Process the spec file at the given `path` with the given `handler` and
+/// call `on_example` with the name, the duration, and the failure message (if
+/// any) of each example that is not skipped.
+fn process<P, H, F>(
+    path: P,
+    handler: &mut H,
+    mut on_example: F,
+) -> Result<RunStats, StatsError<H::Error>>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+    H::Error: Display,
+    F: FnMut(&str, Duration, Option<String>),
+{
+    let config = Config::default().keep_going(true);
+    process_with_outcomes(path, handler, &config, |info, outcome, error| {
+        let (ExampleOutcome::Passed | ExampleOutcome::Failed) = outcome else {
+            return; // Skipped examples are not reported.
+        };
+        let duration = info.duration.unwrap_or_default();
+        on_example(info.name, duration, error.map(Error::to_string));
+    })
+}
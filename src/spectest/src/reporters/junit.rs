@@ -0,0 +1,192 @@
+//! A reporter that records [`Example`](crate::Example) outcomes as JUnit XML.
+
+use std::fmt::Display;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::core::{Handler2, RunStats, StatsError};
+
+/// The recorded outcome of a single [`Example`](crate::Example).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+    /// The [`Example::name`].
+    pub name: String,
+    /// The time spent in the [`Handler2::example`] call.
+    pub time: Duration,
+    /// A description of the failure, or `None` if the example passed.
+    pub failure: Option<String>,
+}
+
+/// A reporter that records a [`TestCase`] for each [`Example`](crate::Example) of the spec
+/// files passed to [`JUnitReporter::process`].
+///
+/// Call [`JUnitReporter::write_xml`] after processing the spec files in order
+/// to emit the recorded test cases as a JUnit XML `<testsuite>` document in
+/// the format expected by Jenkins (the Ant JUnit schema).
+#[derive(Debug)]
+pub struct JUnitReporter {
+    pub name: String,
+    pub test_cases: Vec<TestCase>,
+}
+
+impl Default for JUnitReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JUnitReporter {
+    /// Create a new reporter without any recorded test cases.
+    pub fn new() -> Self {
+        Self {
+            name: String::from("spectest"),
+            test_cases: Vec::new(),
+        }
+    }
+
+    /// Use the given `name` for the emitted `<testsuite>` (defaults to
+    /// `spectest`).
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Process the spec file at the given `path` with the given `handler` and
+    /// record a [`TestCase`] for each [`Example`](crate::Example) that is not skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`process_with_stats`], with the error of
+    /// the first failing example returned only after all examples were
+    /// recorded.
+    ///
+    /// [`process_with_stats`]: crate::process_with_stats
+    pub fn process<P, H>(
+        &mut self,
+        path: P,
+        handler: &mut H,
+    ) -> Result<RunStats, StatsError<H::Error>>
+    where
+        P: AsRef<Path>,
+        H: Handler2,
+        H::Error: Display,
+    {
+        super::process(path, handler, |name, time, failure| {
+            self.test_cases.push(TestCase {
+                name: name.to_string(),
+                time,
+                failure,
+            });
+        })
+    }
+
+    /// Write the recorded test cases as a JUnit XML document to `writer`.
+    pub fn write_xml<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        let tests = self.test_cases.len();
+        let failures = self.test_cases.iter().filter(|t| t.failure.is_some());
+        let failures = failures.count();
+        let time = self.test_cases.iter().map(|t| t.time).sum::<Duration>();
+        let name = escape(&self.name);
+
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<testsuite name="{name}" tests="{tests}" failures="{failures}" errors="0" skipped="0" time="{:.3}">"#,
+            time.as_secs_f64()
+        )?;
+        for test_case in self.test_cases.iter() {
+            let test_name = escape(&test_case.name);
+            let time = test_case.time.as_secs_f64();
+            write!(
+                writer,
+                r#"  <testcase name="{test_name}" classname="{name}" time="{time:.3}""#
+            )?;
+            match &test_case.failure {
+                None => writeln!(writer, "/>")?,
+                Some(failure) => {
+                    let message = escape(failure.lines().next().unwrap_or_default());
+                    writeln!(writer, ">")?;
+                    writeln!(
+                        writer,
+                        r#"    <failure message="{message}">{}</failure>"#,
+                        escape(failure)
+                    )?;
+                    writeln!(writer, "  </testcase>")?;
+                }
+            }
+        }
+        writeln!(writer, "</testsuite>")?;
+
+        Ok(())
+    }
+}
+
+/// Escape the XML special characters in `s`.
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::examples::*;
+    use crate::core::{Error, Example, Handler};
+
+    #[test]
+    fn test_junit_reporter() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                if let Some(code) = example.then.get_mut("output") {
+                    *code = String::from("<redacted>");
+                }
+                Ok(())
+            }
+        }
+
+        let spec = make_spec(INPUT_SQL, OUTPUT_SQL);
+        let spec = spec.clone() + "\n" + &make_spec(INPUT_SQL, "<redacted>");
+        let spec = spec.replacen("Simple queries", "Complex queries", 1);
+        let path = write_spec(&spec)?;
+
+        let mut reporter = JUnitReporter::new();
+        let result = reporter.process(&path, &mut TestHandler);
+        let Err(StatsError { stats, error }) = result else {
+            panic!("expected a failure");
+        };
+        assert!(matches!(*error, Error::Failure { .. }));
+        assert_eq!((stats.passed, stats.failed), (1, 1));
+        // The spec file is left untouched.
+        assert_eq!(std::fs::read_to_string(&path)?, spec);
+
+        let mut xml = Vec::new();
+        reporter.write_xml(&mut xml)?;
+        let xml = String::from_utf8(xml).expect("utf8");
+
+        assert!(xml.contains(r#"<testsuite name="spectest" tests="2" failures="1""#));
+        assert!(xml.contains(r#"<testcase name="Example: Complex queries" classname="spectest""#));
+        assert!(
+            xml.contains(r#"<failure message="unexpected `output` in Example: Complex queries">"#)
+        );
+        assert!(xml.contains("&lt;redacted&gt;"));
+        assert!(xml.contains(r#"<testcase name="Example: Simple queries" classname="spectest""#));
+        assert_eq!(xml.matches("</testcase>").count(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+//! A reporter that writes [`Example`](crate::Example) outcomes in the TAP
+//! format.
+
+use std::fmt::Display;
+use std::io::Write;
+use std::path::Path;
+
+use crate::core::{Handler2, RunStats, StatsError};
+
+/// A reporter that writes a [TAP version 13][tap] test line for each
+/// [`Example`](crate::Example) of the spec files passed to
+/// [`TapReporter::process`] to a `writer`.
+///
+/// Since the number of examples is not known upfront, the TAP plan line is
+/// written at the end by [`TapReporter::finish`].
+///
+/// [tap]: https://testanything.org/tap-version-13-specification.html
+#[derive(Debug)]
+pub struct TapReporter<W: Write> {
+    writer: W,
+    count: usize,
+    error: Option<std::io::Error>,
+}
+
+impl<W: Write> TapReporter<W> {
+    /// Create a new reporter that writes TAP lines to the given `writer`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            count: 0,
+            error: None,
+        }
+    }
+
+    /// Process the spec file at the given `path` with the given `handler` and
+    /// write a test line for each example that is not skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`process_with_stats`], with the error of
+    /// the first failing example returned only after all test lines were
+    /// written. Errors while writing to the `writer` are returned by
+    /// [`TapReporter::finish`].
+    ///
+    /// [`process_with_stats`]: crate::process_with_stats
+    pub fn process<P, H>(
+        &mut self,
+        path: P,
+        handler: &mut H,
+    ) -> Result<RunStats, StatsError<H::Error>>
+    where
+        P: AsRef<Path>,
+        H: Handler2,
+        H::Error: Display,
+    {
+        super::process(path, handler, |name, _, failure| {
+            self.count += 1;
+            if self.error.is_none() {
+                let result = self.write_test_line(name, failure.as_deref());
+                self.error = result.err();
+            }
+        })
+    }
+
+    /// Write the TAP plan line and flush the `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error encountered while writing to the `writer`.
+    pub fn finish(mut self) -> std::io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if self.count == 0 {
+            writeln!(self.writer, "TAP version 13")?;
+        }
+        writeln!(self.writer, "1..{}", self.count)?;
+        self.writer.flush()
+    }
+
+    fn write_test_line(&mut self, name: &str, failure: Option<&str>) -> std::io::Result<()> {
+        if self.count == 1 {
+            writeln!(self.writer, "TAP version 13")?;
+        }
+        let count = self.count;
+        let Some(failure) = failure else {
+            return writeln!(self.writer, "ok {count} - {name}");
+        };
+        writeln!(self.writer, "not ok {count} - {name} # FAILED")?;
+        writeln!(self.writer, "  ---")?;
+        writeln!(self.writer, "  message: |")?;
+        for line in failure.lines() {
+            writeln!(self.writer, "    {line}")?;
+        }
+        writeln!(self.writer, "  ...")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::examples::*;
+    use crate::core::{Error, Example, Handler};
+
+    #[test]
+    fn test_tap_reporter() -> std::io::Result<()> {
+        struct TestHandler;
+
+        impl Handler for TestHandler {
+            type Error = String;
+
+            fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+                if let Some(code) = example.then.get_mut("output") {
+                    *code = String::from("<redacted>");
+                }
+                Ok(())
+            }
+        }
+
+        let spec = make_spec(INPUT_SQL, "<redacted>");
+        let spec = spec.clone() + "\n" + &make_spec(INPUT_SQL, "SELECT 1;");
+        let spec = spec.replacen("Simple queries", "Complex queries", 1);
+        let path = write_spec(&spec)?;
+
+        let mut tap = Vec::new();
+        let mut reporter = TapReporter::new(&mut tap);
+        let result = reporter.process(&path, &mut TestHandler);
+        let Err(StatsError { stats, error }) = result else {
+            panic!("expected a failure");
+        };
+        assert!(matches!(*error, Error::Failure { .. }));
+        assert_eq!((stats.passed, stats.failed), (1, 1));
+        // The spec file is left untouched.
+        assert_eq!(std::fs::read_to_string(&path)?, spec);
+        reporter.finish()?;
+
+        let exp = indoc::indoc! {"
+            TAP version 13
+            ok 1 - Example: Complex queries
+            not ok 2 - Example: Simple queries # FAILED
+              ---
+              message: |
+                unexpected `output` in Example: Simple queries
+                expect: \"SELECT 1;\"
+                actual: \"<redacted>\"
+                         ^^^^^^^^^^
+              ...
+            1..2
+        "};
+        assert_eq!(String::from_utf8(tap).expect("utf8"), exp);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+//! Utilities for testing code that drives or composes [`Handler`]s.
+//!
+//! A [`MockHandler`] records every call that it receives, so tests can assert
+//! that `enter` and `leave` calls are paired and ordered as expected.
+
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+
+use crate::core::{Background, Example, Handler};
+use crate::owned::{OwnedBackground, OwnedExample};
+
+/// A call received by a [`MockHandler`].
+// Boxing the `Example` variant would make the enum awkward to match on.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockCall {
+    BeforeFile(PathBuf),
+    Enter(OwnedBackground),
+    Leave(OwnedBackground),
+    /// The example as it was passed to the handler, with the `then` values
+    /// pre-filled by the caller.
+    Example(OwnedExample),
+    AfterFile(PathBuf),
+    Reset,
+}
+
+/// A [`Handler`] that records all calls as [`MockCall`]s.
+///
+/// The handler leaves the pre-filled `then` values of each example as they
+/// are, so all examples pass.
+///
+/// ```
+/// use spectest::core::process_str;
+/// use spectest::testing::{MockCall, MockHandler};
+///
+/// let mut handler = MockHandler::new();
+/// let source = "# Background\n\nGiven `x` as:\n\n```\n1\n```\n";
+/// process_str(source, &mut handler).expect("valid spec");
+///
+/// assert!(matches!(
+///     handler.calls(),
+///     [
+///         MockCall::BeforeFile(_),
+///         MockCall::Enter(_),
+///         MockCall::Leave(_),
+///         MockCall::AfterFile(_),
+///     ]
+/// ));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MockHandler {
+    calls: Vec<MockCall>,
+}
+
+impl MockHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The calls received so far, in the order in which they were received.
+    pub fn calls(&self) -> &[MockCall] {
+        &self.calls
+    }
+
+    /// Remove and return the calls received so far.
+    pub fn take_calls(&mut self) -> Vec<MockCall> {
+        std::mem::take(&mut self.calls)
+    }
+}
+
+impl Handler for MockHandler {
+    type Error = Infallible;
+
+    fn enter(&mut self, background: &Background) -> Result<(), Self::Error> {
+        self.calls.push(MockCall::Enter(background.to_owned()));
+        Ok(())
+    }
+
+    fn leave(&mut self, background: &Background) -> Result<(), Self::Error> {
+        self.calls.push(MockCall::Leave(background.to_owned()));
+        Ok(())
+    }
+
+    fn example(&mut self, example: &mut Example) -> Result<(), Self::Error> {
+        self.calls.push(MockCall::Example(example.to_owned()));
+        Ok(())
+    }
+
+    fn before_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        self.calls.push(MockCall::BeforeFile(path.to_path_buf()));
+        Ok(())
+    }
+
+    fn after_file(&mut self, path: &Path) -> Result<(), Self::Error> {
+        self.calls.push(MockCall::AfterFile(path.to_path_buf()));
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.calls.push(MockCall::Reset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::examples::*;
+    use crate::core::process;
+
+    #[test]
+    fn test_mock_handler() -> std::io::Result<()> {
+        let spec = indoc::indoc! {r"
+            # Background
+
+            Given `x` as:
+
+            ```
+            1
+            ```
+
+            ## Background
+
+            Given `y` as:
+
+            ```
+            2
+            ```
+
+            ### Example: A
+
+            When `input` is:
+
+            ```
+            x + y
+            ```
+
+            Then `output` is:
+
+            ```
+            3
+            ```
+        "};
+        let path = write_spec(spec)?;
+
+        let mut handler = MockHandler::new();
+        process(&path, &mut handler).expect("valid spec");
+
+        let calls = calls(&handler);
+        let exp = [
+            "before_file",
+            "enter 1 x",
+            "enter 2 y",
+            "example Example: A",
+            "leave 2 y",
+            "leave 1 x",
+            "after_file",
+        ];
+        assert_eq!(calls, exp);
+
+        let MockCall::Example(example) = &handler.calls()[3] else {
+            panic!("expected example call");
+        };
+        assert_eq!(example.then["output"], "3\n");
+
+        assert_eq!(handler.take_calls().len(), exp.len());
+        assert!(handler.calls().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_backgrounds() -> std::io::Result<()> {
+        let background = |level: &str, key: &str| {
+            format!("{level} Background\n\nGiven `{key}` as:\n\n```\n1\n```\n\n")
+        };
+        let spec = [
+            background("##", "x"),
+            background("###", "y"),
+            background("##", "z"),
+        ]
+        .concat();
+        let path = write_spec(&spec)?;
+
+        let mut handler = MockHandler::new();
+        process(&path, &mut handler).expect("valid spec");
+
+        // The nested `y` is left before `x`, and doesn't leak into `z`.
+        let exp = [
+            "before_file",
+            "enter 2 x",
+            "enter 3 y",
+            "leave 3 y",
+            "leave 2 x",
+            "enter 2 x z",
+            "leave 2 x z",
+            "after_file",
+        ];
+        assert_eq!(calls(&handler), exp);
+        Ok(())
+    }
+
+    /// A summary of the calls received by the `handler`.
+    fn calls(handler: &MockHandler) -> Vec<String> {
+        let background = |background: &OwnedBackground| {
+            let mut keys = background.given.keys().cloned().collect::<Vec<_>>();
+            keys.sort();
+            format!("{} {}", background.level, keys.join(" "))
+        };
+        handler
+            .calls()
+            .iter()
+            .map(|call| match call {
+                MockCall::BeforeFile(_) => "before_file".to_string(),
+                MockCall::Enter(bg) => format!("enter {}", background(bg)),
+                MockCall::Leave(bg) => format!("leave {}", background(bg)),
+                MockCall::Example(example) => format!("example {}", example.name),
+                MockCall::AfterFile(_) => "after_file".to_string(),
+                MockCall::Reset => "reset".to_string(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,258 @@
+//! Support for spec files written in TOML instead of Markdown.
+//!
+//! Requires the `toml` feature.
+//!
+//! A TOML spec file consists of `[[background]]` tables with a `given` table
+//! and `[[example]]` tables with a `name` and `when` and `then` tables:
+//!
+//! ```toml
+//! [[background]]
+//! given = { x = "5" }
+//!
+//! [[example]]
+//! name = "Addition"
+//! when = { input = "x + 1" }
+//! then = { result = "6" }
+//! ```
+//!
+//! The spec is converted into the equivalent Markdown document (see
+//! [`to_markdown`]), so handlers see the same [`Section`](crate::Section)
+//! variants as for Markdown spec files. All backgrounds are active for all
+//! examples and are entered in the order in which they are defined.
+//!
+//! TOML spec files can't be rewritten, so the `REWRITE_SPECS` environment
+//! variable is ignored for them.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::core::{run, Error, Handler2, InlineSpec};
+
+/// The maximum number of `[[background]]` tables in a TOML spec file.
+///
+/// Each background is nested in the previous one, and Markdown supports only
+/// six heading levels (one of which is used by the examples).
+pub const MAX_BACKGROUNDS: usize = 5;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlSpec {
+    #[serde(default)]
+    background: Vec<TomlBackground>,
+    #[serde(default)]
+    example: Vec<TomlExample>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlBackground {
+    given: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlExample {
+    name: String,
+    when: BTreeMap<String, String>,
+    then: BTreeMap<String, String>,
+}
+
+/// Convert the TOML spec given as `source` into the equivalent Markdown spec.
+///
+/// # Errors
+///
+/// - When `source` is not a valid TOML spec.
+/// - When `source` has more than [`MAX_BACKGROUNDS`] backgrounds.
+pub fn to_markdown(source: &str) -> Result<String, ::toml::de::Error> {
+    let spec = ::toml::from_str::<TomlSpec>(source)?;
+    if spec.background.len() > MAX_BACKGROUNDS {
+        let msg = format!("expected at most {MAX_BACKGROUNDS} `[[background]]` tables");
+        return Err(serde::de::Error::custom(msg));
+    }
+
+    let mut md = String::new();
+    let mut level = 1;
+    for background in spec.background.iter() {
+        md.push_str(&format!("{} Background\n\n", "#".repeat(level)));
+        push_code_blocks(&mut md, "Given", "as", &background.given);
+        level += 1;
+    }
+    for example in spec.example.iter() {
+        md.push_str(&format!(
+            "{} Example: {}\n\n",
+            "#".repeat(level),
+            example.name
+        ));
+        push_code_blocks(&mut md, "When", "is", &example.when);
+        push_code_blocks(&mut md, "Then", "is", &example.then);
+    }
+    Ok(md)
+}
+
+/// Append a spec paragraph followed by a fenced code block for each entry in
+/// `map` to `md`, starting with a `{prefix} `{key}` {verb}:` paragraph for the
+/// first entry and continuing with `And `{key}` {verb}:` paragraphs.
+fn push_code_blocks(md: &mut String, prefix: &str, verb: &str, map: &BTreeMap<String, String>) {
+    for (i, (key, value)) in map.iter().enumerate() {
+        let prefix = if i == 0 { prefix } else { "And" };
+        push_code(md, format_args!("{prefix} `{key}` {verb}:"), value);
+    }
+}
+
+/// Append a spec `paragraph` followed by a fenced code block with the given
+/// `value` to `md`.
+fn push_code(md: &mut String, paragraph: impl Display, value: &str) {
+    // Use a fence that is longer than any backtick sequence in the value.
+    let mut longest = 0;
+    let mut current = 0;
+    for c in value.chars() {
+        current = if c == '`' { current + 1 } else { 0 };
+        longest = longest.max(current);
+    }
+    let fence = "`".repeat(3.max(longest + 1));
+    let newline = if value.ends_with('\n') { "" } else { "\n" };
+    // Writing to a `String` can't fail.
+    let _ = write!(md, "{paragraph}\n\n{fence}\n{value}{newline}{fence}\n\n");
+}
+
+/// Process the TOML spec file at `path` with the given `handler` (see
+/// [`process`](crate::core::process)).
+///
+/// Malformed TOML spec files are reported as [`Error::IO`] errors with kind
+/// [`InvalidData`](std::io::ErrorKind::InvalidData).
+pub fn process<P, H>(path: P, handler: &mut H) -> Result<(), Error<H::Error>>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+{
+    let md = read_markdown(path.as_ref())?;
+    let name = path.as_ref().to_string_lossy();
+    InlineSpec::new(&name, &md).process(handler)
+}
+
+/// Run the spec file at `path` with the given `handler`.
+///
+/// Files with a `.toml` extension are parsed as TOML spec files, while all
+/// other files are passed to [`run`]. This makes it possible to mix both
+/// formats in a single [`glob_test`](crate::glob_test):
+///
+/// ```ignore
+/// #[spectest::glob_test("testdata/**/*", extensions = [".md", ".toml"])]
+/// fn test(path: &str) {
+///     spectest::run_toml(path, &mut CalculatorHandler::default());
+/// }
+/// ```
+pub fn run_toml<P, H>(path: P, handler: &mut H)
+where
+    P: AsRef<Path>,
+    H: Handler2,
+    H::Error: Display,
+{
+    let path = path.as_ref();
+    if path.extension().is_some_and(|ext| ext == "toml") {
+        let md = match read_markdown(path) {
+            Ok(md) => md,
+            Err(err) => panic!("io error for spec at `{}`: {err}", path.display()),
+        };
+        println!("processing spec at `{}`", path.display());
+        InlineSpec::new(&path.to_string_lossy(), &md).run(handler);
+    } else {
+        run(path, handler);
+    }
+}
+
+/// Read the TOML spec file at `path` and convert it with [`to_markdown`].
+fn read_markdown(path: &Path) -> std::io::Result<String> {
+    let source = std::fs::read_to_string(path)?;
+    to_markdown(&source).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_markdown() {
+        let source = indoc::indoc! {r#"
+            [[background]]
+            given = { x = "5" }
+
+            [[background]]
+            given = { y = "6", z = "7" }
+
+            [[example]]
+            name = "Addition"
+            when = { input = "x + y" }
+            then = { result = "11\n" }
+
+            [[example]]
+            name = "Code"
+            when = { input = "```" }
+            then = { result = "error" }
+        "#};
+        let exp = indoc::indoc! {r"
+            # Background
+
+            Given `x` as:
+
+            ```
+            5
+            ```
+
+            ## Background
+
+            Given `y` as:
+
+            ```
+            6
+            ```
+
+            And `z` as:
+
+            ```
+            7
+            ```
+
+            ### Example: Addition
+
+            When `input` is:
+
+            ```
+            x + y
+            ```
+
+            Then `result` is:
+
+            ```
+            11
+            ```
+
+            ### Example: Code
+
+            When `input` is:
+
+            ````
+            ```
+            ````
+
+            Then `result` is:
+
+            ```
+            error
+            ```
+
+        "};
+        assert_eq!(to_markdown(source).expect("valid spec"), exp);
+
+        let source = "[[example]]\nname = \"Missing then\"\nwhen = { input = \"1\" }\n";
+        let err = to_markdown(source).expect_err("missing then");
+        assert!(err.to_string().contains("missing field `then`"), "{err}");
+
+        let source = "[[background]]\ngiven = {}\n".repeat(MAX_BACKGROUNDS + 1);
+        let err = to_markdown(&source).expect_err("too many backgrounds");
+        assert!(err.to_string().contains("at most 5"), "{err}");
+    }
+}
@@ -0,0 +1,159 @@
+//! Re-run spec files whenever they change on disk.
+//!
+//! Requires the `watch` feature.
+//!
+//! This is meant for authoring specs and is not used by [`run`](crate::run)
+//! and the other functions that process spec files in tests. Call [`watch`]
+//! from a small binary or an `#[ignore]`d test:
+//!
+//! ```no_run
+//! # use spectest::fixtures::IdentityHandler;
+//! spectest::watch("testdata", || IdentityHandler).expect("watcher");
+//! ```
+
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
+
+use notify::{RecursiveMode, Watcher};
+
+use crate::core::{process_with_stats, Handler2};
+
+/// The time to wait for further events after a change before re-running the
+/// changed spec files. Editors often emit several events for a single save.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Watch the spec file or directory at `path` and process each spec file
+/// with a fresh handler created by `make_handler` whenever it changes.
+///
+/// All `.md` files at `path` are processed once on startup. On each change,
+/// the terminal is cleared, the current (UTC) time is printed, and the
+/// changed spec files are processed again. The outcome of each file is
+/// printed, but failures don't stop the watcher. Press Ctrl-C in order to
+/// stop it.
+///
+/// # Errors
+///
+/// - When `path` can't be watched.
+/// - When the watcher reports an error.
+pub fn watch<P, H, F>(path: P, mut make_handler: F) -> notify::Result<()>
+where
+    P: AsRef<Path>,
+    H: Handler2,
+    H::Error: Display,
+    F: FnMut() -> H,
+{
+    let path = path.as_ref();
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(path, RecursiveMode::Recursive)?;
+
+    rerun(&spec_files(path)?, &mut make_handler);
+    while let Ok(event) = rx.recv() {
+        let mut changed = BTreeSet::new();
+        collect(event?, &mut changed);
+        while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+            collect(event?, &mut changed);
+        }
+        if !changed.is_empty() {
+            rerun(&changed.into_iter().collect::<Vec<_>>(), &mut make_handler);
+        }
+    }
+    Ok(())
+}
+
+/// Add the spec files created or modified by `event` to `changed`.
+fn collect(event: notify::Event, changed: &mut BTreeSet<PathBuf>) {
+    if event.kind.is_create() || event.kind.is_modify() {
+        let paths = event.paths.into_iter();
+        changed.extend(paths.filter(|path| is_spec_file(path) && path.is_file()));
+    }
+}
+
+/// Clear the terminal and process the spec files at `paths`.
+fn rerun<H, F>(paths: &[PathBuf], make_handler: &mut F)
+where
+    H: Handler2,
+    H::Error: Display,
+    F: FnMut() -> H,
+{
+    print!("\x1B[2J\x1B[H");
+    println!(
+        "[{}] processing {} spec file(s)",
+        clock(SystemTime::now()),
+        paths.len()
+    );
+    for path in paths {
+        match process_with_stats(path, &mut make_handler()) {
+            Ok(stats) => println!("ok `{}` — {stats}", path.display()),
+            Err(err) => println!("error `{}` — {}\n{err}", path.display(), err.stats),
+        }
+    }
+}
+
+/// The sorted paths of all spec files at `path`, which is either a spec file
+/// or a directory that is searched recursively.
+fn spec_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut paths = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_spec_file(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Check whether `path` has the `.md` extension of spec files.
+fn is_spec_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "md")
+}
+
+/// Format the time of day of `time` in UTC as `HH:MM:SS`.
+fn clock(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (h, m, s) = (secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    format!("{h:02}:{m:02}:{s:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_files() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("nested"))?;
+        for name in ["b.md", "a.md", "notes.txt", "nested/c.md"] {
+            std::fs::write(dir.path().join(name), "")?;
+        }
+
+        let paths = spec_files(dir.path())?;
+        let exp = ["a.md", "b.md", "nested/c.md"].map(|name| dir.path().join(name));
+        assert_eq!(paths, exp);
+
+        let path = dir.path().join("a.md");
+        assert_eq!(spec_files(&path)?, vec![path]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_clock() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(86400 + 3723);
+        assert_eq!(clock(time), "01:02:03");
+    }
+}
@@ -65,3 +65,214 @@ fn test(path: &str) {
     let mut handler = MevalHandler::new();
     spectest::run(path, &mut handler);
 }
+
+#[spectest::glob_test("testdata/integration/**/*.md", group_by = "directory")]
+fn grouped(path: &str) {
+    let mut handler = MevalHandler::new();
+    spectest::run(path, &mut handler);
+}
+
+#[spectest::glob_test(pattern = "integration/**/*.md", base = "testdata")]
+fn based(path: &str) {
+    let mut handler = MevalHandler::new();
+    spectest::run(path, &mut handler);
+}
+
+#[spectest::glob_test(
+    pattern = "testdata/integration/meval/*.md",
+    base = env!("CARGO_MANIFEST_DIR"),
+)]
+fn based_env(path: &str) {
+    let mut handler = MevalHandler::new();
+    spectest::run(path, &mut handler);
+}
+
+#[spectest::glob_test("testdata/integration/**/*.md", runtime = "tokio")]
+async fn tokio(path: &str) {
+    let mut handler = MevalHandler::new();
+    tokio::task::yield_now().await;
+    spectest::run(path, &mut handler);
+}
+
+/// A [`MevalHandler`] variant that can be shared between tests.
+#[derive(Default)]
+struct SharedHandler {
+    vars: Vec<(String, f64)>,
+    runs: usize,
+}
+
+impl spectest::Handler for SharedHandler {
+    type Error = String;
+
+    fn enter(&mut self, background: &spectest::Background) -> Result<(), Self::Error> {
+        for (var_name, var_value) in background.given.iter() {
+            match var_value.trim().parse::<f64>() {
+                Ok(var_value) => self.vars.push((var_name.to_string(), var_value)),
+                Err(err) => return Err(format!("cannot parse `{var_value}` as f64: {err}")),
+            }
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self, _background: &spectest::Background) -> Result<(), Self::Error> {
+        self.vars.clear();
+        Ok(())
+    }
+
+    fn example(&mut self, example: &mut spectest::Example) -> Result<(), Self::Error> {
+        let mut handler = MevalHandler::new();
+        for (var_name, var_value) in self.vars.iter() {
+            handler.ctx.var(var_name.as_str(), *var_value);
+        }
+        spectest::Handler::example(&mut handler, example)
+    }
+
+    fn reset(&mut self) {
+        self.vars.clear();
+        self.runs += 1;
+    }
+}
+
+fn reset_shared_handler(handler: &mut SharedHandler) {
+    spectest::Handler::reset(handler);
+}
+
+#[spectest::glob_test("testdata/integration/**/*.md", test_attr = tokio::test)]
+async fn tokio_attr(path: &str) {
+    let mut handler = MevalHandler::new();
+    tokio::task::yield_now().await;
+    spectest::run(path, &mut handler);
+}
+
+#[spectest::glob_test(
+    "testdata/integration/**/*.md",
+    shared_handler = SharedHandler::default,
+    reset = reset_shared_handler,
+)]
+fn shared(path: &str, handler: &mut SharedHandler) {
+    assert!(handler.runs > 0, "handler is reset before each test");
+    spectest::run(path, handler);
+}
+
+#[spectest::glob_test(
+    "testdata/integration/**/*.md",
+    ignore_pattern = "testdata/integration/**/*.failing.md"
+)]
+fn ignoring(path: &str) {
+    let mut handler = MevalHandler::new();
+    spectest::run(path, &mut handler);
+}
+
+#[spectest::glob_test("testdata/integration/**/*.md", group_by = "directory", doc = true)]
+fn documented(path: &str) {
+    let mut handler = MevalHandler::new();
+    spectest::run(path, &mut handler);
+}
+
+#[spectest::glob_test("testdata/integration/**/*", extensions = [".md"])]
+fn filtered(path: &str) {
+    assert!(path.ends_with(".md"), "unexpected path: {path}");
+    let mut handler = MevalHandler::new();
+    spectest::run(path, &mut handler);
+}
+
+#[spectest::glob_test("testdata/integration/**/*.md", sort = false)]
+fn unsorted(path: &str) {
+    let mut handler = MevalHandler::new();
+    spectest::run(path, &mut handler);
+}
+
+#[spectest::spec_file("testdata/integration/meval/closed_exprs.md")]
+fn single(path: &str) {
+    let mut handler = MevalHandler::new();
+    spectest::run(path, &mut handler);
+}
+
+#[cfg(feature = "toml")]
+#[spectest::glob_test("testdata/toml/**/*", extensions = [".toml"])]
+fn toml(path: &str) {
+    let mut handler = MevalHandler::new();
+    spectest::run_toml(path, &mut handler);
+}
+
+struct RejectingHandler;
+
+impl spectest::Handler for RejectingHandler {
+    type Error = String;
+
+    fn example(&mut self, _example: &mut spectest::Example) -> Result<(), Self::Error> {
+        Err("examples are not supported".to_string())
+    }
+}
+
+#[spectest::glob_test("testdata/integration/**/*.md", should_panic = true)]
+fn rejecting(path: &str) {
+    spectest::run(path, &mut RejectingHandler);
+}
+
+#[cfg(feature = "insta")]
+struct SnapshotHandler<'a>(MevalHandler<'a>);
+
+#[cfg(feature = "insta")]
+impl<'a> spectest::Handler for SnapshotHandler<'a> {
+    type Error = String;
+
+    fn enter(&mut self, background: &spectest::Background) -> Result<(), Self::Error> {
+        spectest::Handler::enter(&mut self.0, background)
+    }
+
+    fn leave(&mut self, background: &spectest::Background) -> Result<(), Self::Error> {
+        spectest::Handler::leave(&mut self.0, background)
+    }
+
+    fn example(&mut self, example: &mut spectest::Example) -> Result<(), Self::Error> {
+        spectest::Handler::example(&mut self.0, example)?;
+        spectest::assert_snapshot!(example, "result", example.then["result"]);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "insta")]
+#[test]
+fn snapshots() {
+    let path = "testdata/integration/meval/closed_exprs.md";
+    let mut handler = SnapshotHandler(MevalHandler::new());
+    spectest::core::process(path, &mut handler).expect("passing spec");
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn cli() {
+    let spectest = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_spectest"))
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .args(args)
+            .output()
+            .expect("spectest binary runs");
+        (
+            output.status.code(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+        )
+    };
+
+    let (status, stdout) = spectest(&["check", "testdata/integration/**/*.md"]);
+    assert_eq!(status, Some(0));
+    assert!(stdout.contains("ok `testdata/integration/meval/closed_exprs.md` (3 examples)"));
+
+    let (status, stdout) = spectest(&["list", "testdata/integration/meval/variable_exprs.md"]);
+    assert_eq!(status, Some(0));
+    assert!(stdout.contains("variable_exprs.md: Example: Subtraction\n"));
+
+    let (status, _) = spectest(&["check", "testdata/none/**/*.md"]);
+    assert_eq!(status, Some(2));
+}
+
+// The generated test names inherit the casing of the function name, so the
+// lint attribute must be forwarded to each generated test.
+#[allow(non_snake_case)]
+#[spectest::glob_test("testdata/integration/**/*.md")]
+#[doc = "Attributes on both sides of the macro are forwarded."]
+fn forwarded_ATTRS(path: &str) {
+    let mut handler = MevalHandler::new();
+    spectest::run(path, &mut handler);
+}